@@ -0,0 +1,365 @@
+// Gets a set of songs ready ahead of time, e.g. a setlist on a laptop for a gig: extracted into the cache, or converted
+// to FLAC, which takes a little over half the disk. Songs are done a few at a time on rayon's pool and pinned, so the
+// cache never evicts them. The songs still to do are kept in batch.json beside the configuration, so a batch that was
+// interrupted carries on where it stopped.
+use std::{
+    collections::BTreeSet,
+    fmt::Display,
+    fs,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use chrono::Local;
+use color_eyre::eyre::Result;
+use crossbeam_channel::{unbounded, Receiver};
+use log::{info, warn};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use drum_weaver::{
+    cache::set_cache,
+    library::{Library, SongRecord},
+    loader::{ensure_converted, ensure_extracted, file_paths, is_extracted, needs_converting},
+};
+
+use super::AppConfig;
+
+const SAVE_INTERVAL: Duration = Duration::from_secs(2); // how often the songs left are written down
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum BatchFormat {
+    Wav,
+    Flac,
+}
+
+impl BatchFormat {
+    pub fn describe(&self) -> &'static str {
+        match self {
+            BatchFormat::Wav => "Extracting",
+            BatchFormat::Flac => "Converting to FLAC",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchPlan {
+    pub format: BatchFormat,
+    pub songs: Vec<SongRecord>, // still to do
+    pub total: usize,
+}
+
+impl BatchPlan {
+    pub fn new(format: BatchFormat, songs: Vec<SongRecord>) -> Self {
+        BatchPlan { format, total: songs.len(), songs }
+    }
+
+    // A batch that was interrupted, if there is one
+    pub fn load() -> Option<BatchPlan> {
+        let path = BatchPlan::path().ok()?;
+        if !path.exists() {
+            return None;
+        }
+        match fs::read_to_string(&path)
+            .map_err(|err| err.to_string())
+            .and_then(|json| serde_json::from_str(&json).map_err(|err| err.to_string()))
+        {
+            Ok(plan) => Some(plan),
+            Err(err) => {
+                warn!("Unable to read {}: {}", path.display(), err);
+                None
+            }
+        }
+    }
+
+    fn save(&self) -> Result<()> {
+        fs::write(BatchPlan::path()?, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    fn remove() -> Result<()> {
+        let path = BatchPlan::path()?;
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    fn path() -> Result<PathBuf> {
+        let config_path = confy::get_configuration_file_path("drum-weaver", None)?;
+        Ok(config_path.with_file_name("batch.json"))
+    }
+}
+
+// The songs named: the queue, the song list as shown (e.g. a search result), all of them, or a genre
+pub fn select_songs(which: &str, all: &[SongRecord], shown: &[SongRecord], queue: &[SongRecord]) -> Vec<SongRecord> {
+    match which.trim().to_lowercase().as_str() {
+        "queue" => queue.to_vec(),
+        "list" => shown.to_vec(),
+        "all" => all.to_vec(),
+        genre => all.iter().filter(|song| song.genre.to_lowercase() == genre).cloned().collect(),
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BatchProgress {
+    pub done: usize,
+    pub total: usize,
+    pub title: String, // of the song done last
+}
+
+#[derive(Debug, Clone)]
+pub struct BatchReport {
+    pub format: BatchFormat,
+    pub prepared: usize,
+    pub ready: usize,                  // already there
+    pub failed: Vec<(String, String)>, // song and error
+    pub left: usize,                   // when cancelled
+    pub bytes: u64,                    // on disk, for the songs of the batch
+    pub elapsed: Duration,
+}
+
+impl BatchReport {
+    pub fn summary(&self) -> String {
+        let verb = match self.format {
+            BatchFormat::Wav => "Extracted",
+            BatchFormat::Flac => "Converted",
+        };
+        let mut summary = format!(
+            "{} {} songs, {} were ready, {} failed, {} MB in {}s",
+            verb,
+            self.prepared,
+            self.ready,
+            self.failed.len(),
+            self.bytes / (1024 * 1024),
+            self.elapsed.as_secs()
+        );
+        if self.left > 0 {
+            summary.push_str(&format!(". Stopped with {} songs to go.", self.left));
+        }
+        summary
+    }
+
+    // Written to batch_report.txt beside the configuration
+    pub fn save(&self) -> Result<PathBuf> {
+        let config_path = confy::get_configuration_file_path("drum-weaver", None)?;
+        let path = config_path.with_file_name("batch_report.txt");
+        fs::write(&path, format!("{}\n{}", Local::now().format("%Y-%m-%d %H:%M"), self))?;
+        Ok(path)
+    }
+}
+
+impl Display for BatchReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}", self.summary())?;
+        for (song, err) in self.failed.iter() {
+            writeln!(f, "{}: {}", song, err)?;
+        }
+        Ok(())
+    }
+}
+
+enum SongOutcome {
+    Prepared,
+    Ready,
+    Failed(String),
+    Cancelled,
+}
+
+// Works through the plan until it is done or cancelled. Progress is called from rayon's threads as each song finishes.
+pub fn run_batch(plan: BatchPlan, cancelled: &AtomicBool, progress: &(dyn Fn(BatchProgress) + Sync)) -> BatchReport {
+    let started = Instant::now();
+    info!("{} {} of {} songs", plan.format.describe(), plan.songs.len(), plan.total);
+    if let Err(err) = plan.save() {
+        warn!("Unable to save the batch: {:?}", err);
+    }
+
+    let (format, total) = (plan.format, plan.total);
+    let songs = plan.songs.clone();
+    let done = AtomicUsize::new(plan.total - plan.songs.len());
+    let remaining = Mutex::new((plan, Instant::now())); // and when it was saved
+
+    let outcomes = songs
+        .par_iter()
+        .map(|song| {
+            if cancelled.load(Ordering::Relaxed) {
+                return SongOutcome::Cancelled;
+            }
+
+            let is_ready = match format {
+                BatchFormat::Wav => is_extracted(&song.folder, &song.file_name),
+                BatchFormat::Flac => !needs_converting(&song.folder, &song.file_name),
+            };
+            let mut report = |_| !cancelled.load(Ordering::Relaxed);
+            let result = match format {
+                _ if is_ready => Ok(()),
+                BatchFormat::Wav => ensure_extracted(&song.folder, &song.file_name, &mut report),
+                BatchFormat::Flac => ensure_converted(&song.folder, &song.file_name, &mut report),
+            };
+            let outcome = match result {
+                Err(_) if cancelled.load(Ordering::Relaxed) => return SongOutcome::Cancelled,
+                Err(err) => {
+                    warn!("Unable to get {} ready: {:?}", song.file_name, err);
+                    SongOutcome::Failed(err.to_string())
+                }
+                Ok(()) if is_ready => SongOutcome::Ready,
+                Ok(()) => SongOutcome::Prepared,
+            };
+
+            {
+                let mut remaining = remaining.lock().unwrap();
+                remaining.0.songs.retain(|left| left.file_name != song.file_name);
+                if remaining.1.elapsed() >= SAVE_INTERVAL {
+                    if let Err(err) = remaining.0.save() {
+                        warn!("Unable to save the batch: {:?}", err);
+                    }
+                    remaining.1 = Instant::now();
+                }
+            }
+            progress(BatchProgress {
+                done: done.fetch_add(1, Ordering::Relaxed) + 1,
+                total,
+                title: song.title.clone(),
+            });
+            outcome
+        })
+        .collect::<Vec<_>>();
+
+    let (remaining, _) = remaining.into_inner().unwrap();
+    let result = if remaining.songs.is_empty() { BatchPlan::remove() } else { remaining.save() };
+    if let Err(err) = result {
+        warn!("Unable to save the batch: {:?}", err);
+    }
+
+    let mut report = BatchReport {
+        format,
+        prepared: 0,
+        ready: 0,
+        failed: vec![],
+        left: remaining.songs.len(),
+        bytes: 0,
+        elapsed: started.elapsed(),
+    };
+    for (song, outcome) in songs.iter().zip(outcomes) {
+        match outcome {
+            SongOutcome::Prepared => report.prepared += 1,
+            SongOutcome::Ready => report.ready += 1,
+            SongOutcome::Failed(err) => report.failed.push((song.file_name.clone(), err)),
+            SongOutcome::Cancelled => continue,
+        }
+        let (track_path, click_path) = file_paths(&song.folder, &song.file_name);
+        report.bytes += [track_path, click_path].iter().filter_map(|path| fs::metadata(path).ok()).map(|metadata| metadata.len()).sum::<u64>();
+    }
+    info!("{}", report.summary());
+    report
+}
+
+pub enum BatchEvent {
+    Progress(BatchProgress),
+    Finished(BatchReport),
+}
+
+// A batch running on its own thread
+pub struct BatchJob {
+    pub format: BatchFormat,
+    pub progress: Option<BatchProgress>,
+    cancelled: Arc<AtomicBool>,
+    events: Receiver<BatchEvent>,
+}
+
+impl BatchJob {
+    pub fn start(plan: BatchPlan) -> BatchJob {
+        let format = plan.format;
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let (event_sender, events) = unbounded();
+
+        let job_cancelled = cancelled.clone();
+        thread::spawn(move || {
+            let report = run_batch(plan, &job_cancelled, &|progress| {
+                event_sender.send(BatchEvent::Progress(progress)).ok();
+            });
+            event_sender.send(BatchEvent::Finished(report)).ok();
+        });
+
+        BatchJob {
+            format,
+            progress: None,
+            cancelled,
+            events,
+        }
+    }
+
+    // Songs being worked on are stopped and stay in the plan, to be done when the batch is resumed
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    // The report, once the batch is done. Keeps up with its progress until then.
+    pub fn poll(&mut self) -> Option<BatchReport> {
+        while let Ok(event) = self.events.try_recv() {
+            match event {
+                BatchEvent::Progress(progress) => self.progress = Some(progress),
+                BatchEvent::Finished(report) => return Some(report),
+            }
+        }
+        None
+    }
+}
+
+// drum_weaver batch: picks the songs, or the batch that was interrupted, pins them and works through them, printing as it goes
+pub fn run_cli(library_path: &str, flac: bool, which: &str, search: Option<&str>, resume: bool) -> bool {
+    let mut config: AppConfig = confy::load("drum-weaver", None).unwrap_or_default();
+
+    let plan = if resume {
+        match BatchPlan::load() {
+            Some(plan) => plan,
+            None => {
+                println!("There is no batch to resume");
+                return false;
+            }
+        }
+    } else {
+        let mut library = Library::new(library_path.to_string());
+        library.load_csv();
+        // a search picks from what it finds
+        let which = match search {
+            Some(query) => {
+                library.search(query);
+                "list"
+            }
+            None => which,
+        };
+        let songs = select_songs(which, library.all_songs(), library.get_songs(), &config.queue);
+        if songs.is_empty() {
+            println!("No songs in '{}'. Try queue, list, all or a genre.", which);
+            return false;
+        }
+        BatchPlan::new(if flac { BatchFormat::Flac } else { BatchFormat::Wav }, songs)
+    };
+
+    // pinned, so the cache keeps them whatever its limit
+    let mut pinned = config.pinned_songs.iter().cloned().collect::<BTreeSet<_>>();
+    pinned.extend(plan.songs.iter().map(|song| song.file_name.clone()));
+    config.pinned_songs = pinned.into_iter().collect();
+    if let Err(err) = confy::store("drum-weaver", None, &config) {
+        println!("Unable to pin the songs: {}", err);
+    }
+    set_cache(config.cache_settings());
+
+    println!("{} {} of {} songs", plan.format.describe(), plan.songs.len(), plan.total);
+    let report = run_batch(plan, &AtomicBool::new(false), &|progress| println!("{}/{} {}", progress.done, progress.total, progress.title));
+    print!("{}", report);
+    if let Ok(path) = report.save() {
+        println!("Saved to {}", path.display());
+    }
+    report.failed.is_empty()
+}
@@ -0,0 +1,447 @@
+// Checks the library for songs that can't be played: missing or broken archives, extracted files that are cut short
+// or don't decode, clicks that don't match their track, and files that aren't in the catalog.
+// Runs from the song list (F5) or the command line, and can extract broken songs again or ignore the ones that fail.
+use std::{
+    collections::HashSet,
+    fmt::Display,
+    fs::{self, File},
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+use chrono::Local;
+use color_eyre::eyre::{ensure, eyre, Result};
+use log::{info, warn};
+use rayon::prelude::*;
+use sevenz_rust::{Password, SevenZReader};
+
+use drum_weaver::{
+    audio::{AudioInfo, Hint},
+    cache::{self, set_cache},
+    library::{IgnoreList, Library, SongRecord},
+    loader::{archive_path, ensure_extracted, file_paths, is_extracted, is_flac, ArchiveMediaSource},
+};
+
+use super::AppConfig;
+
+// The files a song may have beside its archive, and in the cache
+const SONG_FILES: [&str; 9] = [".7z", ".wav", "_click.wav", ".flac", "_click.flac", "_data.json", "_tempo.mid", "_cue.wav", "_take.wav"];
+const HEADER_BYTES: u64 = 64 * 1024; // plenty for a WAV header
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Problem {
+    MissingArchive,
+    BrokenArchive(String),
+    MissingEntries(Vec<String>),
+    BrokenFile(PathBuf, String),
+    SampleRateMismatch(u32, u32), // track, click
+}
+
+impl Problem {
+    pub fn describe(&self) -> String {
+        match self {
+            Problem::MissingArchive => "the 7z file is missing".to_string(),
+            Problem::BrokenArchive(err) => format!("the 7z file is broken: {}", err),
+            Problem::MissingEntries(names) => format!("the 7z file has no {}", names.join(" or ")),
+            Problem::BrokenFile(path, err) => format!("{} is broken: {}", path.display(), err),
+            Problem::SampleRateMismatch(track, click) => format!("the track is {} Hz but the click is {} Hz", track, click),
+        }
+    }
+
+    fn is_archive_problem(&self) -> bool {
+        matches!(self, Problem::MissingArchive | Problem::BrokenArchive(_) | Problem::MissingEntries(_))
+    }
+}
+
+// A song with something wrong with it
+#[derive(Debug, Clone)]
+pub struct SongCheck {
+    pub song: SongRecord,
+    pub problems: Vec<Problem>,
+    pub is_extracted: bool,
+}
+
+impl SongCheck {
+    // Extracting it again would fix it
+    pub fn is_repairable(&self) -> bool {
+        self.problems.iter().any(|problem| matches!(problem, Problem::BrokenFile(..))) && !self.problems.iter().any(Problem::is_archive_problem)
+    }
+
+    // It can't be played at all
+    pub fn is_failing(&self) -> bool {
+        let has_good_files = self.is_extracted && !self.problems.iter().any(|problem| matches!(problem, Problem::BrokenFile(..)));
+        self.problems.iter().any(Problem::is_archive_problem) && !has_good_files
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CheckReport {
+    pub checked: usize,
+    pub songs: Vec<SongCheck>, // only those with problems
+    pub strays: Vec<PathBuf>,
+}
+
+impl CheckReport {
+    pub fn is_clean(&self) -> bool {
+        self.songs.is_empty() && self.strays.is_empty()
+    }
+
+    pub fn summary(&self) -> String {
+        format!("Checked {} songs: {} with problems, {} stray files", self.checked, self.songs.len(), self.strays.len())
+    }
+
+    pub fn to_repair(&self) -> Vec<SongRecord> {
+        self.songs.iter().filter(|check| check.is_repairable()).map(|check| check.song.clone()).collect()
+    }
+
+    pub fn failing(&self) -> Vec<SongRecord> {
+        self.songs.iter().filter(|check| check.is_failing()).map(|check| check.song.clone()).collect()
+    }
+
+    // Written to library_check.txt beside the configuration
+    pub fn save(&self) -> Result<PathBuf> {
+        let config_path = confy::get_configuration_file_path("drum-weaver", None)?;
+        let path = config_path.with_file_name("library_check.txt");
+        fs::write(&path, format!("{}\n{}", Local::now().format("%Y-%m-%d %H:%M"), self))?;
+        Ok(path)
+    }
+}
+
+impl Display for CheckReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}", self.summary())?;
+        for check in self.songs.iter() {
+            writeln!(f, "{} - {}", check.song.artist, check.song.title)?;
+            for problem in check.problems.iter() {
+                writeln!(f, "    {}", problem.describe())?;
+            }
+        }
+        if !self.strays.is_empty() {
+            writeln!(f, "Not in the catalog:")?;
+            for stray in self.strays.iter() {
+                writeln!(f, "    {}", stray.display())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// What a check or repair running in the background comes back with
+pub enum CheckOutcome {
+    Checked(CheckReport),
+    Repaired(usize, usize), // songs repaired, of those tried
+}
+
+// Every song of the catalog, ignored or not. A deep check reads every archive through, which verifies its checksums,
+// and decodes the extracted files. Otherwise only headers and sizes are read.
+pub fn check_library(library_path: &str, deep: bool) -> CheckReport {
+    let catalog = Library::read_catalog(library_path);
+    info!("Checking {} songs in {}", catalog.len(), library_path);
+
+    let songs = catalog.par_iter().filter_map(|song| check_song(song, deep)).collect::<Vec<_>>();
+    let strays = find_strays(&[PathBuf::from(library_path), cache::cache_folder()], &catalog);
+    CheckReport {
+        checked: catalog.len(),
+        songs,
+        strays,
+    }
+}
+
+fn check_song(song: &SongRecord, deep: bool) -> Option<SongCheck> {
+    let mut problems = vec![];
+    let (track_name, click_name) = (format!("{}.wav", song.file_name), format!("{}_click.wav", song.file_name));
+
+    let archive = archive_path(&song.folder, &song.file_name);
+    let mut archive_rates = None;
+    if !archive.exists() {
+        problems.push(Problem::MissingArchive);
+    } else {
+        match SevenZReader::open(&archive, Password::empty()) {
+            Err(err) => problems.push(Problem::BrokenArchive(err.to_string())),
+            Ok(reader) => {
+                let missing = [&track_name, &click_name]
+                    .into_iter()
+                    .filter(|name| !reader.archive().files.iter().any(|entry| entry.name() == name.as_str()))
+                    .cloned()
+                    .collect::<Vec<_>>();
+                if !missing.is_empty() {
+                    problems.push(Problem::MissingEntries(missing));
+                } else if deep {
+                    match verify_archive(&archive, &track_name, &click_name) {
+                        Ok(rates) => archive_rates = Some(rates),
+                        Err(err) => problems.push(Problem::BrokenArchive(err.to_string())),
+                    }
+                }
+            }
+        }
+    }
+
+    let is_extracted = is_extracted(&song.folder, &song.file_name);
+    let rates = if is_extracted {
+        let (track_path, click_path) = file_paths(&song.folder, &song.file_name);
+        let mut rates = vec![];
+        for path in [track_path, click_path] {
+            match check_file(&path, deep) {
+                Ok(info) => rates.push(info.sample_rate),
+                Err(err) => problems.push(Problem::BrokenFile(path, err.to_string())),
+            }
+        }
+        match rates.as_slice() {
+            [track, click] => Some((*track, *click)),
+            _ => None,
+        }
+    } else {
+        archive_rates
+    };
+    if let Some((track, click)) = rates.filter(|(track, click)| track != click) {
+        problems.push(Problem::SampleRateMismatch(track, click));
+    }
+
+    if problems.is_empty() {
+        return None;
+    }
+    Some(SongCheck {
+        song: song.clone(),
+        problems,
+        is_extracted,
+    })
+}
+
+fn wav_hint() -> Hint {
+    let mut hint = Hint::new();
+    hint.with_extension("wav");
+    hint
+}
+
+// A WAV is cut short when it has less audio than its header says. FLACs are only caught out by decoding them.
+fn check_file(path: &Path, deep: bool) -> Result<AudioInfo> {
+    let mut hint = Hint::new();
+    hint.with_extension(if is_flac(path) { "flac" } else { "wav" });
+    let info = AudioInfo::inspect(Box::new(File::open(path)?), &hint, deep)?;
+    if let Some(data_len) = info.data_len().filter(|_| !is_flac(path)) {
+        let len = fs::metadata(path)?.len();
+        ensure!(len >= data_len, "it is cut short, {} of {} bytes", len, data_len);
+    }
+    Ok(info)
+}
+
+// Reads the whole archive, which checks every entry against its checksum, keeping the headers of the track and click.
+// Returns their sample rates.
+fn verify_archive(archive: &Path, track_name: &str, click_name: &str) -> Result<(u32, u32)> {
+    let mut reader = SevenZReader::open(archive, Password::empty())?;
+    let (mut track, mut click) = (None, None);
+    reader.for_each_entries(|entry, data| {
+        let mut header = vec![];
+        Read::take(&mut *data, HEADER_BYTES).read_to_end(&mut header).map_err(sevenz_rust::Error::io)?;
+        io::copy(data, &mut io::sink()).map_err(sevenz_rust::Error::io)?;
+        if entry.name() == track_name {
+            track = Some((header, entry.size()));
+        } else if entry.name() == click_name {
+            click = Some((header, entry.size()));
+        }
+        Ok(true)
+    })?;
+
+    let mut rates = vec![];
+    for (name, entry) in [(track_name, track), (click_name, click)] {
+        let (header, size) = entry.ok_or_else(|| eyre!("it has no {}", name))?;
+        let info = AudioInfo::inspect(Box::new(ArchiveMediaSource::new(header)), &wav_hint(), false)?;
+        if let Some(data_len) = info.data_len() {
+            ensure!(size >= data_len, "{} is cut short, {} of {} bytes", name, size, data_len);
+        }
+        rates.push(info.sample_rate);
+    }
+    Ok((rates[0], rates[1]))
+}
+
+// Files in the folders that no song of the catalog accounts for, e.g. leftovers of songs that were renamed
+fn find_strays(folders: &[PathBuf], catalog: &[SongRecord]) -> Vec<PathBuf> {
+    let expected = catalog
+        .iter()
+        .flat_map(|song| SONG_FILES.iter().map(move |suffix| format!("{}{}", song.file_name, suffix)))
+        .collect::<HashSet<_>>();
+
+    let mut strays = vec![];
+    for folder in folders.iter().filter(|folder| folder.exists()) {
+        walk(folder, &mut |path| {
+            let name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+            if !name.starts_with('.') && !expected.contains(&name) {
+                strays.push(path);
+            }
+        });
+    }
+    strays.sort();
+    strays
+}
+
+fn walk(folder: &Path, found: &mut dyn FnMut(PathBuf)) {
+    let entries = match fs::read_dir(folder) {
+        Ok(entries) => entries,
+        Err(err) => {
+            warn!("Unable to read {}: {}", folder.display(), err);
+            return;
+        }
+    };
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let path = entry.path();
+        if path.is_dir() {
+            walk(&path, found);
+        } else {
+            found(path);
+        }
+    }
+}
+
+// Removes the broken files and extracts the songs again, into the cache. Returns how many were repaired.
+pub fn repair_songs(songs: &[SongRecord]) -> usize {
+    songs
+        .par_iter()
+        .filter(|song| {
+            let (track_path, click_path) = file_paths(&song.folder, &song.file_name);
+            for path in [track_path, click_path].iter().filter(|path| path.exists()) {
+                if let Err(err) = fs::remove_file(path) {
+                    warn!("Unable to remove {}: {}", path.display(), err);
+                    return false;
+                }
+            }
+            match ensure_extracted(&song.folder, &song.file_name, &mut |_| true) {
+                Ok(()) => true,
+                Err(err) => {
+                    warn!("Unable to extract {} again: {:?}", song.file_name, err);
+                    false
+                }
+            }
+        })
+        .count()
+}
+
+// Adds the songs to the ignore list at the path, normally IgnoreList::path(). Returns how many were added.
+pub fn ignore_songs(songs: &[SongRecord], path: &Path) -> Result<usize> {
+    let mut ignored = IgnoreList::load_from(path);
+    let added = songs.iter().filter(|song| ignored.add(&song.file_name)).count();
+    ignored.save_to(path)?;
+    info!("Ignoring {} more songs", added);
+    Ok(added)
+}
+
+// drum_weaver check: prints the report, then repairs or ignores songs when asked. Returns whether the library was clean.
+pub fn run_cli(library_path: &str, deep: bool, repair: bool, ignore: bool) -> bool {
+    let config: AppConfig = confy::load("drum-weaver", None).unwrap_or_default();
+    set_cache(config.cache_settings());
+
+    println!("Checking {}", library_path);
+    let report = check_library(library_path, deep);
+    print!("{}", report);
+
+    let to_repair = report.to_repair();
+    if !to_repair.is_empty() {
+        if repair {
+            println!("Extracted {} of {} broken songs again", repair_songs(&to_repair), to_repair.len());
+        } else {
+            println!("{} songs can be extracted again. Run with --repair to do it.", to_repair.len());
+        }
+    }
+
+    let failing = report.failing();
+    if !failing.is_empty() {
+        if ignore {
+            match IgnoreList::path().and_then(|path| ignore_songs(&failing, &path)) {
+                Ok(added) => println!("Added {} songs to the ignore list", added),
+                Err(err) => println!("Unable to update the ignore list: {}", err),
+            }
+        } else {
+            println!("{} songs can't be played. Run with --ignore to leave them out of the library.", failing.len());
+        }
+    }
+
+    report.is_clean()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process, slice};
+
+    use drum_weaver::cache::CacheSettings;
+    use sevenz_rust::{SevenZArchiveEntry, SevenZWriter};
+
+    use super::*;
+    use crate::app::fixtures::wav;
+
+    // A second of silence, mono
+    fn silence() -> Vec<u8> {
+        wav(1, 16, &[0; 44100])
+    }
+
+    fn write_archive(song: &SongRecord) {
+        fs::create_dir_all(&song.folder).unwrap();
+        let mut writer = SevenZWriter::create(archive_path(&song.folder, &song.file_name)).unwrap();
+        for name in [format!("{}.wav", song.file_name), format!("{}_click.wav", song.file_name)] {
+            let mut entry = SevenZArchiveEntry::default();
+            entry.name = name;
+            entry.has_stream = true;
+            writer.push_archive_entry(entry, Some(silence().as_slice())).unwrap();
+        }
+        writer.finish().unwrap();
+    }
+
+    fn wav_paths(folder: &Path, file_name: &str) -> (PathBuf, PathBuf) {
+        (folder.join(format!("{}.wav", file_name)), folder.join(format!("{}_click.wav", file_name)))
+    }
+
+    fn problems_of<'a>(report: &'a CheckReport, song: &SongRecord) -> Option<&'a SongCheck> {
+        report.songs.iter().find(|check| check.song.file_name == song.file_name)
+    }
+
+    // The cache is shared by the whole process, so it's all one test
+    #[test]
+    fn good_truncated_and_missing_songs() {
+        let root = env::temp_dir().join(format!("drum-weaver-check-{}", process::id()));
+        let library_path = root.join("library").display().to_string();
+        set_cache(CacheSettings {
+            folder: root.join("cache"),
+            quota_mb: 1024,
+            pinned: Default::default(),
+        });
+
+        let catalog = Library::read_catalog(&library_path);
+        let (good, truncated, missing) = (&catalog[0], &catalog[1], &catalog[2]);
+        write_archive(good);
+        write_archive(truncated);
+        // extracted beside its archive, with the track cut short
+        let (track_path, click_path) = wav_paths(Path::new(&truncated.folder), &truncated.file_name);
+        fs::write(&track_path, &silence()[..40000]).unwrap();
+        fs::write(&click_path, silence()).unwrap();
+        let stray = Path::new(&good.folder).join("leftover.wav");
+        fs::write(&stray, silence()).unwrap();
+
+        for deep in [false, true] {
+            let report = check_library(&library_path, deep);
+            assert_eq!(report.checked, catalog.len());
+            assert!(problems_of(&report, good).is_none());
+            assert_eq!(report.strays, vec![stray.clone()]);
+
+            let check = problems_of(&report, truncated).unwrap();
+            assert!(matches!(check.problems.as_slice(), [Problem::BrokenFile(path, _)] if *path == track_path));
+            assert!(check.is_repairable() && !check.is_failing());
+
+            let check = problems_of(&report, missing).unwrap();
+            assert_eq!(check.problems, vec![Problem::MissingArchive]);
+            assert!(check.is_failing() && !check.is_repairable());
+
+            assert_eq!(report.to_repair(), vec![truncated.clone()]);
+            assert!(report.failing().contains(missing) && !report.failing().contains(truncated));
+        }
+
+        assert_eq!(repair_songs(slice::from_ref(truncated)), 1);
+        assert!(!track_path.exists());
+        assert!(check_song(truncated, true).is_none());
+
+        let ignore_list = root.join("ignored.json");
+        assert_eq!(ignore_songs(&[missing.clone(), missing.clone()], &ignore_list).unwrap(), 1);
+        assert_eq!(ignore_songs(&[missing.clone(), good.clone()], &ignore_list).unwrap(), 1);
+        let ignored = IgnoreList::load_from(&ignore_list);
+        assert!(ignored.contains(&missing.file_name) && ignored.contains(&good.file_name) && !ignored.contains(&truncated.file_name));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
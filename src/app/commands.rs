@@ -1,19 +1,44 @@
-use std::io;
+use std::{
+    io,
+    ops::Bound::{Excluded, Unbounded},
+    thread,
+    time::{Duration, Instant},
+};
 
+use chrono::Local;
+use crossbeam_channel::bounded;
 use crossterm::{
     event::DisableMouseCapture,
     execute,
     terminal::{disable_raw_mode, LeaveAlternateScreen},
 };
+use log::{error, info};
 use native_dialog::{MessageDialog, MessageType};
 
+use drum_weaver::{
+    beep::TestSignal,
+    cache::{clear_cache, set_cache},
+    devices::{available_hosts, current_host_name, cycle_preference, read_devices, rescan_devices, set_host, DeviceDetail},
+    library::IgnoreList,
+    loader::LoadError,
+    metronome::Metronome,
+    mixer::{MixCell, MixSource, MAX_GAIN, MAX_PAN},
+    player::{OutputConfig, PlayerCommand, SongStub},
+    song_data::{LoopRegion, PracticeSettings, SongData},
+};
+
 use super::{
-    devices::read_devices,
+    batch::{select_songs, BatchFormat, BatchJob, BatchPlan},
+    check::{check_library, ignore_songs, repair_songs, CheckOutcome},
     events::UiEventTrait,
-    player::{DeviceType, PlayerCommand, SongStub},
-    ActiveFocus, App, AppConfig, MenuItem, PlayerStatus,
+    review::Rating,
+    routine::{Routine, RoutineAction, RoutineRun, RoutineStep},
+    ActiveFocus, App, AppConfig, MenuItem, PlayerStatus, Prompt, PromptKind,
 };
 
+const MIN_LOOP_LENGTH: Duration = Duration::from_secs(1);
+const MAX_COUNT_IN_BARS: usize = 2;
+
 pub trait UiCommandTrait {
     fn do_exit(&mut self);
     fn on_exit(&mut self);
@@ -25,17 +50,77 @@ pub trait UiCommandTrait {
     fn do_autoplay(&mut self);
     fn do_forward(&mut self);
     fn do_backward(&mut self);
-    fn do_speedup(&mut self);
-    fn do_slowdown(&mut self);
+    fn do_change_speed(&mut self, delta: f64);
+    fn do_start_target_bpm(&mut self);
     fn do_reset_speed(&mut self);
     fn do_next_device(&mut self);
     fn do_previous_device(&mut self);
-    fn do_set_device(&mut self, device_type: DeviceType);
-    fn do_increase_volume(&mut self, device_type: DeviceType);
-    fn do_decrease_volume(&mut self, device_type: DeviceType);
-    fn do_reset_volume(&mut self, device_type: DeviceType);
+    fn do_set_device(&mut self);
+    fn do_next_output(&mut self);
+    fn do_cycle_output_channels(&mut self);
+    fn do_start_add_output(&mut self);
+    fn do_delete_output(&mut self);
+    fn send_outputs(&mut self);
+    fn do_rescan_devices(&mut self);
+    fn do_next_test_channel(&mut self);
+    fn do_previous_test_channel(&mut self);
+    fn do_test_channel(&mut self, signal: TestSignal);
+    fn do_cycle_sample_rate(&mut self);
+    fn do_cycle_buffer_size(&mut self);
+    fn do_cycle_host(&mut self);
+    fn selected_device(&self) -> Option<DeviceDetail>;
+    fn do_change_gain(&mut self, source: MixSource, output: &str, delta: i32);
+    fn do_reset_mix_cell(&mut self, source: MixSource, output: &str);
+    fn do_toggle_mute(&mut self, source: MixSource, output: &str);
+    fn do_change_pan(&mut self, source: MixSource, output: &str, delta: i32);
+    fn do_quick_mix(&mut self, source: MixSource, output_idx: usize, delta: Option<i32>);
+    fn set_mix_cell(&mut self, source: MixSource, output: &str, cell: MixCell);
+    fn selected_mix_cell(&self) -> Option<(MixSource, String)>;
+    fn do_next_mix_source(&mut self);
+    fn do_previous_mix_source(&mut self);
+    fn do_next_mix_output(&mut self);
+    fn do_previous_mix_output(&mut self);
+    fn do_start_save_preset(&mut self);
+    fn do_next_preset(&mut self);
+    fn do_delete_preset(&mut self);
+    fn do_complete_prompt(&mut self);
+    fn do_start_add_marker(&mut self);
+    fn do_start_go_to(&mut self);
+    fn do_remove_marker(&mut self);
+    fn do_jump_to_marker(&mut self, idx: usize);
+    fn do_next_marker(&mut self);
+    fn do_previous_marker(&mut self);
+    fn save_song_data(&mut self);
+    fn restore_practice_settings(&mut self);
+    fn do_cycle_loop(&mut self);
+    fn do_cycle_count_in(&mut self);
+    fn do_start_notes(&mut self);
+    fn do_save_practice_settings(&mut self);
+    fn do_export_history(&mut self, extension: &str);
+    fn do_toggle_routine(&mut self);
+    fn do_rate(&mut self, rating: Rating);
+    fn do_review_queue(&mut self);
+    fn run_routine_action(&mut self, action: RoutineAction);
+    fn do_routine_song_ended(&mut self) -> bool;
+    fn do_routine_tick(&mut self);
+    fn do_toggle_metronome(&mut self);
+    fn play_metronome(&mut self);
+    fn do_update_metronome(&mut self, change: fn(&mut Metronome));
+    fn do_change_metronome_bpm(&mut self, change: i32);
+    fn do_toggle_accent(&mut self, beat: usize);
+    fn do_tap_tempo(&mut self);
+    fn do_metronome_tick(&mut self);
     fn do_shuffle_library(&mut self);
     fn do_play_next(&mut self);
+    fn do_prefetch(&mut self);
+    fn do_toggle_pin(&mut self);
+    fn do_clear_cache(&mut self);
+    fn do_check_library(&mut self);
+    fn do_check_tick(&mut self);
+    fn do_offer_to_hide(&mut self, stub: &SongStub, reason: &LoadError);
+    fn do_start_batch(&mut self, format: BatchFormat);
+    fn start_batch(&mut self, plan: BatchPlan);
+    fn do_batch_tick(&mut self);
     fn do_delete_queue(&mut self);
     fn do_insert_queue(&mut self);
     fn do_empty_queue(&mut self);
@@ -50,10 +135,18 @@ pub trait UiCommandTrait {
     fn do_replace_queue(&mut self);
     fn do_restart_song(&mut self);
     fn do_set_repeat(&mut self);
+    fn send_player_setup(&mut self);
 }
 
 impl UiCommandTrait for App {
     fn do_exit(&mut self) {
+        // nothing is left to tell the player to quit
+        if self.is_player_down {
+            self.on_exit();
+            self.is_running = false;
+            return;
+        }
+
         self.is_exiting = true;
         let dialog_result = MessageDialog::new().set_title("Confirm exit").set_text("Are you sure?").set_type(MessageType::Info).show_confirm();
 
@@ -68,17 +161,32 @@ impl UiCommandTrait for App {
     }
 
     fn on_exit(&mut self) {
-        let track_device_name = read_devices()[self.track_device_idx].name.clone();
-        let click_device_name = read_devices()[self.click_device_idx].name.clone();
-
+        // Keep the configured names even if the devices are unplugged right now
         let config = AppConfig {
-            track_device_name: Some(track_device_name),
-            click_device_name: Some(click_device_name),
-            track_volume: Some(self.track_volume),
-            click_volume: Some(self.click_volume),
-            bleed_volume: Some(self.bleed_volume),
+            track_device_name: None, // replaced by outputs
+            click_device_name: None,
+            track_volume: None, // replaced by the mix
+            click_volume: None,
+            bleed_volume: None,
             search_query: Some(self.search_query.clone()),
             queue: self.queue.clone(),
+            outputs: self.outputs.clone(),
+            device_preferences: self.device_preferences.clone(),
+            host_name: self.host_name.clone(),
+            jack_connections: self.jack_connections.clone(),
+            mix: Some(self.global_mix.clone().unwrap_or_else(|| self.mix.clone())),
+            mix_presets: self.mix_presets.clone(),
+            active_preset: self.active_preset.clone(),
+            new_songs_per_day: Some(self.new_songs_per_day),
+            daily_reviews: Some(self.daily_reviews),
+            prefetch_lookahead: Some(self.prefetch.lookahead),
+            prefetch_budget_mb: Some(self.prefetch.budget_mb),
+            prefetch_decode: Some(self.prefetch.decode),
+            status_rate: Some(self.status_rate),
+            cache_folder: Some(self.cache.folder.display().to_string()),
+            cache_quota_mb: Some(self.cache.quota_mb),
+            pinned_songs: self.cache.pinned.iter().cloned().collect(),
+            metronome: Some(self.metronome.clone()),
         };
         confy::store("drum-weaver", None, config).expect("Unable to save configuration");
 
@@ -87,12 +195,30 @@ impl UiCommandTrait for App {
         std::process::exit(0);
     }
 
+    // Everything the player is told when it starts: at startup, and again when it was restarted after a crash
+    fn send_player_setup(&mut self) {
+        // The host decides which devices exist, so it goes before anything device related
+        self.send_player_command(PlayerCommand::SetHost(self.host_name.clone()));
+        self.send_player_command(PlayerCommand::SetJackConnections(self.jack_connections.clone()));
+
+        // Preferences go first so the players open with them
+        for (device_name, preference) in self.device_preferences.clone() {
+            self.send_player_command(PlayerCommand::SetDevicePreference(device_name, preference));
+        }
+
+        // Devices are remembered by name. If one is missing, the player falls back to the default output and reattaches later.
+        self.send_player_command(PlayerCommand::SetOutputs(self.outputs.clone()));
+        self.send_player_command(PlayerCommand::SetPrefetch(self.prefetch));
+        self.send_player_command(PlayerCommand::SetStatusRate(self.status_rate));
+        self.send_player_command(PlayerCommand::SetMix(self.mix.clone()));
+        self.send_player_command(PlayerCommand::SetCountIn(self.count_in_bars));
+    }
+
     fn do_pause(&mut self) {
         self.send_player_command(PlayerCommand::Pause);
     }
 
     fn do_playback(&mut self) {
-        self.player_status = PlayerStatus::Waiting;
         match self.active_focus {
             ActiveFocus::Queue => {
                 let idx = self.queue_state.selected().unwrap_or(0);
@@ -175,22 +301,28 @@ impl UiCommandTrait for App {
         self.send_player_command(PlayerCommand::Backward);
     }
 
-    fn do_speedup(&mut self) {
-        self.send_player_command(PlayerCommand::SpeedUp);
+    fn do_change_speed(&mut self, delta: f64) {
+        self.send_player_command(PlayerCommand::ChangeSpeed(delta));
     }
 
-    fn do_slowdown(&mut self) {
-        self.send_player_command(PlayerCommand::SlowDown);
+    fn do_start_target_bpm(&mut self) {
+        if self.active_stub.is_some() {
+            self.prompt = Some(Prompt {
+                kind: PromptKind::TargetBpm,
+                input: String::new(),
+            });
+        }
     }
 
     fn do_next_device(&mut self) {
         let mut idx = self.device_state.selected().unwrap_or(0);
         let devices = read_devices();
         idx += 1;
-        if idx > devices.len() - 1 {
-            idx = devices.len() - 1;
+        if idx > devices.len().saturating_sub(1) {
+            idx = devices.len().saturating_sub(1);
         }
         self.device_state.select(Some(idx));
+        self.test_channel = 0;
     }
 
     fn do_previous_device(&mut self) {
@@ -200,80 +332,686 @@ impl UiCommandTrait for App {
         }
         idx -= 1;
         self.device_state.select(Some(idx));
+        self.test_channel = 0;
     }
 
-    fn do_set_device(&mut self, device_type: DeviceType) {
-        let idx = self.device_state.selected().unwrap_or(0);
-        let device_name = match device_type {
-            DeviceType::Track => {
-                self.track_device_idx = idx;
-                read_devices()[self.track_device_idx].clone().name
-            }
-            DeviceType::Click => {
-                self.click_device_idx = idx;
-                read_devices()[self.click_device_idx].clone().name
-            }
-            DeviceType::Bleed => read_devices()[self.click_device_idx].clone().name,
+    // Sends the selected output to the selected device
+    fn do_set_device(&mut self) {
+        let device = match self.selected_device() {
+            Some(device) => device,
+            None => return,
         };
 
-        self.send_player_command(PlayerCommand::SetDevice(device_type, device_name));
+        if let Some(output) = self.outputs.get_mut(self.selected_output) {
+            output.device_name = Some(device.name);
+            output.channels.clear(); // the old channels may not exist on this device
+        }
+
+        self.device_warning = None;
+        self.send_outputs();
+    }
+
+    fn do_next_output(&mut self) {
+        self.selected_output = (self.selected_output + 1) % self.outputs.len().max(1);
+    }
+
+    // Steps the selected output through every channel pair of its device, then back to all channels
+    fn do_cycle_output_channels(&mut self) {
+        let output = match self.outputs.get_mut(self.selected_output) {
+            Some(output) => output,
+            None => return,
+        };
+
+        let device_channels = read_devices()
+            .iter()
+            .find(|device| Some(&device.name) == output.device_name.as_ref())
+            .map(|device| device.max_channels() as usize)
+            .unwrap_or(2);
+
+        let pairs = (1..=device_channels)
+            .step_by(2)
+            .map(|first| (first..=(first + 1).min(device_channels)).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        output.channels = match pairs.iter().position(|pair| *pair == output.channels) {
+            Some(idx) if idx + 1 < pairs.len() => pairs[idx + 1].clone(),
+            Some(_) => Vec::new(),
+            None if output.channels.is_empty() => pairs.first().cloned().unwrap_or_default(),
+            None => Vec::new(),
+        };
+
+        self.send_outputs();
+    }
+
+    fn do_start_add_output(&mut self) {
+        self.prompt = Some(Prompt {
+            kind: PromptKind::AddOutput,
+            input: String::new(),
+        });
+    }
+
+    // Removes the selected output and its sends. There is always at least one output.
+    fn do_delete_output(&mut self) {
+        if self.outputs.len() <= 1 || self.selected_output >= self.outputs.len() {
+            return;
+        }
+
+        let output = self.outputs.remove(self.selected_output);
+        info!("Removing output {}", output.name);
+        self.mix.remove_output(&output.name);
+        self.selected_output = self.selected_output.min(self.outputs.len() - 1);
+        self.mixer_output = self.mixer_output.min(self.outputs.len() - 1);
+
+        self.send_outputs();
+        self.send_player_command(PlayerCommand::SetMix(self.mix.clone()));
+    }
+
+    fn send_outputs(&mut self) {
+        self.send_player_command(PlayerCommand::SetOutputs(self.outputs.clone()));
+    }
+
+    fn do_rescan_devices(&mut self) {
+        let devices = rescan_devices();
+        let idx = self.device_state.selected().unwrap_or(0);
+        self.device_state.select(Some(idx.min(devices.len().saturating_sub(1))));
+        self.send_player_command(PlayerCommand::RescanDevices);
+    }
+
+    fn do_next_test_channel(&mut self) {
+        if let Some(device) = self.selected_device() {
+            let channels = device.max_channels() as usize;
+            self.test_channel = std::cmp::min(self.test_channel + 1, channels.saturating_sub(1));
+        }
+    }
+
+    fn do_previous_test_channel(&mut self) {
+        self.test_channel = self.test_channel.saturating_sub(1);
+    }
+
+    fn do_test_channel(&mut self, signal: TestSignal) {
+        if let Some(device) = self.selected_device() {
+            self.send_player_command(PlayerCommand::TestChannel(device.name, self.test_channel, signal));
+        }
+    }
+
+    fn do_cycle_sample_rate(&mut self) {
+        if let Some(device) = self.selected_device() {
+            let preference = self.device_preferences.entry(device.name.clone()).or_default();
+            preference.sample_rate = cycle_preference(preference.sample_rate, &device.sample_rates());
+            let preference = preference.clone();
+            self.send_player_command(PlayerCommand::SetDevicePreference(device.name, preference));
+        }
+    }
+
+    fn do_cycle_buffer_size(&mut self) {
+        if let Some(device) = self.selected_device() {
+            let preference = self.device_preferences.entry(device.name.clone()).or_default();
+            preference.buffer_size = cycle_preference(preference.buffer_size, &device.buffer_sizes());
+            let preference = preference.clone();
+            self.send_player_command(PlayerCommand::SetDevicePreference(device.name, preference));
+        }
+    }
+
+    fn do_cycle_host(&mut self) {
+        let hosts = available_hosts();
+        if hosts.is_empty() {
+            return;
+        }
+
+        let current = current_host_name();
+        let idx = hosts.iter().position(|h| *h == current).map(|idx| (idx + 1) % hosts.len()).unwrap_or(0);
+        let host_name = hosts[idx].clone();
+
+        set_host(Some(host_name.as_str()));
+        rescan_devices();
+        self.host_name = Some(host_name.clone());
+        self.device_state.select(Some(0));
+        self.test_channel = 0;
+        self.send_player_command(PlayerCommand::SetHost(Some(host_name)));
+    }
+
+    fn selected_device(&self) -> Option<DeviceDetail> {
+        read_devices().get(self.device_state.selected().unwrap_or(0)).cloned()
     }
 
     fn do_reset_speed(&mut self) {
         self.send_player_command(PlayerCommand::ResetSpeed);
     }
 
-    fn do_increase_volume(&mut self, device_type: DeviceType) {
-        let volume = match device_type {
-            DeviceType::Track => {
-                self.track_volume = std::cmp::min(self.track_volume + 1, 200);
-                self.track_volume
+    fn do_change_gain(&mut self, source: MixSource, output: &str, delta: i32) {
+        let mut cell = self.mix.cell(source, output);
+        cell.gain = (cell.gain as i32 + delta).clamp(0, MAX_GAIN as i32) as usize;
+        cell.muted = false;
+        self.set_mix_cell(source, output, cell);
+    }
+
+    fn do_reset_mix_cell(&mut self, source: MixSource, output: &str) {
+        self.set_mix_cell(source, output, MixCell::default());
+    }
+
+    fn do_toggle_mute(&mut self, source: MixSource, output: &str) {
+        let mut cell = self.mix.cell(source, output);
+        cell.muted = !cell.muted;
+        self.set_mix_cell(source, output, cell);
+    }
+
+    fn do_change_pan(&mut self, source: MixSource, output: &str, delta: i32) {
+        let mut cell = self.mix.cell(source, output);
+        cell.pan = (cell.pan + delta).clamp(-MAX_PAN, MAX_PAN);
+        self.set_mix_cell(source, output, cell);
+    }
+
+    // The Alt + number key shortcuts in the song list. A delta of None resets the cell.
+    fn do_quick_mix(&mut self, source: MixSource, output_idx: usize, delta: Option<i32>) {
+        let output = match self.outputs.get(output_idx) {
+            Some(output) => output.name.clone(),
+            None => return,
+        };
+
+        match delta {
+            Some(delta) => self.do_change_gain(source, &output, delta),
+            None => self.do_reset_mix_cell(source, &output),
+        }
+    }
+
+    // Any edit turns the mix into a custom one until it is saved as a preset
+    fn set_mix_cell(&mut self, source: MixSource, output: &str, cell: MixCell) {
+        self.mix.set_cell(source, output, cell);
+        self.active_preset = None;
+        self.send_player_command(PlayerCommand::SetMixCell(source, output.to_string(), cell));
+    }
+
+    fn selected_mix_cell(&self) -> Option<(MixSource, String)> {
+        let output = self.outputs.get(self.mixer_output)?;
+        Some((MixSource::ALL[self.mixer_source], output.name.clone()))
+    }
+
+    fn do_next_mix_source(&mut self) {
+        self.mixer_source = (self.mixer_source + 1) % MixSource::ALL.len();
+    }
+
+    fn do_previous_mix_source(&mut self) {
+        self.mixer_source = (self.mixer_source + MixSource::ALL.len() - 1) % MixSource::ALL.len();
+    }
+
+    fn do_next_mix_output(&mut self) {
+        self.mixer_output = (self.mixer_output + 1) % self.outputs.len().max(1);
+    }
+
+    fn do_previous_mix_output(&mut self) {
+        let count = self.outputs.len().max(1);
+        self.mixer_output = (self.mixer_output + count - 1) % count;
+    }
+
+    fn do_start_save_preset(&mut self) {
+        self.prompt = Some(Prompt {
+            kind: PromptKind::SavePreset,
+            input: self.active_preset.clone().unwrap_or_default(),
+        });
+    }
+
+    // Loads the preset after the active one, in name order
+    fn do_next_preset(&mut self) {
+        let next = match self.active_preset.as_ref() {
+            Some(active) => self.mix_presets.range::<String, _>((Excluded(active), Unbounded)).next(),
+            None => None,
+        }
+        .or_else(|| self.mix_presets.iter().next());
+
+        if let Some((name, matrix)) = next {
+            info!("Loading mix preset {}", name);
+            self.mix = matrix.clone();
+            self.active_preset = Some(name.clone());
+            self.send_player_command(PlayerCommand::SetMix(self.mix.clone()));
+        }
+    }
+
+    // Forgets the active preset. The mix itself stays as it is.
+    fn do_delete_preset(&mut self) {
+        if let Some(name) = self.active_preset.take() {
+            info!("Deleting mix preset {}", name);
+            self.mix_presets.remove(&name);
+        }
+    }
+
+    fn do_complete_prompt(&mut self) {
+        let prompt = match self.prompt.take() {
+            Some(prompt) => prompt,
+            None => return,
+        };
+
+        let input = prompt.input.trim().to_string();
+        if input.is_empty() && prompt.kind != PromptKind::Notes {
+            return;
+        }
+
+        match prompt.kind {
+            PromptKind::SavePreset => {
+                info!("Saving mix preset {}", input);
+                self.mix_presets.insert(input.clone(), self.mix.clone());
+                self.active_preset = Some(input);
             }
-            DeviceType::Click => {
-                self.click_volume = std::cmp::min(self.click_volume + 1, 200);
-                self.click_volume
+            PromptKind::AddOutput => {
+                if self.outputs.iter().any(|output| output.name == input) {
+                    return;
+                }
+
+                // new outputs start on the selected device with nothing sent to them
+                info!("Adding output {}", input);
+                let device_name = self.selected_device().map(|device| device.name);
+                self.outputs.push(OutputConfig::new(&input, device_name));
+                self.selected_output = self.outputs.len() - 1;
+                self.send_outputs();
             }
-            DeviceType::Bleed => {
-                self.bleed_volume = std::cmp::min(self.bleed_volume + 1, 200);
-                self.bleed_volume
+            PromptKind::GoTo => match parse_go_to(&input) {
+                Some(GoTo::Time(position)) => self.send_player_command(PlayerCommand::Seek(position)),
+                Some(GoTo::Bar(bar)) => self.send_player_command(PlayerCommand::SeekBar(bar)),
+                Some(GoTo::Percent(percent)) => {
+                    if let Some(duration) = self.playback_status.as_ref().and_then(|status| status.track_duration) {
+                        self.send_player_command(PlayerCommand::Seek(duration.mul_f64(percent / 100.0)));
+                    }
+                }
+                None => self.notice = Some(format!("Can't go to '{}'. Try 2:35, bar 48 or 75%.", input)),
+            },
+            PromptKind::TargetBpm => {
+                let song_bpm = self.active_stub.as_ref().map(|stub| stub.bpm).unwrap_or(0);
+                let target = input
+                    .to_lowercase()
+                    .trim_start_matches("play at")
+                    .trim_end_matches("bpm")
+                    .trim()
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|bpm| bpm.is_finite() && *bpm > 0.0);
+                match target {
+                    _ if song_bpm == 0 => self.notice = Some("The BPM of this song is unknown".to_string()),
+                    Some(target) => self.send_player_command(PlayerCommand::SetSpeed(target / song_bpm as f64)),
+                    None => self.notice = Some(format!("'{}' is not a BPM", input)),
+                }
+            }
+            PromptKind::AddMarker(position) => {
+                info!("Adding marker {} at {:?}", input, position);
+                self.song_data.add_marker(&input, position);
+                self.save_song_data();
             }
+            PromptKind::Notes => {
+                // an empty note clears it
+                self.song_data.notes = input;
+                self.save_song_data();
+            }
+            PromptKind::Batch(format) => {
+                let library = self.library.as_ref().unwrap();
+                let songs = select_songs(&input, library.all_songs(), library.get_songs(), &self.queue);
+                if songs.is_empty() {
+                    self.notice = Some(format!("No songs in '{}'. Try queue, list, all or a genre.", input));
+                } else {
+                    self.start_batch(BatchPlan::new(format, songs));
+                }
+            }
+            PromptKind::StartRoutine => match Routine::find(&input) {
+                Ok(routine) => {
+                    let (run, action) = RoutineRun::start(routine, self.library.as_ref().unwrap().all_songs());
+                    self.routine = Some(run);
+                    self.run_routine_action(action);
+                }
+                Err(err) => self.notice = Some(err.to_string()),
+            },
+        }
+    }
+
+    fn do_start_add_marker(&mut self) {
+        if let Some(position) = self.playback_status.as_ref().and_then(|status| status.track_position) {
+            self.prompt = Some(Prompt {
+                kind: PromptKind::AddMarker(position),
+                input: String::new(),
+            });
+        }
+    }
+
+    fn do_start_go_to(&mut self) {
+        if self.active_stub.is_some() {
+            self.prompt = Some(Prompt {
+                kind: PromptKind::GoTo,
+                input: String::new(),
+            });
+        }
+    }
+
+    fn do_remove_marker(&mut self) {
+        let position = match self.playback_status.as_ref().and_then(|status| status.track_position) {
+            Some(position) => position,
+            None => return,
+        };
+
+        if let Some(marker) = self.song_data.remove_marker_at(position) {
+            info!("Removing marker {}", marker.name);
+            self.save_song_data();
+        }
+    }
+
+    // The number keys jump to the markers in song order
+    fn do_jump_to_marker(&mut self, idx: usize) {
+        if let Some(marker) = self.song_data.markers.get(idx) {
+            let position = marker.position();
+            self.send_player_command(PlayerCommand::Seek(position));
+        }
+    }
+
+    fn do_next_marker(&mut self) {
+        let position = self.playback_status.as_ref().and_then(|status| status.track_position).unwrap_or_default();
+        if let Some(marker) = self.song_data.next_marker(position) {
+            let position = marker.position();
+            self.send_player_command(PlayerCommand::Seek(position));
+        }
+    }
+
+    // Before the first marker this goes back to the start of the song
+    fn do_previous_marker(&mut self) {
+        let position = self.playback_status.as_ref().and_then(|status| status.track_position).unwrap_or_default();
+        let target = self.song_data.previous_marker(position).map(|marker| marker.position()).unwrap_or_default();
+        self.send_player_command(PlayerCommand::Seek(target));
+    }
+
+    fn save_song_data(&mut self) {
+        let stub = match self.active_stub.as_ref() {
+            Some(stub) => stub,
+            None => return,
+        };
+
+        if let Err(err) = self.song_data.save(&stub.file_name) {
+            error!("Unable to save the data of {}: {:?}", stub.file_name, err);
+            self.notice = Some(format!("Could not save the song data: {}", err));
+        }
+    }
+
+    // Mirrors what the player does when a song loads: its saved settings replace the current ones,
+    // and a song without its own mix goes back to the one in use before. Replays keep any changes.
+    fn restore_practice_settings(&mut self) {
+        let file_name = match self.active_stub.as_ref() {
+            Some(stub) => stub.file_name.clone(),
+            None => return,
         };
+        if self.settings_song.as_ref() == Some(&file_name) {
+            return;
+        }
 
-        self.send_player_command(PlayerCommand::SetVolume(device_type, volume));
+        let practice = self.song_data.practice.clone();
+        match practice.mix {
+            Some(matrix) => {
+                if self.global_mix.is_none() {
+                    self.global_mix = Some(self.mix.clone());
+                }
+                self.mix = matrix;
+            }
+            None => {
+                if let Some(matrix) = self.global_mix.take() {
+                    self.mix = matrix;
+                }
+            }
+        }
+        self.loop_region = practice.loop_region;
+        self.loop_start = None;
+        self.count_in_bars = practice.count_in_bars;
+        self.settings_song = Some(file_name);
     }
 
-    fn do_decrease_volume(&mut self, device_type: DeviceType) {
-        let volume = match device_type {
-            DeviceType::Track => {
-                self.track_volume = std::cmp::max(self.track_volume.saturating_sub(1), 0);
-                self.track_volume
+    // First press sets A, the second sets B and starts looping, the third stops
+    fn do_cycle_loop(&mut self) {
+        let position = match self.playback_status.as_ref().and_then(|status| status.track_position) {
+            Some(position) => position,
+            None => return,
+        };
+
+        match (self.loop_region, self.loop_start) {
+            (Some(_), _) => {
+                self.loop_region = None;
+                self.send_player_command(PlayerCommand::SetLoop(None));
             }
-            DeviceType::Click => {
-                self.click_volume = std::cmp::max(self.click_volume.saturating_sub(1), 0);
-                self.click_volume
+            (None, None) => self.loop_start = Some(position),
+            (None, Some(start)) if start.abs_diff(position) < MIN_LOOP_LENGTH => {
+                self.notice = Some("A loop needs to be at least a second long".to_string());
             }
-            DeviceType::Bleed => {
-                self.bleed_volume = std::cmp::max(self.bleed_volume.saturating_sub(1), 0);
-                self.bleed_volume
+            (None, Some(start)) => {
+                let region = LoopRegion::new(start, position);
+                info!("Looping {:?} to {:?}", region.start(), region.end());
+                self.loop_region = Some(region);
+                self.loop_start = None;
+                self.send_player_command(PlayerCommand::SetLoop(Some(region)));
             }
+        }
+    }
+
+    fn do_cycle_count_in(&mut self) {
+        self.count_in_bars = (self.count_in_bars + 1) % (MAX_COUNT_IN_BARS + 1);
+        self.send_player_command(PlayerCommand::SetCountIn(self.count_in_bars));
+    }
+
+    fn do_start_notes(&mut self) {
+        if self.active_stub.is_some() {
+            self.prompt = Some(Prompt {
+                kind: PromptKind::Notes,
+                input: self.song_data.notes.clone(),
+            });
+        }
+    }
+
+    // Remembers the current speed, mix, loop and count-in as the song's own
+    fn do_save_practice_settings(&mut self) {
+        if self.active_stub.is_none() {
+            return;
+        }
+
+        self.song_data.practice = PracticeSettings {
+            speed: self.playback_status.as_ref().map(|status| status.speed),
+            mix: Some(self.mix.clone()),
+            loop_region: self.loop_region,
+            count_in_bars: self.count_in_bars,
         };
+        if self.global_mix.is_none() {
+            self.global_mix = Some(self.mix.clone());
+        }
+        self.notice = Some("Saved the practice settings for this song".to_string());
+        self.save_song_data(); // replaces the notice if it fails
+    }
 
-        self.send_player_command(PlayerCommand::SetVolume(device_type, volume));
+    fn do_export_history(&mut self, extension: &str) {
+        match self.history.export(extension) {
+            Ok(path) => {
+                info!("Exported the history to {}", path.display());
+                self.notice = Some(format!("Exported the history to {}", path.display()));
+            }
+            Err(err) => {
+                error!("Unable to export the history: {:?}", err);
+                self.notice = Some(format!("Could not export the history: {}", err));
+            }
+        }
     }
 
-    fn do_reset_volume(&mut self, device_type: DeviceType) {
-        match device_type {
-            DeviceType::Track => {
-                self.track_volume = 100;
+    // Rates the last song played. Until then it counts as okay.
+    fn do_rate(&mut self, rating: Rating) {
+        let (file_name, title) = match self.pending_rating.take() {
+            Some(pending) => pending,
+            None => return,
+        };
+
+        info!("Rating {} as {}", title, rating.label());
+        self.reviews.review(&file_name, rating, Local::now().date_naive());
+        if let Err(err) = self.reviews.save() {
+            error!("Unable to save the reviews: {:?}", err);
+        }
+        self.notice = Some(format!("{}: {}", title, rating.label()));
+    }
+
+    // Replaces the queue with today's reviews, then new songs from those shown in the library
+    fn do_review_queue(&mut self) {
+        let library = self.library.as_ref().unwrap();
+        let daily = self
+            .reviews
+            .daily_queue(Local::now().date_naive(), library.all_songs(), library.get_songs(), self.daily_reviews, self.new_songs_per_day);
+        if daily.songs.is_empty() {
+            self.notice = Some("Nothing to review today".to_string());
+            return;
+        }
+
+        info!("Review queue: {} due, {} new", daily.due, daily.new);
+        self.queue = daily.songs;
+        self.queue_state.select(Some(0));
+        self.do_prefetch();
+        self.active_focus = ActiveFocus::Queue;
+        self.notice = Some(format!("Today's reviews: {} due, {} new, {} mastered", daily.due, daily.new, self.reviews.mastered()));
+    }
+
+    // Starts a routine, or stops the one that is running. The song playing carries on, a click stops.
+    fn do_toggle_routine(&mut self) {
+        if let Some(run) = self.routine.take() {
+            info!("Stopping the routine {} at step {}", run.routine.name, run.step + 1);
+            if let Err(err) = self.history.append_routine(&run.record(false)) {
+                error!("Unable to save the routine history: {:?}", err);
             }
-            DeviceType::Click => {
-                self.click_volume = 100;
+            if matches!(run.current_step(), Some(RoutineStep::Click { .. })) {
+                self.send_player_command(PlayerCommand::Stop);
+                self.player_status = PlayerStatus::Ready;
+            }
+            self.notice = Some(format!("Stopped the routine {}", run.routine.name));
+            return;
+        }
+
+        match Routine::load_all() {
+            Ok(routines) if routines.is_empty() => self.notice = Some("No routines yet. Add one to the routines folder next to the configuration.".to_string()),
+            Ok(routines) => {
+                self.notice = Some(format!("Routines: {}", routines.iter().map(|routine| routine.name.clone()).collect::<Vec<_>>().join(", ")));
+                self.prompt = Some(Prompt {
+                    kind: PromptKind::StartRoutine,
+                    input: String::new(),
+                });
+            }
+            Err(err) => {
+                error!("Unable to read the routines: {:?}", err);
+                self.notice = Some(format!("Could not read the routines: {}", err));
+            }
+        }
+    }
+
+    fn run_routine_action(&mut self, action: RoutineAction) {
+        match action {
+            RoutineAction::PlayClick(bpm, beats_per_bar) => {
+                self.metronome_started = None;
+                self.send_player_command(PlayerCommand::PlayMetronome(Metronome::new(bpm, beats_per_bar)));
+                self.player_status = PlayerStatus::Playing(format!("Click at {} BPM", bpm));
+                self.active_stub = None;
+                self.song_data = SongData::default();
             }
-            DeviceType::Bleed => {
-                self.bleed_volume = 100;
+            RoutineAction::Play(song, speed) => {
+                // set once it plays, since playing brings back the song's own speed
+                self.send_player_command(PlayerCommand::Play(SongStub::from_song_record(&song)));
+                self.pending_speed = Some((song.file_name.clone(), speed));
+            }
+            RoutineAction::Finished => {
+                if let Some(run) = self.routine.take() {
+                    info!("Finished the routine {}", run.routine.name);
+                    if let Err(err) = self.history.append_routine(&run.record(true)) {
+                        error!("Unable to save the routine history: {:?}", err);
+                    }
+                    self.notice = Some(format!("Finished the routine {}. Well played!", run.routine.name));
+                }
+                // the click of a last click step plays until stopped
+                if self.active_stub.is_none() {
+                    self.send_player_command(PlayerCommand::Stop);
+                    self.player_status = PlayerStatus::Ready;
+                }
+            }
+        }
+    }
+
+    // Returns whether the routine took care of what plays next
+    fn do_routine_song_ended(&mut self) -> bool {
+        let library = match self.library.as_ref() {
+            Some(library) => library.all_songs().clone(),
+            None => return false,
+        };
+        match self.routine.as_mut().and_then(|run| run.song_ended(&library)) {
+            Some(action) => {
+                self.run_routine_action(action);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn do_routine_tick(&mut self) {
+        let action = match (self.routine.as_mut(), self.library.as_ref()) {
+            (Some(run), Some(library)) => run.tick(library.all_songs()),
+            _ => None,
+        };
+        if let Some(action) = action {
+            self.run_routine_action(action);
+        }
+    }
+
+    // Starts the metronome in place of whatever is playing, or stops it when it is already going
+    fn do_toggle_metronome(&mut self) {
+        if self.metronome_started.take().is_some() {
+            self.send_player_command(PlayerCommand::Stop);
+            self.player_status = PlayerStatus::Ready;
+            return;
+        }
+
+        if self.routine.is_some() {
+            self.do_toggle_routine();
+        }
+        self.metronome_started = Some(Instant::now());
+        self.play_metronome();
+    }
+
+    fn play_metronome(&mut self) {
+        info!("Metronome at {}", self.metronome.describe());
+        self.send_player_command(PlayerCommand::PlayMetronome(self.metronome.clone()));
+        self.player_status = PlayerStatus::Playing(format!("Metronome at {}", self.metronome.describe()));
+        self.active_stub = None;
+        self.song_data = SongData::default();
+    }
+
+    // Changes are heard straight away when the metronome is going
+    fn do_update_metronome(&mut self, change: fn(&mut Metronome)) {
+        change(&mut self.metronome);
+        if self.metronome_started.is_some() {
+            self.play_metronome();
+        }
+    }
+
+    fn do_change_metronome_bpm(&mut self, change: i32) {
+        self.metronome.change_bpm(change);
+        if self.metronome_started.is_some() {
+            self.play_metronome();
+        }
+    }
+
+    fn do_toggle_accent(&mut self, beat: usize) {
+        self.metronome.toggle_accent(beat);
+        if self.metronome_started.is_some() {
+            self.play_metronome();
+        }
+    }
+
+    // Every tap restarts a running metronome, so its downbeat lands on the last tap
+    fn do_tap_tempo(&mut self) {
+        if let Some(bpm) = self.tap_tempo.tap() {
+            self.metronome.set_bpm(bpm);
+            if self.metronome_started.is_some() {
+                self.play_metronome();
+            }
+        }
+    }
+
+    // Stops the metronome once its timer has run out
+    fn do_metronome_tick(&mut self) {
+        if let (Some(started), Some(timer)) = (self.metronome_started, self.metronome.timer()) {
+            if started.elapsed() >= timer {
+                self.metronome_started = None;
+                self.send_player_command(PlayerCommand::Stop);
+                self.player_status = PlayerStatus::Ready;
+                self.notice = Some(format!("Time's up: {} min at {}", timer.as_secs() / 60, self.metronome.describe()));
             }
         }
-        self.send_player_command(PlayerCommand::ResetVolume(device_type));
     }
 
     fn do_shuffle_library(&mut self) {
@@ -288,12 +1026,12 @@ impl UiCommandTrait for App {
                 use rand::seq::SliceRandom;
                 let mut rng = rand::thread_rng();
                 self.queue.shuffle(&mut rng);
+                self.do_prefetch();
             }
         }
     }
 
     fn do_play_next(&mut self) {
-        self.player_status = PlayerStatus::Waiting;
         if !self.queue.is_empty() {
             let mut idx = self.queue_state.selected().unwrap_or(0);
             if self.active_stub.is_some() {
@@ -333,6 +1071,176 @@ impl UiCommandTrait for App {
         self.active_stub = None; // dangerous if the play commands execute first. But they shouldn't.
     }
 
+    // The queue songs after the one playing, wrapping around as the queue does, for the player to get ready
+    fn do_prefetch(&mut self) {
+        let current = self.active_stub.as_ref().and_then(|stub| self.queue.iter().position(|song| song.file_name == stub.file_name));
+        let start = match current {
+            Some(idx) => idx + 1,
+            None if self.active_stub.is_some() => self.queue_state.selected().map(|idx| idx + 1).unwrap_or(0),
+            None => self.queue_state.selected().unwrap_or(0),
+        };
+
+        let upcoming = (0..self.queue.len())
+            .map(|offset| &self.queue[(start + offset) % self.queue.len()])
+            .filter(|song| current.is_none_or(|idx| song.file_name != self.queue[idx].file_name))
+            .take(self.prefetch.lookahead)
+            .map(SongStub::from_song_record)
+            .collect();
+        self.send_player_command(PlayerCommand::Prefetch(upcoming));
+    }
+
+    // Pins the song under the cursor so it is never evicted from the cache, or unpins it
+    fn do_toggle_pin(&mut self) {
+        let selected = match self.active_focus {
+            ActiveFocus::Library => self.library_state.selected().and_then(|idx| self.library.as_ref().unwrap().get_songs().get(idx)).cloned(),
+            ActiveFocus::Queue => self.queue_state.selected().and_then(|idx| self.queue.get(idx)).cloned(),
+        };
+        let song = match selected {
+            Some(song) => song,
+            None => return,
+        };
+
+        if self.cache.pinned.remove(&song.file_name) {
+            self.notice = Some(format!("Unpinned {}", song.title));
+        } else {
+            self.cache.pinned.insert(song.file_name.clone());
+            self.notice = Some(format!("Pinned {}. It stays in the cache.", song.title));
+        }
+        set_cache(self.cache.clone());
+    }
+
+    fn do_clear_cache(&mut self) {
+        self.notice = Some(match clear_cache() {
+            Ok((songs, bytes)) => format!("Cleared {} songs, {} MB, from the cache", songs, bytes / (1024 * 1024)),
+            Err(err) => {
+                error!("Unable to clear the cache: {:?}", err);
+                format!("Clearing the cache failed: {}", err)
+            }
+        });
+    }
+
+    // Checks the whole catalog in the background. do_check_tick picks up the report.
+    fn do_check_library(&mut self) {
+        if self.library_check.is_some() {
+            return;
+        }
+        let library_path = self.library.as_ref().unwrap().path.clone();
+        let (sender, receiver) = bounded(1);
+        thread::spawn(move || sender.send(CheckOutcome::Checked(check_library(&library_path, false))).ok());
+        self.library_check = Some(receiver);
+        self.notice = Some("Checking the library...".to_string());
+    }
+
+    // Once the check is done, offers to ignore the songs that can't be played and to extract the broken ones again
+    fn do_check_tick(&mut self) {
+        let outcome = match self.library_check.as_ref().and_then(|receiver| receiver.try_recv().ok()) {
+            Some(outcome) => outcome,
+            None => return,
+        };
+        self.library_check = None;
+
+        let report = match outcome {
+            CheckOutcome::Checked(report) => report,
+            CheckOutcome::Repaired(repaired, tried) => {
+                self.notice = Some(format!("Extracted {} of {} broken songs again", repaired, tried));
+                return;
+            }
+        };
+        info!("{}", report.summary());
+        self.notice = Some(match report.save() {
+            Ok(path) => format!("{}. See {}", report.summary(), path.display()),
+            Err(err) => {
+                error!("Unable to save the library check: {:?}", err);
+                report.summary()
+            }
+        });
+
+        let failing = report.failing();
+        if !failing.is_empty() && confirm("Songs that can't be played", &format!("{} songs can't be played. Leave them out of the library?", failing.len())) {
+            match IgnoreList::path().and_then(|path| ignore_songs(&failing, &path)) {
+                Ok(_) => {
+                    for song in failing.iter() {
+                        self.library.as_mut().unwrap().remove_song_by_stub(SongStub::from_song_record(song));
+                        self.queue.retain(|queued| queued.file_name != song.file_name);
+                    }
+                    self.do_prefetch();
+                }
+                Err(err) => error!("Unable to update the ignore list: {:?}", err),
+            }
+        }
+
+        let to_repair = report.to_repair();
+        if !to_repair.is_empty() && confirm("Broken songs", &format!("{} songs were extracted badly. Extract them again?", to_repair.len())) {
+            let (sender, receiver) = bounded(1);
+            let tried = to_repair.len();
+            thread::spawn(move || sender.send(CheckOutcome::Repaired(repair_songs(&to_repair), tried)).ok());
+            self.library_check = Some(receiver);
+            self.notice = Some(format!("Extracting {} songs again...", tried));
+        }
+    }
+
+    // Hides a song that won't load from the library, once the user agrees. It stays hidden until taken out of ignored.json.
+    fn do_offer_to_hide(&mut self, stub: &SongStub, reason: &LoadError) {
+        let text = format!("{} won't load: {}.\n\nHide it from the library?", stub.title, reason);
+        if !confirm("Song won't load", &text) {
+            return;
+        }
+
+        let mut ignored = IgnoreList::load();
+        ignored.add(&stub.file_name);
+        if let Err(err) = ignored.save() {
+            error!("Unable to update the ignore list: {:?}", err);
+        }
+        self.library.as_mut().unwrap().remove_song_by_stub(stub.clone());
+        self.queue.retain(|song| song.file_name != stub.file_name);
+        self.do_prefetch();
+        self.notice = Some(format!("Hid {}. Take it out of ignored.json to have it back.", stub.title));
+    }
+
+    // Stops the batch running, or offers to carry on with one that was interrupted, or asks which songs to do
+    fn do_start_batch(&mut self, format: BatchFormat) {
+        if let Some(job) = &self.batch {
+            job.cancel();
+            self.notice = Some("Stopping the batch...".to_string());
+            return;
+        }
+
+        if let Some(plan) = BatchPlan::load() {
+            let text = format!("{} of {} songs are left from the last batch. Carry on with it?", plan.songs.len(), plan.total);
+            if confirm("Unfinished batch", &text) {
+                self.start_batch(plan);
+                return;
+            }
+        }
+        self.prompt = Some(Prompt {
+            kind: PromptKind::Batch(format),
+            input: String::new(),
+        });
+    }
+
+    // The songs are pinned, so the cache keeps them whatever its limit
+    fn start_batch(&mut self, plan: BatchPlan) {
+        self.cache.pinned.extend(plan.songs.iter().map(|song| song.file_name.clone()));
+        set_cache(self.cache.clone());
+        self.notice = Some(format!("{} {} songs", plan.format.describe(), plan.songs.len()));
+        self.batch = Some(BatchJob::start(plan));
+    }
+
+    fn do_batch_tick(&mut self) {
+        let report = match self.batch.as_mut().and_then(|job| job.poll()) {
+            Some(report) => report,
+            None => return,
+        };
+        self.batch = None;
+        self.notice = Some(match report.save() {
+            Ok(path) => format!("{}. See {}", report.summary(), path.display()),
+            Err(err) => {
+                error!("Unable to save the batch report: {:?}", err);
+                report.summary()
+            }
+        });
+    }
+
     fn do_delete_queue(&mut self) {
         if self.active_menu_item != MenuItem::Library {
             return;
@@ -347,6 +1255,7 @@ impl UiCommandTrait for App {
                 let idx = self.queue_state.selected().unwrap_or(0);
                 self.queue.remove(idx);
                 self.queue_state.select(Some(idx));
+                self.do_prefetch();
             }
             ActiveFocus::Library => {}
         }
@@ -367,12 +1276,14 @@ impl UiCommandTrait for App {
                 }
 
                 self.queue.push(song);
+                self.do_prefetch();
             }
         }
     }
 
     fn do_empty_queue(&mut self) {
         self.queue.clear();
+        self.do_prefetch();
     }
 
     fn do_goto_first(&mut self) {
@@ -471,6 +1382,7 @@ impl UiCommandTrait for App {
             }
         }
         self.do_cancel_search();
+        self.do_prefetch();
     }
 
     fn do_cancel_search(&mut self) {
@@ -492,3 +1404,79 @@ impl UiCommandTrait for App {
         self.is_repeating = !self.is_repeating;
     }
 }
+
+// Where the go to prompt was asked to go
+#[derive(Debug, PartialEq)]
+enum GoTo {
+    Time(Duration),
+    Bar(usize),
+    Percent(f64),
+}
+
+// Understands "2:35", "1:02:03" and plain seconds, "bar 48" (or "b48"), and "75%", kept to 0 to 100
+fn parse_go_to(input: &str) -> Option<GoTo> {
+    let input = input.trim().to_lowercase();
+
+    if let Some(percent) = input.strip_suffix('%') {
+        let percent = percent.trim().parse::<f64>().ok().filter(|percent| percent.is_finite())?;
+        return Some(GoTo::Percent(percent.clamp(0.0, 100.0)));
+    }
+
+    if let Some(bar) = input.strip_prefix("bar").or_else(|| input.strip_prefix('b')) {
+        return bar.trim().parse::<usize>().ok().map(GoTo::Bar);
+    }
+
+    // seconds, then minutes and hours in front of them
+    let mut seconds = 0.0;
+    for part in input.split(':') {
+        let value = part.trim().parse::<f64>().ok().filter(|value| value.is_finite() && *value >= 0.0)?;
+        seconds = seconds * 60.0 + value;
+    }
+    // finite isn't enough, it has to fit a Duration too
+    Duration::try_from_secs_f64(seconds).ok().map(GoTo::Time)
+}
+
+fn confirm(title: &str, text: &str) -> bool {
+    MessageDialog::new().set_title(title).set_text(text).set_type(MessageType::Info).show_confirm().unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn go_to_a_time() {
+        assert_eq!(parse_go_to("2:35"), Some(GoTo::Time(Duration::from_secs(155))));
+        assert_eq!(parse_go_to("1:02:03"), Some(GoTo::Time(Duration::from_secs(3723))));
+        assert_eq!(parse_go_to(" 90 "), Some(GoTo::Time(Duration::from_secs(90))));
+        assert_eq!(parse_go_to("0:07.5"), Some(GoTo::Time(Duration::from_millis(7500))));
+        assert_eq!(parse_go_to("1e300"), None);
+        assert_eq!(parse_go_to("99999999999999999999:0"), None);
+    }
+
+    #[test]
+    fn go_to_a_bar() {
+        assert_eq!(parse_go_to("bar 48"), Some(GoTo::Bar(48)));
+        assert_eq!(parse_go_to("b48"), Some(GoTo::Bar(48)));
+        assert_eq!(parse_go_to("Bar48"), Some(GoTo::Bar(48)));
+        assert_eq!(parse_go_to("bar"), None);
+        assert_eq!(parse_go_to("bar -2"), None);
+    }
+
+    #[test]
+    fn go_to_a_percent() {
+        assert_eq!(parse_go_to("75%"), Some(GoTo::Percent(75.0)));
+        assert_eq!(parse_go_to("12.5 %"), Some(GoTo::Percent(12.5)));
+        assert_eq!(parse_go_to("150%"), Some(GoTo::Percent(100.0)));
+        assert_eq!(parse_go_to("-5%"), Some(GoTo::Percent(0.0)));
+        assert_eq!(parse_go_to("%"), None);
+        assert_eq!(parse_go_to("nan%"), None);
+    }
+
+    #[test]
+    fn nonsense_goes_nowhere() {
+        for input in ["", "-5", "1::2", ":30", "1:", "2:35x", "inf", "chorus"] {
+            assert_eq!(parse_go_to(input), None, "{:?}", input);
+        }
+    }
+}
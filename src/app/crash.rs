@@ -0,0 +1,66 @@
+// What happens when something panics. The UI runs on the main thread: a panic there puts the terminal back the way it
+// was before the report is printed. The player runs supervised on its own thread and is restarted, so a panic there
+// leaves the terminal alone. Either way the panic is logged and written to crash_report.txt beside the configuration.
+use std::{backtrace::Backtrace, fs, io, panic, path::PathBuf, thread};
+
+use chrono::Local;
+use color_eyre::eyre::Result;
+use crossterm::{
+    cursor::Show,
+    event::DisableMouseCapture,
+    execute,
+    terminal::{disable_raw_mode, LeaveAlternateScreen},
+};
+use drum_weaver::player::panic_message;
+use log::error;
+
+pub fn install_panic_hook() {
+    if let Err(err) = color_eyre::install() {
+        error!("Unable to install color-eyre: {:?}", err);
+    }
+
+    let report_hook = panic::take_hook(); // color-eyre's
+    panic::set_hook(Box::new(move |info| {
+        let thread_name = thread::current().name().unwrap_or("unnamed").to_string();
+        let message = panic_message(info.payload());
+        let location = info.location().map(|location| location.to_string()).unwrap_or_default();
+        error!("Thread {} panicked at {}: {}", thread_name, location, message);
+        if let Err(err) = write_report(&thread_name, &location, &message) {
+            error!("Unable to write the crash report: {:?}", err);
+        }
+
+        if thread_name == "main" {
+            restore_terminal();
+            report_hook(info);
+            if let Ok(path) = report_path() {
+                eprintln!("The crash report was saved to {}", path.display());
+            }
+        }
+    }));
+}
+
+// Raw mode off, out of the alternate screen, the mouse released and the cursor shown
+pub fn restore_terminal() {
+    disable_raw_mode().ok();
+    execute!(io::stdout(), LeaveAlternateScreen, DisableMouseCapture, Show).ok();
+}
+
+pub fn report_path() -> Result<PathBuf> {
+    let config_path = confy::get_configuration_file_path("drum-weaver", None)?;
+    Ok(config_path.with_file_name("crash_report.txt"))
+}
+
+// Replaces the last report, which is the one worth sending
+fn write_report(thread_name: &str, location: &str, message: &str) -> Result<()> {
+    let report = format!(
+        "Drum Weaver {} crashed at {}\nThread: {}\nLocation: {}\nMessage: {}\n\n{}\n",
+        env!("CARGO_PKG_VERSION"),
+        Local::now().format("%Y-%m-%d %H:%M:%S"),
+        thread_name,
+        location,
+        message,
+        Backtrace::force_capture()
+    );
+    fs::write(report_path()?, report)?;
+    Ok(())
+}
@@ -1,153 +1,303 @@
 use crossterm::event::{KeyCode, KeyModifiers};
 use log::{error, info};
 
-use crate::app::{player::PlayerCommand, PlayerStatus};
+use drum_weaver::{
+    beep::TestSignal,
+    metronome::Metronome,
+    mixer::MixSource,
+    player::{PlayerCommand, PlayerEvent},
+    song_data::SongData,
+};
 
 use super::{
+    batch::BatchFormat,
     commands::UiCommandTrait,
-    player::{DeviceType, PlayerEvent},
-    App, InputEvent, MenuItem, UiEvent,
+    crash::report_path,
+    review::{Rating, MIN_REVIEW_PLAY},
+    App, InputEvent, MenuItem, PlayerStatus, UiEvent,
 };
 
 pub trait UiEventTrait {
-    fn handle_ui_events(&mut self);
-    fn handle_player_events(&mut self);
+    fn handle_ui_event(&mut self, event: UiEvent<InputEvent>);
+    fn handle_player_event(&mut self, event: PlayerEvent);
     fn send_player_command(&mut self, command: PlayerCommand);
 }
 
 impl UiEventTrait for App {
-    fn handle_ui_events(&mut self) {
+    fn handle_ui_event(&mut self, event: UiEvent<InputEvent>) {
         // Handle UI events
         // This comes first since we want any interaction to
         // change state as a priority
         if !self.is_exiting {
-            if let Ok(event) = self.ui_command_receiver.try_recv() {
-                if matches!(self.player_status, PlayerStatus::Waiting | PlayerStatus::Decompressing | PlayerStatus::Decompressed) {
-                    return;
-                }
-                match event {
-                    UiEvent::Input(input_event) => {
+            match event {
+                UiEvent::Input(input_event) => {
 
-                        info!("Input event");
+                    info!("Input event");
 
-                        match input_event {
-                            // Commands for the help view
-                            InputEvent::Key(event) if event.modifiers.contains(KeyModifiers::ALT) && self.active_menu_item == MenuItem::Library && self.is_searching => {
-                                if event.code == KeyCode::Char('r') {
-                                    self.do_replace_queue()
+                    match input_event {
+                        // Typing into a prompt
+                        InputEvent::Key(event) if self.prompt.is_some() => match event.code {
+                            KeyCode::Char(char) => {
+                                if let Some(prompt) = self.prompt.as_mut() {
+                                    prompt.input.push(char);
                                 }
                             }
-
-                            InputEvent::Key(event) if event.modifiers.contains(KeyModifiers::SHIFT) && !self.is_searching => match event.code {
-                                KeyCode::Char('s') => self.active_menu_item = MenuItem::Library,
-                                KeyCode::Char('d') => self.active_menu_item = MenuItem::Devices,
-                                KeyCode::Char('h') => self.active_menu_item = MenuItem::Help,
-                                KeyCode::Char('q') => self.do_exit(),
-                                KeyCode::Left => self.do_slowdown(),
-                                KeyCode::Right => self.do_speedup(),
-                                _ => {}
-                            },
-
-                            // Commands for searching
-                            InputEvent::Key(event) if self.active_menu_item == MenuItem::Library && self.is_searching => match event.code {
-                                KeyCode::Char(char) => {
-                                    self.search_query.push(char);
-                                    self.do_search();
-                                }
-                                KeyCode::Backspace => {
-                                    self.search_query.pop();
-                                    self.do_search();
-                                }
-                                KeyCode::Esc => {
-                                    self.is_searching = false;
-                                    self.search_query.clear();
-                                    self.do_cancel_search();
+                            KeyCode::Backspace => {
+                                if let Some(prompt) = self.prompt.as_mut() {
+                                    prompt.input.pop();
                                 }
-                                KeyCode::Enter => {
-                                    self.is_searching = false;
-                                    self.do_complete_search();
-                                }
-                                KeyCode::Tab => {
-                                    self.is_searching = false;
-                                    self.do_empty_queue();
-                                    self.do_complete_search();
+                            }
+                            KeyCode::Esc => self.prompt = None,
+                            KeyCode::Enter => self.do_complete_prompt(),
+                            _ => {}
+                        },
+
+                        // Dismissing the error banner
+                        InputEvent::Key(event) if self.error_banner.is_some() && event.code == KeyCode::Esc => self.error_banner = None,
+
+                        // Commands for the help view
+                        InputEvent::Key(event) if event.modifiers.contains(KeyModifiers::ALT) && self.active_menu_item == MenuItem::Library && self.is_searching => {
+                            if event.code == KeyCode::Char('r') {
+                                self.do_replace_queue()
+                            }
+                        }
+
+                        // The quick mix shortcuts and fine speed steps
+                        InputEvent::Key(event) if event.modifiers.contains(KeyModifiers::ALT) && self.active_menu_item == MenuItem::Library => match event.code {
+                            KeyCode::Char('1') => self.do_quick_mix(MixSource::Track, 0, Some(-1)),
+                            KeyCode::Char('2') => self.do_quick_mix(MixSource::Track, 0, None),
+                            KeyCode::Char('3') => self.do_quick_mix(MixSource::Track, 0, Some(1)),
+                            KeyCode::Char('4') => self.do_quick_mix(MixSource::Click, 1, Some(-1)),
+                            KeyCode::Char('5') => self.do_quick_mix(MixSource::Click, 1, None),
+                            KeyCode::Char('6') => self.do_quick_mix(MixSource::Click, 1, Some(1)),
+                            KeyCode::Char('7') => self.do_quick_mix(MixSource::Track, 1, Some(-1)),
+                            KeyCode::Char('8') => self.do_quick_mix(MixSource::Track, 1, None),
+                            KeyCode::Char('9') => self.do_quick_mix(MixSource::Track, 1, Some(1)),
+                            KeyCode::Left => self.do_change_speed(-0.01),
+                            KeyCode::Right => self.do_change_speed(0.01),
+                            _ => {}
+                        },
+
+                        InputEvent::Key(event) if event.modifiers.contains(KeyModifiers::SHIFT) && !self.is_searching => match event.code {
+                            KeyCode::Char('s') => self.active_menu_item = MenuItem::Library,
+                            KeyCode::Char('d') => self.active_menu_item = MenuItem::Devices,
+                            KeyCode::Char('m') => self.active_menu_item = MenuItem::Mixer,
+                            KeyCode::Char('i') => self.active_menu_item = MenuItem::Insights,
+                            KeyCode::Char('y') => self.active_menu_item = MenuItem::Metronome,
+                            KeyCode::Char('h') => self.active_menu_item = MenuItem::Help,
+                            KeyCode::Char('q') => self.do_exit(),
+                            KeyCode::Left => self.do_change_speed(-0.1),
+                            KeyCode::Right => self.do_change_speed(0.1),
+                            KeyCode::Char('+') if self.active_menu_item == MenuItem::Mixer => {
+                                if let Some((source, output)) = self.selected_mix_cell() {
+                                    self.do_change_gain(source, &output, 5);
                                 }
-                                _ => {}
-                            },
-
-                            // Commands for the device view
-                            InputEvent::Key(event) if self.active_menu_item == MenuItem::Help => match event.code {
-                                KeyCode::Char('s') => self.active_menu_item = MenuItem::Library,
-                                KeyCode::Char('d') => self.active_menu_item = MenuItem::Devices,
-                                KeyCode::Char('q') => self.do_exit(),
-                                KeyCode::Char(' ') => self.do_pause(),
-                                KeyCode::Down => self.do_next_device(),
-                                KeyCode::Up => self.do_previous_device(),
-                                KeyCode::Char('t') => self.do_set_device(DeviceType::Track),
-                                KeyCode::Char('c') => self.do_set_device(DeviceType::Click),
-                                _ => {}
-                            },
-
-                            // Commands for the device view
-                            InputEvent::Key(event) if self.active_menu_item == MenuItem::Devices => match event.code {
-                                KeyCode::Char('s') => self.active_menu_item = MenuItem::Library,
-                                KeyCode::Char('d') => self.active_menu_item = MenuItem::Devices,
-                                KeyCode::Char('h') => self.active_menu_item = MenuItem::Help,
-                                KeyCode::Char('q') => self.do_exit(),
-                                KeyCode::Char(' ') => self.do_pause(),
-                                KeyCode::Down => self.do_next_device(),
-                                KeyCode::Up => self.do_previous_device(),
-                                KeyCode::Char('t') => self.do_set_device(DeviceType::Track),
-                                KeyCode::Char('c') => self.do_set_device(DeviceType::Click),
-                                _ => {}
-                            },
-
-                            // Commands for the library/queue
-                            InputEvent::Key(event) if self.active_menu_item == MenuItem::Library => match event.code {
-                                KeyCode::Char('s') => self.active_menu_item = MenuItem::Library,
-                                KeyCode::Char('d') => self.active_menu_item = MenuItem::Devices,
-                                KeyCode::Char('h') => self.active_menu_item = MenuItem::Help,
-                                KeyCode::Char('q') => self.do_exit(),
-                                KeyCode::Char('r') => self.do_reset_speed(),
-                                KeyCode::Char('a') => self.do_set_repeat(),
-                                KeyCode::Char('z') => self.do_restart_song(),
-                                KeyCode::Char('1') => self.do_decrease_volume(DeviceType::Track),
-                                KeyCode::Char('2') => self.do_reset_volume(DeviceType::Track),
-                                KeyCode::Char('3') => self.do_increase_volume(DeviceType::Track),
-                                KeyCode::Char('4') => self.do_decrease_volume(DeviceType::Click),
-                                KeyCode::Char('5') => self.do_reset_volume(DeviceType::Click),
-                                KeyCode::Char('6') => self.do_increase_volume(DeviceType::Click),
-                                KeyCode::Char('7') => self.do_decrease_volume(DeviceType::Bleed),
-                                KeyCode::Char('8') => self.do_reset_volume(DeviceType::Bleed),
-                                KeyCode::Char('9') => self.do_increase_volume(DeviceType::Bleed),
-                                KeyCode::Char('g') => self.do_start_search(),
-                                KeyCode::Char(' ') => self.do_pause(),
-                                KeyCode::Char('n') => self.do_play_next(),
-                                KeyCode::Char('x') => self.do_shuffle_library(),
-                                KeyCode::Char('/') => self.do_empty_queue(),
-                                KeyCode::Delete => self.do_delete_queue(),
-                                KeyCode::Insert => self.do_insert_queue(),
-                                KeyCode::Enter => self.do_playback(),
-                                KeyCode::Down => self.do_select_next(),
-                                KeyCode::Up => self.do_select_previous(),
-                                KeyCode::Tab => self.do_tab(),
-                                KeyCode::Left => self.do_backward(),
-                                KeyCode::Right => self.do_forward(),
-                                KeyCode::Home => self.do_goto_first(),
-                                KeyCode::End => self.do_goto_last(),
-                                KeyCode::PageDown => self.do_page_down(),
-                                KeyCode::PageUp => self.do_page_up(),
-                                _ => {}
-                            },
-                            InputEvent::Key(_) => {} // ignorable
-                            InputEvent::Mouse(_) => {
-                                // TODO: Option to scroll through library or queue
-                                // TODO: Double click on item to play it
-                                // All that requires tracking rows and pages
-                                // which has little benefit compared to the effort
-                                // Should rethink proper UI (egui or some webview)
                             }
-                            InputEvent::Tick => {}
+                            KeyCode::Char('+') if self.active_menu_item == MenuItem::Devices => self.do_start_add_output(),
+                            _ => {}
+                        },
+
+                        // Commands for searching
+                        InputEvent::Key(event) if self.active_menu_item == MenuItem::Library && self.is_searching => match event.code {
+                            KeyCode::Char(char) => {
+                                self.search_query.push(char);
+                                self.do_search();
+                            }
+                            KeyCode::Backspace => {
+                                self.search_query.pop();
+                                self.do_search();
+                            }
+                            KeyCode::Esc => {
+                                self.is_searching = false;
+                                self.search_query.clear();
+                                self.do_cancel_search();
+                            }
+                            KeyCode::Enter => {
+                                self.is_searching = false;
+                                self.do_complete_search();
+                            }
+                            KeyCode::Tab => {
+                                self.is_searching = false;
+                                self.do_empty_queue();
+                                self.do_complete_search();
+                            }
+                            _ => {}
+                        },
+
+                        // Commands for the device view
+                        InputEvent::Key(event) if self.active_menu_item == MenuItem::Help => match event.code {
+                            KeyCode::Char('s') => self.active_menu_item = MenuItem::Library,
+                            KeyCode::Char('d') => self.active_menu_item = MenuItem::Devices,
+                            KeyCode::Char('m') => self.active_menu_item = MenuItem::Mixer,
+                            KeyCode::Char('i') => self.active_menu_item = MenuItem::Insights,
+                            KeyCode::Char('y') => self.active_menu_item = MenuItem::Metronome,
+                            KeyCode::Char('q') => self.do_exit(),
+                            KeyCode::Char(' ') => self.do_pause(),
+                            KeyCode::Down => self.do_next_device(),
+                            KeyCode::Up => self.do_previous_device(),
+                            _ => {}
+                        },
+
+                        // Commands for the device view
+                        InputEvent::Key(event) if self.active_menu_item == MenuItem::Devices => match event.code {
+                            KeyCode::Char('s') => self.active_menu_item = MenuItem::Library,
+                            KeyCode::Char('d') => self.active_menu_item = MenuItem::Devices,
+                            KeyCode::Char('m') => self.active_menu_item = MenuItem::Mixer,
+                            KeyCode::Char('i') => self.active_menu_item = MenuItem::Insights,
+                            KeyCode::Char('y') => self.active_menu_item = MenuItem::Metronome,
+                            KeyCode::Char('h') => self.active_menu_item = MenuItem::Help,
+                            KeyCode::Char('q') => self.do_exit(),
+                            KeyCode::Char(' ') => self.do_pause(),
+                            KeyCode::Down => self.do_next_device(),
+                            KeyCode::Up => self.do_previous_device(),
+                            KeyCode::Tab => self.do_next_output(),
+                            KeyCode::Enter => self.do_set_device(),
+                            KeyCode::Char('l') => self.do_cycle_output_channels(),
+                            KeyCode::Char('+') => self.do_start_add_output(),
+                            KeyCode::Delete => self.do_delete_output(),
+                            KeyCode::Char('r') => self.do_rescan_devices(),
+                            KeyCode::Left => self.do_previous_test_channel(),
+                            KeyCode::Right => self.do_next_test_channel(),
+                            KeyCode::Char('b') => self.do_test_channel(TestSignal::Beep),
+                            KeyCode::Char('o') => self.do_test_channel(TestSignal::Tone),
+                            KeyCode::Char('k') => self.do_cycle_sample_rate(),
+                            KeyCode::Char('f') => self.do_cycle_buffer_size(),
+                            KeyCode::Char('a') => self.do_cycle_host(),
+                            _ => {}
+                        },
+
+                        // Commands for the insights view
+                        InputEvent::Key(event) if self.active_menu_item == MenuItem::Insights => match event.code {
+                            KeyCode::Char('s') => self.active_menu_item = MenuItem::Library,
+                            KeyCode::Char('d') => self.active_menu_item = MenuItem::Devices,
+                            KeyCode::Char('m') => self.active_menu_item = MenuItem::Mixer,
+                            KeyCode::Char('y') => self.active_menu_item = MenuItem::Metronome,
+                            KeyCode::Char('h') => self.active_menu_item = MenuItem::Help,
+                            KeyCode::Char('q') => self.do_exit(),
+                            KeyCode::Char(' ') => self.do_pause(),
+                            KeyCode::Char('c') => self.do_export_history("csv"),
+                            KeyCode::Char('j') => self.do_export_history("json"),
+                            _ => {}
+                        },
+
+                        // Commands for the metronome view
+                        InputEvent::Key(event) if self.active_menu_item == MenuItem::Metronome => match event.code {
+                            KeyCode::Char('s') => self.active_menu_item = MenuItem::Library,
+                            KeyCode::Char('d') => self.active_menu_item = MenuItem::Devices,
+                            KeyCode::Char('m') => self.active_menu_item = MenuItem::Mixer,
+                            KeyCode::Char('i') => self.active_menu_item = MenuItem::Insights,
+                            KeyCode::Char('h') => self.active_menu_item = MenuItem::Help,
+                            KeyCode::Char('q') => self.do_exit(),
+                            KeyCode::Char(' ') | KeyCode::Enter => self.do_toggle_metronome(),
+                            KeyCode::Up | KeyCode::Char('+') | KeyCode::Char('=') => self.do_change_metronome_bpm(1),
+                            KeyCode::Down | KeyCode::Char('-') => self.do_change_metronome_bpm(-1),
+                            KeyCode::Right | KeyCode::PageUp => self.do_change_metronome_bpm(5),
+                            KeyCode::Left | KeyCode::PageDown => self.do_change_metronome_bpm(-5),
+                            KeyCode::Char('t') => self.do_tap_tempo(),
+                            KeyCode::Char('b') => self.do_update_metronome(Metronome::cycle_beats_per_bar),
+                            KeyCode::Char('n') => self.do_update_metronome(Metronome::cycle_beat_unit),
+                            KeyCode::Char('u') => self.do_update_metronome(Metronome::cycle_subdivision),
+                            KeyCode::Char('p') => self.do_update_metronome(Metronome::cycle_polyrhythm),
+                            KeyCode::Char('c') => self.do_update_metronome(Metronome::cycle_timer),
+                            KeyCode::Char(digit @ '1'..='9') => self.do_toggle_accent(digit as usize - '0' as usize),
+                            _ => {}
+                        },
+
+                        // Commands for the mixer view
+                        InputEvent::Key(event) if self.active_menu_item == MenuItem::Mixer => match (event.code, self.selected_mix_cell()) {
+                            (KeyCode::Char('s'), _) => self.active_menu_item = MenuItem::Library,
+                            (KeyCode::Char('d'), _) => self.active_menu_item = MenuItem::Devices,
+                            (KeyCode::Char('i'), _) => self.active_menu_item = MenuItem::Insights,
+                            (KeyCode::Char('y'), _) => self.active_menu_item = MenuItem::Metronome,
+                            (KeyCode::Char('h'), _) => self.active_menu_item = MenuItem::Help,
+                            (KeyCode::Char('q'), _) => self.do_exit(),
+                            (KeyCode::Char(' '), _) => self.do_pause(),
+                            (KeyCode::Down, _) => self.do_next_mix_output(),
+                            (KeyCode::Up, _) => self.do_previous_mix_output(),
+                            (KeyCode::Right | KeyCode::Tab, _) => self.do_next_mix_source(),
+                            (KeyCode::Left, _) => self.do_previous_mix_source(),
+                            (KeyCode::Char('+') | KeyCode::Char('='), Some((source, output))) => self.do_change_gain(source, &output, 5),
+                            (KeyCode::Char('-'), Some((source, output))) => self.do_change_gain(source, &output, -5),
+                            (KeyCode::Char('['), Some((source, output))) => self.do_change_pan(source, &output, -10),
+                            (KeyCode::Char(']'), Some((source, output))) => self.do_change_pan(source, &output, 10),
+                            (KeyCode::Char('m'), Some((source, output))) => self.do_toggle_mute(source, &output),
+                            (KeyCode::Char('0'), Some((source, output))) => self.do_reset_mix_cell(source, &output),
+                            (KeyCode::Char('w'), _) => self.do_start_save_preset(),
+                            (KeyCode::Char('p'), _) => self.do_next_preset(),
+                            (KeyCode::Delete, _) => self.do_delete_preset(),
+                            _ => {}
+                        },
+
+                        // Commands for the library/queue
+                        InputEvent::Key(event) if self.active_menu_item == MenuItem::Library => match event.code {
+                            KeyCode::Char('s') => self.active_menu_item = MenuItem::Library,
+                            KeyCode::Char('d') => self.active_menu_item = MenuItem::Devices,
+                            KeyCode::Char('h') => self.active_menu_item = MenuItem::Help,
+                            KeyCode::Char('q') => self.do_exit(),
+                            KeyCode::Char('r') => self.do_reset_speed(),
+                            KeyCode::Char('a') => self.do_set_repeat(),
+                            KeyCode::Char('z') => self.do_restart_song(),
+                            KeyCode::Char('e') => self.send_player_command(PlayerCommand::ExportTempoMap),
+                            KeyCode::Char(digit @ '1'..='9') => self.do_jump_to_marker(digit as usize - '1' as usize),
+                            KeyCode::Char('k') => self.do_start_add_marker(),
+                            KeyCode::Char('j') => self.do_start_go_to(),
+                            KeyCode::Char('t') => self.do_start_target_bpm(),
+                            KeyCode::Char('u') => self.do_remove_marker(),
+                            KeyCode::Char('[') => self.do_previous_marker(),
+                            KeyCode::Char(']') => self.do_next_marker(),
+                            KeyCode::Char('l') => self.do_cycle_loop(),
+                            KeyCode::Char('c') => self.do_cycle_count_in(),
+                            KeyCode::Char('o') => self.do_start_notes(),
+                            KeyCode::Char('w') => self.do_save_practice_settings(),
+                            KeyCode::Char('p') => self.do_toggle_routine(),
+                            KeyCode::Char('v') => self.do_review_queue(),
+                            KeyCode::Char('f') => self.do_toggle_pin(),
+                            KeyCode::Char('b') => self.do_clear_cache(),
+                            KeyCode::F(1) => self.do_rate(Rating::NailedIt),
+                            KeyCode::F(2) => self.do_rate(Rating::Okay),
+                            KeyCode::F(3) => self.do_rate(Rating::Struggled),
+                            KeyCode::F(5) => self.do_check_library(),
+                            KeyCode::F(6) => self.do_start_batch(BatchFormat::Wav),
+                            KeyCode::F(7) => self.do_start_batch(BatchFormat::Flac),
+                            KeyCode::Char('m') => self.active_menu_item = MenuItem::Mixer,
+                            KeyCode::Char('i') => self.active_menu_item = MenuItem::Insights,
+                            KeyCode::Char('y') => self.active_menu_item = MenuItem::Metronome,
+                            KeyCode::Char('g') => self.do_start_search(),
+                            KeyCode::Char(' ') => self.do_pause(),
+                            KeyCode::Char('n') => self.do_play_next(),
+                            KeyCode::Char('x') => self.do_shuffle_library(),
+                            KeyCode::Char('/') => self.do_empty_queue(),
+                            KeyCode::Delete => self.do_delete_queue(),
+                            KeyCode::Insert => self.do_insert_queue(),
+                            KeyCode::Enter => self.do_playback(),
+                            KeyCode::Down => self.do_select_next(),
+                            KeyCode::Up => self.do_select_previous(),
+                            KeyCode::Tab => self.do_tab(),
+                            KeyCode::Left => self.do_backward(),
+                            KeyCode::Right => self.do_forward(),
+                            KeyCode::Home => self.do_goto_first(),
+                            KeyCode::End => self.do_goto_last(),
+                            KeyCode::PageDown => self.do_page_down(),
+                            KeyCode::PageUp => self.do_page_up(),
+                            _ => {}
+                        },
+                        InputEvent::Key(_) => {} // ignorable
+                        InputEvent::Mouse(event) => {
+                            info!("Mouse event: {:?}", event);
+                            // TODO: Option to scroll through library or queue
+                            // TODO: Double click on item to play it
+                            // All that requires tracking rows and pages
+                            // which has little benefit compared to the effort
+                            // Should rethink proper UI (egui or some webview)
+                        }
+                        // Nothing changes but the size, and every event is followed by drawing the screen
+                        InputEvent::Resize => {}
+                        // Background work that reports back on its own time
+                        InputEvent::Tick => {
+                            self.do_routine_tick();
+                            self.do_metronome_tick();
+                            self.do_check_tick();
+                            self.do_batch_tick();
                         }
                     }
                 }
@@ -155,48 +305,126 @@ impl UiEventTrait for App {
         }
     }
 
-    fn handle_player_events(&mut self) {
+    fn handle_player_event(&mut self, event: PlayerEvent) {
         // handle signals from the player
-        if let Ok(event) = self.player_event_receiver.try_recv() {
-            match event {
-                PlayerEvent::Decompressing => {
-                    self.player_status = PlayerStatus::Decompressing;
-                }
-                PlayerEvent::Decompressed => {
-                    self.player_status = PlayerStatus::Decompressed;
+        match event {
+            PlayerEvent::Loading(stub, progress) => {
+                self.loading = Some((stub, progress));
+            }
+            PlayerEvent::Playing(stub) => {
+                let stub_clone = stub.clone();
+                self.player_status = PlayerStatus::Playing(stub.title);
+                self.song_data = SongData::load(&stub_clone.folder, &stub_clone.file_name);
+                self.loading = None;
+                if let Some((_, speed)) = self.pending_speed.take().filter(|(file_name, _)| *file_name == stub_clone.file_name) {
+                    self.send_player_command(PlayerCommand::SetSpeed(speed));
                 }
-                PlayerEvent::Playing(stub) => {
-                    let stub_clone = stub.clone();
-                    self.player_status = PlayerStatus::Playing(stub.title);
-                    self.active_stub = Some(stub_clone);
+                self.active_stub = Some(stub_clone);
+                self.metronome_started = None;
+                self.restore_practice_settings();
+                self.notice = None;
+                self.do_prefetch();
+            }
+            PlayerEvent::Paused => {
+                self.player_status = PlayerStatus::Paused;
+            }
+            PlayerEvent::Continuing(stub) => {
+                // no stub while only the metronome plays
+                self.player_status = PlayerStatus::Playing(stub.map(|stub| stub.title).unwrap_or_else(|| "Metronome".to_string()));
+            }
+            PlayerEvent::Crashed(message, is_restarted) => {
+                error!("The player crashed: {}", message);
+                // whatever was playing or loading is gone, along with the song's practice settings
+                self.player_status = PlayerStatus::Error("Player crashed".to_string());
+                self.playback_status = None;
+                self.active_stub = None;
+                self.loading = None;
+                self.settings_song = None;
+                self.loop_region = None;
+                self.loop_start = None;
+                if let Some(matrix) = self.global_mix.take() {
+                    self.mix = matrix;
                 }
-                PlayerEvent::Paused => {
-                    self.player_status = PlayerStatus::Paused;
+
+                let report = report_path().map(|path| format!(" Details are in {}.", path.display())).unwrap_or_default();
+                self.error_banner = Some(if is_restarted {
+                    format!("The player crashed ({}) and was restarted.{} Esc to dismiss.", message, report)
+                } else {
+                    format!("The player keeps crashing ({}) and was left down. Quit and start Drum Weaver again.{}", message, report)
+                });
+                if is_restarted {
+                    self.send_player_setup();
+                    self.do_prefetch();
+                } else {
+                    self.is_player_down = true;
                 }
-                PlayerEvent::Continuing(stub) => {
-                    self.player_status = PlayerStatus::Playing(stub.unwrap().title);
+            }
+            PlayerEvent::Quit => {
+                self.on_exit();
+                self.is_running = false;
+            }
+            PlayerEvent::LoadFailure(stub, reason) => {
+                error!("App received LoadFailure: {}: {}", stub.title, reason);
+                self.loading = None;
+                let message = format!("{}: {}. {}", stub.title, reason, reason.hint()).trim_end().to_string();
+                // whatever was playing carries on
+                if matches!(self.player_status, PlayerStatus::Playing(_) | PlayerStatus::Paused) {
+                    self.notice = Some(format!("Could not load {}", message));
+                } else {
+                    self.player_status = PlayerStatus::Error(message);
                 }
-                PlayerEvent::Quit => {
-                    self.on_exit();
-                    self.is_running = false;
+                self.do_routine_song_ended(); // moves on to the next song of the routine, if there is one
+                if reason.is_song_broken() {
+                    self.do_offer_to_hide(&stub, &reason);
                 }
-                PlayerEvent::LoadFailure(stub) => {
-                    error!("App received LoadFailure: {}", stub.title);
-                    self.library.as_mut().unwrap().remove_song_by_stub(stub.clone());
-                    self.player_status = PlayerStatus::Error(stub.title);
+            }
+            PlayerEvent::Status(status) => {
+                self.playback_status = Some(status);
+                self.do_routine_tick();
+                self.do_metronome_tick();
+            }
+            PlayerEvent::DeviceFallback(output, name) => {
+                error!("{} device {} is missing. Using the default output.", output, name);
+                self.device_warning = Some(format!("{} device '{}' is missing, using the default output", output, name));
+            }
+            PlayerEvent::Notice(notice) => {
+                self.notice = Some(notice);
+            }
+            PlayerEvent::Played(record) => {
+                info!("Played {} for {:.0}s at {:.0}%", record.title, record.played, record.speed * 100.0);
+                // a play long enough to count is a review, okay until rated
+                if record.played >= MIN_REVIEW_PLAY {
+                    self.reviews.review(&record.file_name, Rating::Okay, record.started.date_naive());
+                    if let Err(err) = self.reviews.save() {
+                        error!("Unable to save the reviews: {:?}", err);
+                    }
+                    self.pending_rating = Some((record.file_name.clone(), record.title.clone()));
                 }
-                PlayerEvent::Status(status) => {
-                    self.playback_status = Some(status);
+                if let Err(err) = self.history.append(record) {
+                    error!("Unable to save the history: {:?}", err);
+                    self.notice = Some(format!("Could not save the practice history: {}", err));
                 }
-                PlayerEvent::Ended => {
-                    self.player_status = PlayerStatus::Ended;
+            }
+            PlayerEvent::DeviceRestored(output, name) => {
+                info!("{} device {} reattached", output, name);
+                self.device_warning = None;
+            }
+            PlayerEvent::Ended => {
+                self.player_status = PlayerStatus::Ended;
+                if !self.do_routine_song_ended() {
                     self.do_autoplay();
                 }
             }
         }
     }
 
+    // Once the player is left down nobody is listening, and the banner says so
     fn send_player_command(&mut self, command: PlayerCommand) {
-        self.player_command_sender.send(command).unwrap();
+        if self.is_player_down {
+            return;
+        }
+        if let Err(err) = self.player_command_sender.send(command) {
+            error!("Unable to send {:?} to the player: it is gone", err.into_inner());
+        }
     }
 }
@@ -1,24 +1,29 @@
-pub mod audio;
-pub mod beep;
+pub mod batch;
+pub mod check;
 pub mod commands;
-pub mod devices;
+pub mod crash;
 pub mod events;
-pub mod library;
-pub mod player;
+#[cfg(test)]
+#[path = "../fixtures.rs"]
+mod fixtures;
 pub mod render;
+pub mod review;
+pub mod routine;
 pub mod setup;
 pub mod status_bar;
+use crate::app::commands::UiCommandTrait;
 use crate::app::render::UiRenderTrait;
 use crate::app::setup::UiSetupTrait;
 
 use std::{
+    collections::{BTreeMap, HashMap},
     fmt::Display,
     io::{stdout, Stdout},
-    thread,
-    time::Duration,
+    path::PathBuf,
+    time::{Duration, Instant},
 };
 
-use crossbeam_channel::{unbounded, Receiver, Sender};
+use crossbeam_channel::{never, select, tick, unbounded, Receiver, Sender};
 use crossterm::{
     event::EnableMouseCapture,
     event::{KeyEvent, MouseEvent},
@@ -30,13 +35,30 @@ use crossterm::{
 use log::{info, error};
 use ratatui::{backend::CrosstermBackend, widgets::TableState, Terminal};
 
+use drum_weaver::{
+    cache::{set_cache, CacheSettings, DEFAULT_QUOTA_MB},
+    devices::{set_host, DevicePreference},
+    history::History,
+    library::{Library, SongRecord},
+    loader::LoadProgress,
+    metronome::{Metronome, TapTempo},
+    mixer::MixMatrix,
+    player::{OutputConfig, PlaybackStatus, PlayerCommand, PlayerEvent, SongStub, DEFAULT_STATUS_RATE},
+    prefetch::{PrefetchSettings, DEFAULT_BUDGET_MB, DEFAULT_LOOKAHEAD},
+    song_data::{LoopRegion, SongData},
+};
+
 use self::{
-    devices::read_devices,
+    batch::{BatchFormat, BatchJob},
+    check::CheckOutcome,
     events::UiEventTrait,
-    library::{Library, SongRecord},
-    player::{DeviceType, PlaybackStatus, PlayerCommand, PlayerEvent, SongStub},
+    review::{ReviewSchedule, DEFAULT_DAILY_REVIEWS, DEFAULT_NEW_SONGS_PER_DAY},
+    routine::RoutineRun,
 };
 
+// How often the check, a batch, a routine or the metronome is moved along while one is running
+const TICK_RATE: Duration = Duration::from_millis(500);
+
 #[derive(PartialEq)]
 pub enum ActiveFocus {
     Library,
@@ -48,11 +70,8 @@ pub enum PlayerStatus {
     Ready,
     Playing(String),
     Paused,
-    Decompressing,
-    Decompressed,
     Ended,
     Error(String),
-    Waiting,
 }
 
 impl PlayerStatus {
@@ -61,32 +80,63 @@ impl PlayerStatus {
             PlayerStatus::Ready => "Ready".to_string(),
             PlayerStatus::Playing(_) => "Playing".to_string(),
             PlayerStatus::Paused => "Paused".to_string(),
-            PlayerStatus::Decompressing => "Decompressing ...".to_string(),
-            PlayerStatus::Decompressed => "Decompressed".to_string(),
             PlayerStatus::Ended => "Ended".to_string(),
             PlayerStatus::Error(s) => format!("Error loading song: {}", s),
-            PlayerStatus::Waiting => "Waiting ...".to_string(),
         }
     }
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize, Clone, Default)]
 struct AppConfig {
-    track_device_name: Option<String>,
+    // toml wants plain values before any table, so those go first
+    track_device_name: Option<String>, // replaced by outputs
     click_device_name: Option<String>,
     track_volume: Option<usize>,
     click_volume: Option<usize>,
     bleed_volume: Option<usize>,
     search_query: Option<String>,
+    host_name: Option<String>,
+    active_preset: Option<String>,
+    new_songs_per_day: Option<usize>,  // brought into the review queue
+    daily_reviews: Option<usize>,      // at most, on top of the new songs
+    prefetch_lookahead: Option<usize>, // queue songs extracted ahead of playback
+    prefetch_budget_mb: Option<u64>,   // the most disk they may take
+    prefetch_decode: Option<bool>,     // decode them too, at the cost of memory
+    cache_folder: Option<String>,      // where songs are extracted to. Beside the configuration when missing.
+    cache_quota_mb: Option<u64>,
+    status_rate: Option<u32>, // playback status updates a second, for the gauge and beat indicator
+    #[serde(default)]
+    pinned_songs: Vec<String>, // file names of the songs that stay in the cache
     queue: Vec<SongRecord>,
+    #[serde(default)]
+    outputs: Vec<OutputConfig>, // when empty, built from the old track and click devices
+    #[serde(default)]
+    device_preferences: HashMap<String, DevicePreference>,
+    #[serde(default)]
+    jack_connections: HashMap<String, Vec<String>>, // e.g. "click_L" -> ["system:playback_3"]
+    mix: Option<MixMatrix>, // when missing, built from the old volumes above
+    #[serde(default)]
+    mix_presets: BTreeMap<String, MixMatrix>,
+    metronome: Option<Metronome>,
+}
+
+impl AppConfig {
+    fn cache_settings(&self) -> CacheSettings {
+        CacheSettings {
+            folder: self.cache_folder.clone().map(PathBuf::from).unwrap_or_else(CacheSettings::default_folder),
+            quota_mb: self.cache_quota_mb.unwrap_or(DEFAULT_QUOTA_MB),
+            pinned: self.pinned_songs.iter().cloned().collect(),
+        }
+    }
 }
 
 impl Display for AppConfig {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let track_device_name = self.track_device_name.as_ref().cloned().unwrap();
-        let click_device_name = self.click_device_name.as_ref().cloned().unwrap();
+        for output in self.outputs.iter() {
+            writeln!(f, "{} Device: {}", output.name, output.device_name.as_deref().unwrap_or("default"))?;
+        }
 
-        write!(f, "Track Device: {}\nClick Device: {}", track_device_name, click_device_name)
+        Ok(())
     }
 }
 
@@ -107,21 +157,55 @@ pub struct App {
     pub is_exiting: bool,
     pub playback_status: Option<PlaybackStatus>,
     pub player_status: PlayerStatus,
-    pub track_device_idx: usize,
-    pub click_device_idx: usize,
-    pub track_volume: usize,
-    pub click_volume: usize,
-    pub bleed_volume: usize,
+    pub outputs: Vec<OutputConfig>,
+    pub selected_output: usize, // in the devices view
+    pub device_warning: Option<String>,
+    pub notice: Option<String>, // the outcome of the last export and the like
+    pub device_preferences: HashMap<String, DevicePreference>,
+    pub test_channel: usize,
+    pub host_name: Option<String>,
+    pub jack_connections: HashMap<String, Vec<String>>,
+    pub mix: MixMatrix,
+    pub mix_presets: BTreeMap<String, MixMatrix>,
+    pub active_preset: Option<String>,
+    pub mixer_source: usize, // selected cell in the mixer view
+    pub mixer_output: usize,
+    pub prompt: Option<Prompt>,
     pub active_stub: Option<SongStub>,
+    pub loading: Option<(SongStub, LoadProgress)>, // the song that plays next, while it loads
+    pub pending_speed: Option<(String, f64)>,      // file name and speed, set once that song plays
+    pub song_data: SongData,                       // of the active song
+    pub settings_song: Option<String>,             // file name of the song whose practice settings are in effect
+    pub global_mix: Option<MixMatrix>,             // the mix to go back to while a song has its own
+    pub loop_region: Option<LoopRegion>,
+    pub loop_start: Option<Duration>, // the A point, while waiting for B
+    pub count_in_bars: usize,
+    pub history: History,
+    pub routine: Option<RoutineRun>, // the practice routine being worked through
+    pub reviews: ReviewSchedule,
+    pub pending_rating: Option<(String, String)>, // file name and title of the last song played, until it is rated
+    pub new_songs_per_day: usize,
+    pub daily_reviews: usize,
+    pub metronome: Metronome,
+    pub metronome_started: Option<Instant>, // while the metronome view's click is going
+    pub tap_tempo: TapTempo,
+    pub prefetch: PrefetchSettings,
+    pub cache: CacheSettings,
+    pub library_check: Option<Receiver<CheckOutcome>>, // while a check or repair runs in the background
+    pub batch: Option<BatchJob>,                       // songs being extracted or converted ahead of time
     pub is_searching: bool,
     pub search_query: String,
     pub is_repeating: bool,
-    pub page_size: usize, // calculated off number of visible rows. Used in paging.
+    pub page_size: usize,             // calculated off number of visible rows. Used in paging.
+    pub error_banner: Option<String>, // something gone badly wrong, until dismissed
+    pub is_player_down: bool,         // it kept crashing and was not restarted
+    pub status_rate: u32,
 }
 
 pub enum InputEvent {
     Key(KeyEvent),
     Mouse(MouseEvent),
+    Resize, // the terminal changed size, so the screen is drawn again
     Tick,
 }
 pub enum UiEvent<I> {
@@ -132,6 +216,9 @@ pub enum UiEvent<I> {
 pub enum MenuItem {
     Library,
     Devices,
+    Mixer,
+    Insights,
+    Metronome,
     Help,
 }
 
@@ -140,11 +227,48 @@ impl From<MenuItem> for usize {
         match input {
             MenuItem::Library => 1,
             MenuItem::Devices => 2,
-            MenuItem::Help => 3,
+            MenuItem::Mixer => 3,
+            MenuItem::Insights => 4,
+            MenuItem::Metronome => 5,
+            MenuItem::Help => 6,
         }
     }
 }
 
+// What a text prompt is asking for. Shown in place of the menu while open.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum PromptKind {
+    SavePreset,
+    AddOutput,
+    AddMarker(Duration), // where the marker goes, taken when the prompt opened
+    GoTo,
+    TargetBpm,
+    Notes,
+    StartRoutine,
+    Batch(BatchFormat),
+}
+
+impl PromptKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            PromptKind::SavePreset => "Save mix preset as: ",
+            PromptKind::AddOutput => "New output name: ",
+            PromptKind::AddMarker(_) => "Marker name (intro, verse, chorus, solo, outro ...): ",
+            PromptKind::GoTo => "Go to (2:35, bar 48 or 75%): ",
+            PromptKind::TargetBpm => "Play at BPM: ",
+            PromptKind::Notes => "Notes: ",
+            PromptKind::StartRoutine => "Start routine: ",
+            PromptKind::Batch(BatchFormat::Wav) => "Extract the queue, the list, all or a genre: ",
+            PromptKind::Batch(BatchFormat::Flac) => "Convert to FLAC the queue, the list, all or a genre: ",
+        }
+    }
+}
+
+pub struct Prompt {
+    pub kind: PromptKind,
+    pub input: String,
+}
+
 impl App {
     pub fn new(player_command_sender: Sender<PlayerCommand>, player_event_receiver: Receiver<PlayerEvent>) -> Self {
         let config: AppConfig = match confy::load("drum-weaver", None) {
@@ -155,18 +279,30 @@ impl App {
             }
         };
 
-        let mut track_device_idx = 0;
-        let mut click_device_idx = 0;
+        // The host decides which devices exist, so it goes before anything device related
+        set_host(config.host_name.as_deref());
 
-        if let Some(click_device_name) = config.click_device_name.as_ref() {
-            click_device_idx = read_devices().iter().position(|d| &d.name == click_device_name).unwrap_or(0);
-            player_command_sender.send(PlayerCommand::SetDevice(DeviceType::Click, click_device_name.clone())).unwrap();
-        }
+        // Devices are remembered by name. If one is missing, the player falls back to the default output and reattaches later.
+        let outputs = if config.outputs.is_empty() {
+            OutputConfig::defaults(config.track_device_name.clone(), config.click_device_name.clone())
+        } else {
+            config.outputs.clone()
+        };
 
-        if let Some(track_device_name) = config.track_device_name.as_ref() {
-            track_device_idx = read_devices().iter().position(|d| &d.name == track_device_name).unwrap_or(0);
-            player_command_sender.send(PlayerCommand::SetDevice(DeviceType::Track, track_device_name.clone())).unwrap();
-        }
+        // The cache goes before anything that might extract a song
+        let cache = config.cache_settings();
+        set_cache(cache.clone());
+
+        let prefetch = PrefetchSettings {
+            lookahead: config.prefetch_lookahead.unwrap_or(DEFAULT_LOOKAHEAD),
+            budget_mb: config.prefetch_budget_mb.unwrap_or(DEFAULT_BUDGET_MB),
+            decode: config.prefetch_decode.unwrap_or(false),
+        };
+
+        let mix = config
+            .mix
+            .clone()
+            .unwrap_or_else(|| MixMatrix::from_volumes(config.track_volume.unwrap_or(100), config.click_volume.unwrap_or(100), config.bleed_volume.unwrap_or(100)));
 
         // Set up the terminal
         let mut stdout = stdout();
@@ -185,7 +321,7 @@ impl App {
 
         info!("App initialized");
 
-        App {
+        let mut app = App {
             player_command_sender,
             player_event_receiver,
             ui_command_sender,
@@ -202,18 +338,52 @@ impl App {
             is_exiting: false,
             playback_status: None,
             player_status: PlayerStatus::Ready,
-            track_device_idx,
-            click_device_idx,
-            track_volume: config.track_volume.unwrap_or(100),
-            click_volume: config.click_volume.unwrap_or(100),
-            bleed_volume: config.bleed_volume.unwrap_or(100),
+            outputs,
+            selected_output: 0,
+            device_warning: None,
+            notice: None,
+            device_preferences: config.device_preferences,
+            test_channel: 0,
+            host_name: config.host_name,
+            jack_connections: config.jack_connections,
+            mix,
+            mix_presets: config.mix_presets,
+            active_preset: config.active_preset,
+            mixer_source: 0,
+            mixer_output: 0,
+            prompt: None,
             active_stub: None,
+            loading: None,
+            pending_speed: None,
+            song_data: SongData::default(),
+            settings_song: None,
+            global_mix: None,
+            loop_region: None,
+            loop_start: None,
+            count_in_bars: 0,
+            history: History::load(),
+            routine: None,
+            reviews: ReviewSchedule::load(),
+            pending_rating: None,
+            new_songs_per_day: config.new_songs_per_day.unwrap_or(DEFAULT_NEW_SONGS_PER_DAY),
+            daily_reviews: config.daily_reviews.unwrap_or(DEFAULT_DAILY_REVIEWS),
+            metronome: config.metronome.unwrap_or_default(),
+            metronome_started: None,
+            tap_tempo: TapTempo::default(),
+            prefetch,
+            cache,
+            library_check: None,
+            batch: None,
             is_searching: false,
             search_query: config.search_query.unwrap_or_default(),
             is_repeating: false,
             page_size: 10,
-        }
-
+            error_banner: None,
+            is_player_down: false,
+            status_rate: config.status_rate.unwrap_or(DEFAULT_STATUS_RATE),
+        };
+        app.send_player_setup();
+        app
     }
 
     pub fn run(&mut self) {
@@ -244,30 +414,47 @@ impl App {
             }
         };
 
-        let player_command_sender_clone = self.player_command_sender.clone();
-
-        // set the initial volumes
-        self.send_player_command(PlayerCommand::SetVolume(DeviceType::Track, self.track_volume));
-        self.send_player_command(PlayerCommand::SetVolume(DeviceType::Click, self.click_volume));
-        self.send_player_command(PlayerCommand::SetVolume(DeviceType::Bleed, self.bleed_volume));
-
-        // listen for position updates
-        thread::spawn(move || loop {
-            player_command_sender_clone.send(PlayerCommand::GetStatus).unwrap();
-            thread::sleep(Duration::from_millis(500));
-        });
+        // the first song of the queue can be getting ready before anything plays
+        self.do_prefetch();
 
         info!("App is running");
 
+        // Sleeps until there is input or the player has news, and only draws then. The player sends the status on its own.
+        // Ticks only come while something needs them.
+        let (ui_events, player_events) = (self.ui_command_receiver.clone(), self.player_event_receiver.clone());
+        let no_ticks = never();
+        let mut ticks: Option<Receiver<Instant>> = None;
+        self.render_ui();
         while self.is_running {
-            // Wait for the user to pick a folder
-            if self.library.is_none() {
-                continue;
-            };
+            select! {
+                recv(ui_events) -> event => match event {
+                    Ok(event) => self.handle_ui_event(event),
+                    Err(_) => break,
+                },
+                recv(player_events) -> event => match event {
+                    Ok(event) => self.handle_player_event(event),
+                    Err(_) => break,
+                },
+                recv(ticks.as_ref().unwrap_or(&no_ticks)) -> _ => self.handle_ui_event(UiEvent::Input(InputEvent::Tick)),
+            }
 
-            self.handle_ui_events();
-            self.handle_player_events();
+            // whatever else the player sent meanwhile, so drawing never falls behind
+            for event in player_events.try_iter() {
+                self.handle_player_event(event);
+            }
             self.render_ui();
+
+            // started and stopped with what needs it, so the schedule isn't reset by every event
+            match (self.is_ticking(), ticks.is_some()) {
+                (true, false) => ticks = Some(tick(TICK_RATE)),
+                (false, true) => ticks = None,
+                _ => {}
+            }
         }
     }
+
+    // Whether a tick has anything to do: something running in the background, or against the clock
+    fn is_ticking(&self) -> bool {
+        self.library_check.is_some() || self.batch.is_some() || self.routine.is_some() || self.metronome_started.is_some()
+    }
 }
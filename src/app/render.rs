@@ -1,26 +1,39 @@
 use std::time::Duration;
 
+use chrono::Local;
 use ratatui::{
-    layout::{Constraint, Direction, Layout, Rect},
+    layout::{Constraint, Direction, Layout},
     style::{Color, Modifier, Style},
-    symbols,
-    text::{Line, Span, Text},
-    widgets::{Block, BorderType, Borders, Cell, LineGauge, Paragraph, Row, Table, Tabs},
+    text::{Line, Span},
+    widgets::{Block, BorderType, Borders, Cell, Paragraph, Row, Table, Tabs, Wrap},
 };
 
-use super::{devices::read_devices, status_bar::CustomGauge, ActiveFocus, App, MenuItem, PlayerStatus};
+use drum_weaver::{
+    devices::{current_host_name, is_jack_host, read_devices},
+    mixer::MixSource,
+    player::PlaybackStatus,
+};
+
+use super::{status_bar::CustomGauge, ActiveFocus, App, MenuItem, PlayerStatus};
 
 pub trait UiRenderTrait {
     fn render_ui(&mut self);
+    fn render_banner(&mut self) -> Option<Paragraph<'static>>;
     fn render_menu(&mut self) -> Tabs<'static>;
     fn render_songs(&mut self) -> Table<'static>;
     fn render_queue(&mut self) -> Table<'static>;
     fn render_devices(&mut self) -> Table<'static>;
+    fn render_device_details(&mut self) -> Paragraph<'static>;
+    fn render_mixer(&mut self) -> Table<'static>;
+    fn render_mixer_details(&mut self) -> Paragraph<'static>;
+    fn render_practice_time(&mut self) -> Paragraph<'static>;
+    fn render_most_practiced(&mut self) -> Table<'static>;
+    fn render_metronome(&mut self) -> Paragraph<'static>;
+    fn render_prompt(&mut self) -> Option<Paragraph<'static>>;
     fn render_footer(&mut self) -> Paragraph<'static>;
-    fn render_gauge(&mut self) -> LineGauge<'static>;
+    fn render_beat(&mut self) -> Option<Paragraph<'static>>;
     fn render_search(&mut self) -> Paragraph<'static>;
     fn render_help(&mut self) -> Paragraph<'static>;
-    fn render_wait(&mut self) -> Paragraph<'static>;
 }
 
 impl UiRenderTrait for App {
@@ -34,31 +47,41 @@ impl UiRenderTrait for App {
         let songs_view = if self.active_menu_item == MenuItem::Library { Some(self.render_songs()) } else { None };
         let queue_view = if self.active_menu_item == MenuItem::Library { Some(self.render_queue()) } else { None };
         let device_view = if self.active_menu_item == MenuItem::Devices { Some(self.render_devices()) } else { None };
-        let wait_view = match self.player_status {
-            PlayerStatus::Waiting | PlayerStatus::Decompressing | PlayerStatus::Decompressed => Some(self.render_wait()),
-            _ => None,
-        };
+        let device_details_view = if self.active_menu_item == MenuItem::Devices { Some(self.render_device_details()) } else { None };
+        let mixer_view = if self.active_menu_item == MenuItem::Mixer { Some(self.render_mixer()) } else { None };
+        let mixer_details_view = if self.active_menu_item == MenuItem::Mixer { Some(self.render_mixer_details()) } else { None };
+        let practice_time_view = if self.active_menu_item == MenuItem::Insights { Some(self.render_practice_time()) } else { None };
+        let most_practiced_view = if self.active_menu_item == MenuItem::Insights { Some(self.render_most_practiced()) } else { None };
+        let metronome_view = if self.active_menu_item == MenuItem::Metronome { Some(self.render_metronome()) } else { None };
+        let prompt_view = self.render_prompt();
         let footer_view = self.render_footer();
+        let beat_view = self.render_beat();
         let help_view = self.render_help();
+        let banner_view = self.render_banner();
 
+        // the metronome loops, so how far through it is means nothing
         let gauge_view = match &self.playback_status {
-            Some(status) => {
-                if status.track_position.is_some() && status.track_duration.is_some() {
-                    let block = Block::default().borders(Borders::ALL).border_type(BorderType::Rounded).style(Style::default().fg(Color::Gray));
-
-                    let gauge_view = CustomGauge::new(
-                        status.track_position.unwrap().as_secs() as f64,
-                        status.track_duration.unwrap().as_secs() as f64,
-                        Style::default().fg(Color::White).bg(Color::Black).add_modifier(Modifier::BOLD),
-                    )
-                    .block(block);
-
-                    Some(gauge_view)
-                } else {
-                    None
+            Some(PlaybackStatus {
+                track_position: Some(track_position),
+                track_duration: Some(track_duration),
+                ..
+            }) if self.active_stub.is_some() => {
+                let mut block = Block::default().borders(Borders::ALL).border_type(BorderType::Rounded).style(Style::default().fg(Color::Gray));
+                if !self.song_data.notes.is_empty() {
+                    block = block.title(self.song_data.notes.clone());
                 }
+
+                let gauge_view = CustomGauge::new(
+                    track_position.as_secs_f64(),
+                    track_duration.as_secs_f64(),
+                    Style::default().fg(Color::White).bg(Color::Black).add_modifier(Modifier::BOLD),
+                )
+                .block(block)
+                .markers(self.song_data.markers.iter().map(|marker| (marker.position, marker.name.clone())).collect());
+
+                Some(gauge_view)
             }
-            None => None,
+            _ => None,
         };
 
         self.terminal
@@ -71,7 +94,22 @@ impl UiRenderTrait for App {
                 let size = frame.size();
                 let chunks = Layout::default().direction(Direction::Vertical).margin(0).constraints(constraints).split(size);
 
-                if self.is_searching {
+                // the banner takes the top of the main view
+                let body = match banner_view {
+                    Some(banner_view) => {
+                        let banner_chunks = Layout::default()
+                            .direction(Direction::Vertical)
+                            .constraints([Constraint::Length(4), Constraint::Min(3)].as_ref())
+                            .split(chunks[1]);
+                        frame.render_widget(banner_view, banner_chunks[0]);
+                        banner_chunks[1]
+                    }
+                    None => chunks[1],
+                };
+
+                if let Some(prompt_view) = prompt_view {
+                    frame.render_widget(prompt_view, chunks[0]);
+                } else if self.is_searching {
                     frame.render_widget(search_view, chunks[0]);
                 } else {
                     frame.render_widget(menu_view, chunks[0]);
@@ -82,7 +120,7 @@ impl UiRenderTrait for App {
                         let songlist_chunks = Layout::default()
                             .direction(Direction::Horizontal)
                             .constraints([Constraint::Percentage(70), Constraint::Percentage(30)].as_ref())
-                            .split(chunks[1]);
+                            .split(body);
 
                         frame.render_stateful_widget(songs_view.unwrap(), songlist_chunks[0], &mut self.library_state);
 
@@ -91,43 +129,82 @@ impl UiRenderTrait for App {
                         frame.render_stateful_widget(queue_view.unwrap(), songlist_chunks[1], &mut self.queue_state);
                     }
                     MenuItem::Devices => {
-                        frame.render_stateful_widget(device_view.unwrap(), chunks[1], &mut self.device_state);
+                        let device_chunks = Layout::default()
+                            .direction(Direction::Horizontal)
+                            .constraints([Constraint::Percentage(60), Constraint::Percentage(40)].as_ref())
+                            .split(body);
+
+                        frame.render_stateful_widget(device_view.unwrap(), device_chunks[0], &mut self.device_state);
+                        frame.render_widget(device_details_view.unwrap(), device_chunks[1]);
+                    }
+                    MenuItem::Mixer => {
+                        let mixer_chunks = Layout::default()
+                            .direction(Direction::Horizontal)
+                            .constraints([Constraint::Percentage(60), Constraint::Percentage(40)].as_ref())
+                            .split(body);
+
+                        frame.render_widget(mixer_view.unwrap(), mixer_chunks[0]);
+                        frame.render_widget(mixer_details_view.unwrap(), mixer_chunks[1]);
+                    }
+                    MenuItem::Insights => {
+                        let insights_chunks = Layout::default()
+                            .direction(Direction::Horizontal)
+                            .constraints([Constraint::Percentage(40), Constraint::Percentage(60)].as_ref())
+                            .split(body);
+
+                        frame.render_widget(practice_time_view.unwrap(), insights_chunks[0]);
+                        frame.render_widget(most_practiced_view.unwrap(), insights_chunks[1]);
+                    }
+                    MenuItem::Metronome => {
+                        frame.render_widget(metronome_view.unwrap(), body);
                     }
                     MenuItem::Help => {
-                        frame.render_widget(help_view, chunks[1]);
+                        frame.render_widget(help_view, body);
                     }
                 } // end match
 
                 frame.render_widget(footer_view, chunks[2]);
                 if let PlayerStatus::Playing(_) = self.player_status {
+                    let progress_chunks = Layout::default()
+                        .direction(Direction::Horizontal)
+                        .constraints([Constraint::Length(34), Constraint::Min(10)].as_ref())
+                        .split(chunks[3]);
+
+                    if let Some(beat_view) = beat_view {
+                        frame.render_widget(beat_view, progress_chunks[0]);
+                    }
                     if let Some(gauge_view) = gauge_view {
-                        frame.render_widget(gauge_view, chunks[3]);
+                        frame.render_widget(gauge_view, progress_chunks[1]);
                     }
                 }
-
-                if matches!(self.player_status, PlayerStatus::Waiting | PlayerStatus::Decompressing | PlayerStatus::Decompressed) {
-                    let dialog_width = 30;
-                    let dialog_height = 3;
-                    let dialog_x = (size.width - dialog_width) / 2; // Center horizontally
-                    let dialog_y = (size.height - dialog_height) / 2; // Center vertically
-
-                    //let layout = Layout::default().direction(Direction::Vertical).constraints([Constraint::Min(0)].as_ref()).split(size);
-                    frame.render_widget(wait_view.unwrap(), Rect::new(dialog_x, dialog_y, dialog_width, dialog_height));
-                }
             })
             .expect("Unable to draw UI");
     }
 
+    fn render_banner(&mut self) -> Option<Paragraph<'static>> {
+        let banner = self.error_banner.as_ref()?;
+        let block = Block::default().borders(Borders::ALL).border_type(BorderType::Rounded);
+        Some(
+            Paragraph::new(banner.clone())
+                .wrap(Wrap { trim: true })
+                .style(Style::default().fg(Color::Red).add_modifier(Modifier::BOLD))
+                .block(block),
+        )
+    }
+
     fn render_menu(&mut self) -> Tabs<'static> {
-        let menu_titles = ["Songs", "Devices", "Help", "Quit"];
+        // the key is the first letter, except where that was already taken
+        let menu_titles = [("Songs", 0), ("Devices", 0), ("Mixer", 0), ("Insights", 0), ("Rhythm", 2), ("Help", 0), ("Quit", 0)];
 
         let menu = menu_titles
             .iter()
-            .map(|t| {
-                let (first, rest) = t.split_at(1);
+            .map(|(t, key)| {
+                let (before, rest) = t.split_at(*key);
+                let (key, after) = rest.split_at(1);
                 Line::from(vec![
-                    Span::styled(first, Style::default().fg(Color::LightBlue).add_modifier(Modifier::UNDERLINED)),
-                    Span::styled(rest, Style::default().fg(Color::White)),
+                    Span::styled(before, Style::default().fg(Color::White)),
+                    Span::styled(key, Style::default().fg(Color::LightBlue).add_modifier(Modifier::UNDERLINED)),
+                    Span::styled(after, Style::default().fg(Color::White)),
                 ])
             })
             .collect();
@@ -181,8 +258,11 @@ impl UiRenderTrait for App {
 
             let playlist_cell = Cell::from(Span::styled(playlist_position, Style::default().fg(selected_fg)));
 
+            // pinned songs stay in the extraction cache
             let selected_cell = if is_selected {
                 Cell::from(Span::styled("▶".to_string(), Style::default().fg(selected_fg)))
+            } else if self.cache.pinned.contains(&song.file_name) {
+                Cell::from(Span::styled("*".to_string(), Style::default().fg(selected_fg)))
             } else {
                 Cell::from(Span::styled("".to_string(), Style::default().fg(selected_fg)))
             };
@@ -325,11 +405,20 @@ impl UiRenderTrait for App {
             .border_type(BorderType::Plain);
 
         let mut rows = vec![];
-        for (idx, device) in read_devices().into_iter().enumerate() {
-            let is_track = if self.track_device_idx == idx { "Yes" } else { "" };
-            let is_click = if self.click_device_idx == idx { "Yes" } else { "" };
+        for device in read_devices().into_iter() {
+            let outputs = self
+                .outputs
+                .iter()
+                .filter(|output| output.device_name.as_ref() == Some(&device.name))
+                .map(|output| output.name.clone())
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            let preference = self.device_preferences.get(&device.name).cloned().unwrap_or_default();
+            let sample_rate = preference.sample_rate.map(|rate| rate.to_string()).unwrap_or_else(|| "Auto".to_string());
+            let buffer_size = preference.buffer_size.map(|size| size.to_string()).unwrap_or_else(|| "Auto".to_string());
 
-            let row = Row::new(vec![Cell::from(is_track), Cell::from(is_click), Cell::from(device.name.clone())]);
+            let row = Row::new(vec![Cell::from(outputs), Cell::from(device.name.clone()), Cell::from(sample_rate), Cell::from(buffer_size)]);
             rows.push(row);
         }
 
@@ -339,89 +428,424 @@ impl UiRenderTrait for App {
             .block(device_ui)
             .highlight_style(highlight_style)
             .header(Row::new(vec![
-                Cell::from(Span::styled("Track?", Style::default().add_modifier(Modifier::BOLD))),
-                Cell::from(Span::styled("Click?", Style::default().add_modifier(Modifier::BOLD))),
+                Cell::from(Span::styled("Outputs", Style::default().add_modifier(Modifier::BOLD))),
                 Cell::from(Span::styled("Device", Style::default().add_modifier(Modifier::BOLD))),
+                Cell::from(Span::styled("Rate", Style::default().add_modifier(Modifier::BOLD))),
+                Cell::from(Span::styled("Buffer", Style::default().add_modifier(Modifier::BOLD))),
             ]))
-            .widths(&[Constraint::Length(10), Constraint::Length(10), Constraint::Percentage(45)]);
+            .widths(&[Constraint::Length(16), Constraint::Percentage(50), Constraint::Length(7), Constraint::Length(7)]);
 
         device_table
     }
 
-    fn render_footer(&mut self) -> Paragraph<'static> {
-        let mut status = vec![Span::styled(self.player_status.as_string(), Style::default().fg(Color::LightBlue))];
+    fn render_device_details(&mut self) -> Paragraph<'static> {
+        let header_style = Style::default().add_modifier(Modifier::BOLD).fg(Color::Yellow);
+        let key_style = Style::default().fg(Color::LightCyan);
 
-        match self.player_status {
-            PlayerStatus::Playing(_) => {
-                if self.active_stub.is_some() {
-                    let stub = self.active_stub.as_ref().unwrap();
-                    status.push(Span::raw(": "));
-                    status.push(Span::styled(stub.title.clone(), Style::default().add_modifier(Modifier::UNDERLINED)));
-                    status.push(Span::raw(" by "));
-                    status.push(Span::styled(stub.artist.clone(), Style::default().add_modifier(Modifier::UNDERLINED)));
+        let mut lines = vec![Line::from(vec![Span::styled("Host: ", header_style), Span::raw(current_host_name())])];
+        if is_jack_host() {
+            let ports = self.outputs.iter().map(|output| format!("{}_L/R", output.port_name())).collect::<Vec<_>>().join(", ");
+            lines.push(Line::from(format!("Ports: {}, connected as configured", ports)));
+        }
+        lines.push(Line::from("\n"));
+
+        lines.push(Line::from(Span::styled("Outputs", header_style)));
+        for (idx, output) in self.outputs.iter().enumerate() {
+            let marker = if idx == self.selected_output { "▶ " } else { "  " };
+            lines.push(Line::from(format!(
+                "{}{}: {}, channels {}",
+                marker,
+                output.name,
+                output.device_name.as_deref().unwrap_or("default"),
+                output.describe_channels()
+            )));
+        }
+        lines.push(Line::from("\n"));
+
+        match read_devices().get(self.device_state.selected().unwrap_or(0)) {
+            Some(device) => {
+                lines.push(Line::from(Span::styled(device.name.clone(), header_style)));
+                lines.push(Line::from("\n"));
+                lines.push(Line::from(Span::styled("Supported configurations", header_style)));
+                if device.configs.is_empty() {
+                    lines.push(Line::from("None reported"));
                 }
-            }
-            PlayerStatus::Paused => {
-                if self.active_stub.is_some() {
-                    let stub = self.active_stub.as_ref().unwrap();
-                    status.push(Span::raw(": "));
-                    status.push(Span::styled(stub.title.clone(), Style::default().add_modifier(Modifier::UNDERLINED)));
-                    status.push(Span::raw(" by "));
-                    status.push(Span::styled(stub.artist.clone(), Style::default().add_modifier(Modifier::UNDERLINED)));
+                for config in device.configs.iter() {
+                    lines.push(Line::from(config.describe()));
                 }
+                lines.push(Line::from("\n"));
+                lines.push(Line::from(vec![
+                    Span::styled("Test channel: ", header_style),
+                    Span::raw(format!("{} of {}", self.test_channel + 1, device.max_channels())),
+                ]));
+            }
+            None => lines.push(Line::from("No device selected")),
+        }
+
+        lines.push(Line::from("\n"));
+        lines.push(Line::from(vec![Span::styled("Left/Right", key_style), Span::raw(": Pick the channel to test")]));
+        lines.push(Line::from(vec![Span::styled("b or o", key_style), Span::raw(": Play the beep or a test tone on that channel")]));
+        lines.push(Line::from(vec![Span::styled("k or f", key_style), Span::raw(": Cycle the preferred sample rate or buffer size")]));
+        lines.push(Line::from(vec![Span::styled("TAB", key_style), Span::raw(": Pick the output to change")]));
+        lines.push(Line::from(vec![Span::styled("ENTER", key_style), Span::raw(": Send that output to the selected device")]));
+        lines.push(Line::from(vec![Span::styled("l", key_style), Span::raw(": Cycle the device channels the output uses")]));
+        lines.push(Line::from(vec![Span::styled("+ or DELETE", key_style), Span::raw(": Add an output or remove the selected one")]));
+        lines.push(Line::from(vec![Span::styled("r", key_style), Span::raw(": Rescan devices")]));
+        lines.push(Line::from(vec![Span::styled("a", key_style), Span::raw(": Switch the audio host API")]));
+
+        Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title("Details").border_type(BorderType::Plain))
+    }
+
+    fn render_mixer(&mut self) -> Table<'static> {
+        let title = match self.active_preset.as_ref() {
+            Some(name) => format!("Mixer ({})", name),
+            None => "Mixer (custom)".to_string(),
+        };
+        let mixer_ui = Block::default()
+            .borders(Borders::ALL)
+            .style(Style::default().fg(Color::White))
+            .title(title)
+            .border_type(BorderType::Plain);
+
+        let selected_style = Style::default().bg(Color::Yellow).fg(Color::Black).add_modifier(Modifier::BOLD);
+        let muted_style = Style::default().fg(Color::Rgb(60, 60, 60));
+
+        let mut rows = vec![];
+        for (output_idx, output) in self.outputs.iter().enumerate() {
+            let mut cells = vec![Cell::from(Span::styled(output.name.clone(), Style::default().add_modifier(Modifier::BOLD)))];
+            for (source_idx, source) in MixSource::ALL.iter().enumerate() {
+                let cell = self.mix.cell(*source, &output.name);
+                let style = if source_idx == self.mixer_source && output_idx == self.mixer_output {
+                    selected_style
+                } else if cell.is_audible() {
+                    Style::default()
+                } else {
+                    muted_style
+                };
+                cells.push(Cell::from(Span::styled(cell.describe(), style)));
             }
-            _ => {}
+            rows.push(Row::new(cells));
+        }
+
+        let mut header = vec![Cell::from(Span::styled("Output", Style::default().add_modifier(Modifier::BOLD)))];
+        for source in MixSource::ALL.iter() {
+            header.push(Cell::from(Span::styled(source.label(), Style::default().add_modifier(Modifier::BOLD))));
+        }
+
+        Table::new(rows).block(mixer_ui).header(Row::new(header)).widths(&[Constraint::Length(16); MixSource::ALL.len() + 1])
+    }
+
+    fn render_mixer_details(&mut self) -> Paragraph<'static> {
+        let header_style = Style::default().add_modifier(Modifier::BOLD).fg(Color::Yellow);
+        let key_style = Style::default().fg(Color::LightCyan);
+
+        let mut lines = vec![Line::from(Span::styled("Presets", header_style))];
+        if self.mix_presets.is_empty() {
+            lines.push(Line::from("None saved"));
+        }
+        for name in self.mix_presets.keys() {
+            let marker = if self.active_preset.as_ref() == Some(name) { "▶ " } else { "  " };
+            lines.push(Line::from(format!("{}{}", marker, name)));
+        }
+
+        lines.push(Line::from("\n"));
+        lines.push(Line::from(vec![Span::styled("Arrows", key_style), Span::raw(": Pick an output and a source")]));
+        lines.push(Line::from(vec![Span::styled("+ or -", key_style), Span::raw(": Raise or lower the gain")]));
+        lines.push(Line::from(vec![Span::styled("[ or ]", key_style), Span::raw(": Pan left or right")]));
+        lines.push(Line::from(vec![Span::styled("m", key_style), Span::raw(": Mute or unmute")]));
+        lines.push(Line::from(vec![Span::styled("0", key_style), Span::raw(": Reset to full gain, centered")]));
+        lines.push(Line::from(vec![Span::styled("w", key_style), Span::raw(": Save the mix as a preset")]));
+        lines.push(Line::from(vec![Span::styled("p", key_style), Span::raw(": Load the next preset")]));
+        lines.push(Line::from(vec![Span::styled("DELETE", key_style), Span::raw(": Delete the active preset")]));
+
+        Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title("Details").border_type(BorderType::Plain))
+    }
+
+    // Practice time per day and per week, as bars scaled to the busiest, and the streaks
+    fn render_practice_time(&mut self) -> Paragraph<'static> {
+        const BAR_WIDTH: usize = 20;
+        let header_style = Style::default().add_modifier(Modifier::BOLD).fg(Color::Yellow);
+        let key_style = Style::default().fg(Color::LightCyan);
+        let today = Local::now().date_naive();
+
+        let bar = |played: Duration, most: Duration| {
+            let width = if most.is_zero() {
+                0
+            } else {
+                (played.as_secs_f64() / most.as_secs_f64() * BAR_WIDTH as f64).round() as usize
+            };
+            Span::styled(format!("{:<width$}", "█".repeat(width), width = BAR_WIDTH), Style::default().fg(Color::LightGreen))
+        };
+
+        let mut lines = vec![Line::from(Span::styled("Last 7 days", header_style))];
+        let days = self.history.daily_totals(today, 7);
+        let most = days.iter().map(|(_, played)| *played).max().unwrap_or_default();
+        for (day, played) in days {
+            lines.push(Line::from(vec![
+                Span::raw(format!("{:<11}", day.format("%a %d %b"))),
+                bar(played, most),
+                Span::raw(format!(" {}", practice_time(played))),
+            ]));
+        }
+
+        lines.push(Line::from("\n"));
+        lines.push(Line::from(Span::styled("Last 4 weeks", header_style)));
+        let weeks = self.history.weekly_totals(today, 4);
+        let most = weeks.iter().map(|(_, played)| *played).max().unwrap_or_default();
+        for (monday, played) in weeks {
+            lines.push(Line::from(vec![
+                Span::raw(format!("{:<11}", monday.format("%d %b"))),
+                bar(played, most),
+                Span::raw(format!(" {}", practice_time(played))),
+            ]));
+        }
+
+        let (current, longest) = self.history.streaks(today);
+        lines.push(Line::from("\n"));
+        lines.push(Line::from(Span::styled("Streaks", header_style)));
+        lines.push(Line::from(format!("Current: {} day{}", current, if current == 1 { "" } else { "s" })));
+        lines.push(Line::from(format!("Longest: {} day{}", longest, if longest == 1 { "" } else { "s" })));
+
+        lines.push(Line::from("\n"));
+        lines.push(Line::from(vec![Span::styled("c or j", key_style), Span::raw(": Export every play as CSV or JSON")]));
+
+        Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title("Practice time").border_type(BorderType::Plain))
+    }
+
+    // The songs we've spent the most time on, with the speed of the last few days they were played
+    fn render_most_practiced(&mut self) -> Table<'static> {
+        const SPEED_DAYS: usize = 5;
+        let rows = self
+            .history
+            .most_practiced(self.page_size.max(10))
+            .into_iter()
+            .map(|song| {
+                let speeds = song
+                    .speeds
+                    .iter()
+                    .skip(song.speeds.len().saturating_sub(SPEED_DAYS))
+                    .map(|(_, speed)| format!("{:.0}%", speed * 100.0))
+                    .collect::<Vec<_>>();
+                Row::new(vec![
+                    Cell::from(song.title),
+                    Cell::from(song.artist),
+                    Cell::from(practice_time(song.played)),
+                    Cell::from(song.plays.to_string()),
+                    Cell::from(speeds.join(" → ")),
+                ])
+            })
+            .collect::<Vec<_>>();
+
+        let header = ["Song", "Artist", "Time", "Plays", "Speed"]
+            .iter()
+            .map(|title| Cell::from(Span::styled(*title, Style::default().add_modifier(Modifier::BOLD))))
+            .collect::<Vec<_>>();
+
+        Table::new(rows)
+            .block(Block::default().borders(Borders::ALL).title("Most practiced").border_type(BorderType::Plain))
+            .header(Row::new(header))
+            .widths(&[
+                Constraint::Percentage(30),
+                Constraint::Percentage(20),
+                Constraint::Length(8),
+                Constraint::Length(6),
+                Constraint::Min(20),
+            ])
+    }
+
+    // The metronome's settings, its beats with the one playing lit up, the timer and the keys
+    fn render_metronome(&mut self) -> Paragraph<'static> {
+        let header_style = Style::default().add_modifier(Modifier::BOLD).fg(Color::Yellow);
+        let key_style = Style::default().fg(Color::LightCyan);
+        let metronome = &self.metronome;
+        let current_beat = match (&self.metronome_started, &self.playback_status) {
+            (Some(_), Some(status)) => status.bar_beat.map(|bar_beat| bar_beat.beat),
+            _ => None,
+        };
+
+        let mut beats = vec![Span::raw("Beats:        ")];
+        for beat in 1..=metronome.beats_per_bar {
+            let symbol = if metronome.is_accented(beat) { "● " } else { "○ " };
+            let style = if current_beat == Some(beat) {
+                Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)
+            } else {
+                Style::default().fg(Color::Gray)
+            };
+            beats.push(Span::styled(symbol, style));
+        }
+
+        let timer = match (self.metronome_started, metronome.timer()) {
+            (Some(started), Some(timer)) => format!("{} min, {} left", timer.as_secs() / 60, minutes_seconds(timer.saturating_sub(started.elapsed()))),
+            (Some(started), None) => format!("off, {} so far", minutes_seconds(started.elapsed())),
+            (None, Some(timer)) => format!("{} min", timer.as_secs() / 60),
+            (None, None) => "off".to_string(),
+        };
+
+        let mut lines = vec![
+            Line::from(Span::styled(if self.metronome_started.is_some() { "Playing" } else { "Stopped" }, header_style)),
+            Line::from(format!("Tempo:        {} BPM", metronome.bpm)),
+            Line::from(format!("Time:         {}", metronome.time_signature())),
+            Line::from(format!("Subdivision:  {}", metronome.subdivision_name())),
+            Line::from(format!(
+                "Polyrhythm:   {}",
+                metronome.polyrhythm.map(|(notes, beats)| format!("{} against {}", notes, beats)).unwrap_or_else(|| "none".to_string())
+            )),
+            Line::from(beats),
+            Line::from(format!("Timer:        {}", timer)),
+            Line::from("\n"),
+            Line::from(Span::styled("Keys", header_style)),
+        ];
+
+        let keys = [
+            ("SPACE or ENTER", "Start or stop the click. It plays on the click's outputs, at the click's levels in the mixer."),
+            ("Up or Down Arrow", "Change the tempo by 1 BPM. Right and Left change it by 5."),
+            ("t", "Tap the tempo."),
+            ("b or n", "Change the beats in a bar, or whether they are quarters or eighths."),
+            ("1 to 9", "Accent a beat, or stop accenting it."),
+            ("u", "Subdivide the beats into eighths, triplets or sixteenths."),
+            ("p", "Play a polyrhythm on top: 3 against 2, 4 against 3 and so on."),
+            ("c", "Set the timer. The click stops when it runs out."),
+        ];
+        for (key, description) in keys {
+            lines.push(Line::from(vec![Span::styled(key, key_style), Span::raw(format!(": {}", description))]));
+        }
+
+        Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title("Metronome").border_type(BorderType::Plain))
+    }
+
+    fn render_prompt(&mut self) -> Option<Paragraph<'static>> {
+        let prompt = self.prompt.as_ref()?;
+        let items = vec![Span::styled(prompt.kind.label(), Style::default().fg(Color::LightBlue)), Span::raw(prompt.input.clone())];
+
+        Some(Paragraph::new(Line::from(items)).block(Block::default().borders(Borders::ALL).border_type(BorderType::Rounded)))
+    }
+
+    fn render_footer(&mut self) -> Paragraph<'static> {
+        let mut status = vec![Span::styled(self.player_status.as_string(), Style::default().fg(Color::LightBlue))];
+
+        if let (PlayerStatus::Playing(_) | PlayerStatus::Paused, Some(stub)) = (&self.player_status, &self.active_stub) {
+            status.push(Span::raw(": "));
+            status.push(Span::styled(stub.title.clone(), Style::default().add_modifier(Modifier::UNDERLINED)));
+            status.push(Span::raw(" by "));
+            status.push(Span::styled(stub.artist.clone(), Style::default().add_modifier(Modifier::UNDERLINED)));
         }
 
         status.push(Span::raw(" | "));
-        status.push(Span::styled("Track Volume: ", Style::default().fg(Color::LightBlue)));
-        status.push(Span::raw(self.track_volume.to_string()));
+        status.push(Span::styled("Outputs: ", Style::default().fg(Color::LightBlue)));
+        status.push(Span::raw(self.outputs.len().to_string()));
+        if let Some(levels) = self.playback_status.as_ref().map(|status| &status.levels).filter(|levels| !levels.is_empty()) {
+            for (output, peak) in levels.iter() {
+                status.push(Span::raw(format!(" {} ", output)));
+                status.push(Span::styled(level_meter(*peak), Style::default().fg(if *peak >= 1.0 { Color::Red } else { Color::LightGreen })));
+            }
+        }
         status.push(Span::raw(" | "));
-        status.push(Span::styled(" Click Volume: ", Style::default().fg(Color::LightBlue)));
-        status.push(Span::raw(self.click_volume.to_string()));
+        status.push(Span::styled(" Mix: ", Style::default().fg(Color::LightBlue)));
+        status.push(Span::raw(self.active_preset.clone().unwrap_or_else(|| "custom".to_string())));
         status.push(Span::raw(" | "));
-        status.push(Span::styled(" Bleed Volume: ", Style::default().fg(Color::LightBlue)));
-        status.push(Span::raw(self.bleed_volume.to_string()));
+        status.push(Span::styled(" Speed: ", Style::default().fg(Color::LightBlue)));
+        let speed = self.playback_status.as_ref().map(|status| status.speed).unwrap_or(1.0);
+        let bpm = self.active_stub.as_ref().map(|stub| stub.bpm).unwrap_or(0);
+        if bpm > 0 {
+            status.push(Span::raw(format!("{:.0}% ({:.0} BPM)", speed * 100.0, speed * bpm as f64)));
+        } else {
+            status.push(Span::raw(format!("{:.0}%", speed * 100.0)));
+        }
         status.push(Span::raw(" | "));
         status.push(Span::styled(" Repeat: ", Style::default().fg(Color::LightBlue)));
         status.push(Span::raw(if self.is_repeating { "On" } else { "Off" }));
 
-        let spans = Line::from(status);
+        if let Some(region) = self.loop_region {
+            status.push(Span::raw(" | "));
+            status.push(Span::styled(" Loop: ", Style::default().fg(Color::LightBlue)));
+            status.push(Span::raw(format!("{}-{}", minutes_seconds(region.start()), minutes_seconds(region.end()))));
+        } else if let Some(start) = self.loop_start {
+            status.push(Span::raw(" | "));
+            status.push(Span::styled(" Loop: ", Style::default().fg(Color::LightBlue)));
+            status.push(Span::raw(format!("{}-?", minutes_seconds(start))));
+        }
 
-        Paragraph::new(spans).block(Block::default().borders(Borders::ALL).border_type(BorderType::Rounded))
-    }
+        if let Some((stub, progress)) = &self.loading {
+            status.push(Span::raw(" | "));
+            status.push(Span::styled(" Loading: ", Style::default().fg(Color::LightBlue)));
+            status.push(Span::raw(format!("{} ({})", stub.title, progress.describe())));
+        }
 
-    fn render_gauge(&mut self) -> LineGauge<'static> {
-        let start_color = (0, 255, 0);
-        let end_color = (255, 0, 0);
+        if let Some(job) = &self.batch {
+            status.push(Span::raw(" | "));
+            status.push(Span::styled(format!(" {}: ", job.format.describe()), Style::default().fg(Color::LightBlue)));
+            match &job.progress {
+                _ if job.is_cancelled() => status.push(Span::raw("stopping")),
+                Some(progress) => status.push(Span::raw(format!("{}/{} ({})", progress.done, progress.total, progress.title))),
+                None => status.push(Span::raw("starting")),
+            }
+        }
 
-        let track_position = self
-            .playback_status
-            .as_ref()
-            .and_then(|status| status.track_position) // If PlaybackStatus exists, get track_position
-            .unwrap_or(Duration::from_secs(0)); // If it is None, default to 0 seconds
+        if self.count_in_bars > 0 {
+            status.push(Span::raw(" | "));
+            status.push(Span::styled(" Count-in: ", Style::default().fg(Color::LightBlue)));
+            status.push(Span::raw(format!("{} bar{}", self.count_in_bars, if self.count_in_bars == 1 { "" } else { "s" })));
+        }
 
-        let track_duration = self
-            .playback_status
-            .as_ref()
-            .and_then(|status| status.track_duration) // If PlaybackStatus exists, get track_duration
-            .unwrap_or(Duration::from_secs(0)); // If it is None, default to 0 seconds
+        if let Some(run) = &self.routine {
+            let song_remaining = match &self.playback_status {
+                Some(PlaybackStatus {
+                    track_position: Some(position),
+                    track_duration: Some(duration),
+                    speed,
+                    ..
+                }) => duration.saturating_sub(*position).div_f64(speed.max(0.1)),
+                _ => Duration::ZERO,
+            };
+            let step = run.current_step().map(|step| step.describe()).unwrap_or_default();
+            status.push(Span::raw(" | "));
+            status.push(Span::styled(
+                format!(" {} {}/{}: ", run.routine.name, run.step + 1, run.routine.steps.len()),
+                Style::default().fg(Color::LightBlue),
+            ));
+            status.push(Span::raw(format!("{} ({} left)", step, minutes_seconds(run.step_remaining(song_remaining)))));
+        }
 
-        // Calculate the progress ratio
-        let progress_ratio = track_position.as_secs_f64() / track_duration.as_secs_f64();
+        if let Some((_, title)) = &self.pending_rating {
+            status.push(Span::raw(" | "));
+            status.push(Span::styled(format!(" How did {} go? ", title), Style::default().fg(Color::LightBlue)));
+            status.push(Span::raw("F1 nailed it, F2 okay, F3 struggled"));
+        }
 
-        let color = lerp_color(start_color, end_color, progress_ratio);
+        if let Some(notice) = &self.notice {
+            status.push(Span::raw(" | "));
+            status.push(Span::styled(notice.clone(), Style::default().fg(Color::LightGreen)));
+        }
 
-        let gauge = LineGauge::default()
-            .gauge_style(Style::default().fg(color).bg(Color::White).add_modifier(Modifier::BOLD))
-            .line_set(symbols::line::THICK);
-        //.ratio(progress_ratio)
+        if let Some(warning) = &self.device_warning {
+            status.push(Span::raw(" | "));
+            status.push(Span::styled(warning.clone(), Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)));
+        }
 
-        if track_duration.as_secs() > 0 {
-            gauge.ratio(progress_ratio)
-        } else {
-            gauge
+        let spans = Line::from(status);
+
+        Paragraph::new(spans).block(Block::default().borders(Borders::ALL).border_type(BorderType::Rounded))
+    }
+
+    // One block per beat of the bar. The current one lights up at the start of each beat, brighter on the downbeat.
+    fn render_beat(&mut self) -> Option<Paragraph<'static>> {
+        let bar_beat = self.playback_status.as_ref()?.bar_beat?;
+        let is_flashing = bar_beat.phase < 0.3;
+
+        let block = if bar_beat.beats_per_bar > 8 { "█ " } else { "██ " }; // odd meters still fit the box
+        let mut blocks = vec![];
+        for beat in 1..=bar_beat.beats_per_bar {
+            let style = match (beat == bar_beat.beat, bar_beat.is_downbeat()) {
+                (true, true) if is_flashing => Style::default().fg(Color::Red),
+                (true, false) if is_flashing => Style::default().fg(Color::Green),
+                (true, _) => Style::default().fg(Color::Gray),
+                (false, _) => Style::default().fg(Color::Rgb(60, 60, 60)),
+            };
+            blocks.push(Span::styled(block, style));
         }
+
+        let title = format!("Bar {} | Beat {} | {:.0} BPM", bar_beat.bar, bar_beat.beat, bar_beat.bpm);
+        Some(Paragraph::new(vec![Line::from(blocks.clone()), Line::from(blocks)]).block(Block::default().borders(Borders::ALL).border_type(BorderType::Rounded).title(title)))
     }
 
     fn render_search(&mut self) -> Paragraph<'static> {
@@ -439,9 +863,21 @@ impl UiRenderTrait for App {
             Line::from(vec![Span::styled("q", Style::default().fg(Color::LightCyan)), Span::raw(": Quit (boo!).")]),
             Line::from(vec![
                 Span::styled("d", Style::default().fg(Color::LightCyan)),
-                Span::raw(": Show device selection. You can pick output devices for tracks and clicks separately."),
+                Span::raw(": Show device selection. Add named outputs and pick a device and channels for each. Press r there to rescan devices."),
             ]),
             Line::from(vec![Span::styled("s", Style::default().fg(Color::LightCyan)), Span::raw(": Show the song list.")]),
+            Line::from(vec![
+                Span::styled("m", Style::default().fg(Color::LightCyan)),
+                Span::raw(": Show the mixer. Every source can be sent to every output with its own gain, mute and pan."),
+            ]),
+            Line::from(vec![
+                Span::styled("i", Style::default().fg(Color::LightCyan)),
+                Span::raw(": Show how much you've practiced, your most practiced songs and your streaks."),
+            ]),
+            Line::from(vec![
+                Span::styled("y", Style::default().fg(Color::LightCyan)),
+                Span::raw(": Show the metronome, for practicing with just a click. Its keys are listed there."),
+            ]),
             Line::from(vec![Span::styled("h", Style::default().fg(Color::LightCyan)), Span::raw(": Show this help screen.")]),
             Line::from("\n"),
             Line::from(Span::styled("Song list Commands", header_style)),
@@ -451,15 +887,74 @@ impl UiRenderTrait for App {
             ]),
             Line::from(vec![
                 Span::styled("Left or Right Arrow", Style::default().fg(Color::LightCyan)),
-                Span::raw(": Move forward or backward in the current song."),
+                Span::raw(": Move back or forward a bar in the current song (10 seconds if the bars are unknown)."),
+            ]),
+            Line::from(vec![
+                Span::styled("k or u", Style::default().fg(Color::LightCyan)),
+                Span::raw(": Add a section marker (intro, verse, chorus ...) here, or remove the one you're in."),
+            ]),
+            Line::from(vec![Span::styled("[ or ]", Style::default().fg(Color::LightCyan)), Span::raw(": Jump to the previous or next marker.")]),
+            Line::from(vec![Span::styled("1 to 9", Style::default().fg(Color::LightCyan)), Span::raw(": Jump to the first to ninth marker.")]),
+            Line::from(vec![
+                Span::styled("j", Style::default().fg(Color::LightCyan)),
+                Span::raw(": Go to a time, a bar or a percentage of the song, e.g. 2:35, bar 48 or 75%."),
             ]),
             Line::from(vec![
                 Span::styled("Shift Right or Shift Left Arrow", Style::default().fg(Color::LightCyan)),
-                Span::raw(": Speed up or slow down the playback."),
+                Span::raw(": Speed up or slow down the playback by 10%. Alt and the arrows change it by 1%."),
+            ]),
+            Line::from(vec![
+                Span::styled("t", Style::default().fg(Color::LightCyan)),
+                Span::raw(": Play at a target BPM. The speed is worked out from the song's BPM."),
             ]),
             Line::from(vec![Span::styled("r", Style::default().fg(Color::LightCyan)), Span::raw(": Reset the playback speed.")]),
             Line::from(vec![Span::styled("a", Style::default().fg(Color::LightCyan)), Span::raw(": Enable or disable repeat.")]),
             Line::from(vec![Span::styled("z", Style::default().fg(Color::LightCyan)), Span::raw(": Restart the current song.")]),
+            Line::from(vec![
+                Span::styled("l", Style::default().fg(Color::LightCyan)),
+                Span::raw(": Set the start of a loop, then its end. Press again to stop looping."),
+            ]),
+            Line::from(vec![
+                Span::styled("c", Style::default().fg(Color::LightCyan)),
+                Span::raw(": Count in with no, one or two bars of click before the song starts."),
+            ]),
+            Line::from(vec![Span::styled("o", Style::default().fg(Color::LightCyan)), Span::raw(": Edit the notes for the current song.")]),
+            Line::from(vec![
+                Span::styled("v", Style::default().fg(Color::LightCyan)),
+                Span::raw(": Replace the queue with today's reviews: the songs due again, then a few new ones from the song list."),
+            ]),
+            Line::from(vec![
+                Span::styled("f", Style::default().fg(Color::LightCyan)),
+                Span::raw(": Pin the selected song, marked *, so it is never evicted from the extraction cache. Press again to unpin it."),
+            ]),
+            Line::from(vec![
+                Span::styled("b", Style::default().fg(Color::LightCyan)),
+                Span::raw(": Clear the extraction cache. Pinned songs and songs being extracted stay."),
+            ]),
+            Line::from(vec![
+                Span::styled("F5", Style::default().fg(Color::LightCyan)),
+                Span::raw(": Check the library for songs that can't be played, then offer to extract broken songs again and to ignore the rest."),
+            ]),
+            Line::from(vec![
+                Span::styled("F6 or F7", Style::default().fg(Color::LightCyan)),
+                Span::raw(": Extract, or convert to FLAC, the queue, the list as shown, all songs or a genre ahead of time. Press again to stop."),
+            ]),
+            Line::from(vec![
+                Span::styled("F1, F2 or F3", Style::default().fg(Color::LightCyan)),
+                Span::raw(": Rate the last song played as nailed it, okay or struggled. Unrated songs count as okay."),
+            ]),
+            Line::from(vec![
+                Span::styled("p", Style::default().fg(Color::LightCyan)),
+                Span::raw(": Start a practice routine from the routines folder next to the configuration, or stop the one running."),
+            ]),
+            Line::from(vec![
+                Span::styled("w", Style::default().fg(Color::LightCyan)),
+                Span::raw(": Save the speed, mix, loop and count-in as the current song's own. They come back whenever it is played."),
+            ]),
+            Line::from(vec![
+                Span::styled("e", Style::default().fg(Color::LightCyan)),
+                Span::raw(": Export the tempo map of the current song as a MIDI file next to it."),
+            ]),
             Line::from(vec![
                 Span::styled("SPACE", Style::default().fg(Color::LightCyan)),
                 Span::raw(": Pause or continue the song that is playing"),
@@ -469,9 +964,18 @@ impl UiRenderTrait for App {
                 Span::raw(": Move to the first or last song in the queue or song list."),
             ]),
             Line::from(vec![Span::styled("x", Style::default().fg(Color::LightCyan)), Span::raw(": Shuffle or un-shuffle the playlist")]),
-            Line::from(vec![Span::styled("1 or 4", Style::default().fg(Color::LightCyan)), Span::raw(": Lower the track or click volume")]),
-            Line::from(vec![Span::styled("2 or 5", Style::default().fg(Color::LightCyan)), Span::raw(": Reset the track or click volume")]),
-            Line::from(vec![Span::styled("3 or 6", Style::default().fg(Color::LightCyan)), Span::raw(": Increase the track or click volume")]),
+            Line::from(vec![
+                Span::styled("Alt 1, 4 or 7", Style::default().fg(Color::LightCyan)),
+                Span::raw(": Lower the track on the first output, the click or the track on the second"),
+            ]),
+            Line::from(vec![
+                Span::styled("Alt 2, 5 or 8", Style::default().fg(Color::LightCyan)),
+                Span::raw(": Reset the track on the first output, the click or the track on the second"),
+            ]),
+            Line::from(vec![
+                Span::styled("Alt 3, 6 or 9", Style::default().fg(Color::LightCyan)),
+                Span::raw(": Increase the track on the first output, the click or the track on the second"),
+            ]),
             Line::from(vec![
                 Span::styled("g", Style::default().fg(Color::LightCyan)),
                 Span::raw(": start filtering for a specific song or artist."),
@@ -491,28 +995,25 @@ impl UiRenderTrait for App {
         // Create a Paragraph with the help screen content
         Paragraph::new(help_content.clone()).style(Style::default()).block(Block::default().borders(Borders::ALL).title("Help"))
     }
-
-    fn render_wait(&mut self) -> Paragraph<'static> {
-        let dialog = Block::default().borders(Borders::ALL).style(Style::default().fg(Color::White).bg(Color::Blue));
-
-        let text = Text::styled("Please wait...              ", Style::default().fg(Color::White));
-
-        Paragraph::new(text).block(dialog)
-    }
 }
 
-// Function to perform linear interpolation (lerp) for colors
-fn lerp_color(start_color: (u8, u8, u8), end_color: (u8, u8, u8), t: f64) -> Color {
-    let (start_r, start_g, start_b) = start_color;
-    let (end_r, end_g, end_b) = end_color;
-
-    let r = lerp(start_r, end_r, t);
-    let g = lerp(start_g, end_g, t);
-    let b = lerp(start_b, end_b, t);
+fn minutes_seconds(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
 
-    Color::Rgb(r, g, b)
+// Eight steps of 6 dB, so the quietest step is -48 dBFS
+fn level_meter(peak: f32) -> String {
+    let db = 20.0 * peak.max(1e-6).log10();
+    let steps = ((db + 48.0) / 6.0).clamp(0.0, 8.0).round() as usize;
+    format!("{}{}", "▮".repeat(steps), "▯".repeat(8 - steps))
 }
 
-fn lerp(start: u8, end: u8, t: f64) -> u8 {
-    (start as f64 + (end as f64 - start as f64) * t) as u8
+fn practice_time(duration: Duration) -> String {
+    let minutes = duration.as_secs() / 60;
+    if minutes >= 60 {
+        format!("{}h {:02}m", minutes / 60, minutes % 60)
+    } else {
+        format!("{}m", minutes)
+    }
 }
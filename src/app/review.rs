@@ -0,0 +1,338 @@
+// Spaced repetition for songs. Every play counts as a review, rated okay unless we say how it went.
+// Songs we struggle with come back the next day, the ones we nail wait longer each time and
+// mastered songs only come back every couple of months. New songs are brought in a few a day.
+// Kept in reviews.json beside the configuration.
+use std::{collections::BTreeMap, fs, path::PathBuf};
+
+use chrono::{Days, NaiveDate};
+use color_eyre::eyre::Result;
+use log::warn;
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+
+use drum_weaver::library::SongRecord;
+
+pub const DEFAULT_NEW_SONGS_PER_DAY: usize = 2;
+pub const DEFAULT_DAILY_REVIEWS: usize = 10;
+pub const MIN_REVIEW_PLAY: f64 = 30.0; // seconds. Anything shorter was just skipping through.
+
+const STARTING_EASE: f64 = 2.5; // how much longer the wait gets after nailing a song
+const MIN_EASE: f64 = 1.3;
+const MAX_EASE: f64 = 3.0;
+const MASTERED_DAYS: f64 = 60.0;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum Rating {
+    NailedIt,
+    Okay,
+    Struggled,
+}
+
+impl Rating {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Rating::NailedIt => "nailed it",
+            Rating::Okay => "okay",
+            Rating::Struggled => "struggled",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+struct CardState {
+    interval: f64, // days
+    ease: f64,
+    due: NaiveDate,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ReviewCard {
+    introduced: NaiveDate,
+    last_reviewed: NaiveDate,
+    reviews: usize,
+    last_rating: Rating,
+    state: CardState,
+    previous: Option<CardState>, // before the last review, so it can be rated again
+}
+
+impl ReviewCard {
+    pub fn is_mastered(&self) -> bool {
+        self.state.interval >= MASTERED_DAYS
+    }
+
+    // Playing a song again the same day only replaces that day's rating, so a routine of repeats doesn't push it far away
+    fn rate(&mut self, rating: Rating, today: NaiveDate) {
+        if self.last_reviewed == today {
+            if let Some(previous) = self.previous.take() {
+                self.state = previous;
+                self.reviews -= 1;
+            }
+        }
+
+        let CardState { interval, ease, .. } = self.state;
+        let (interval, ease) = match rating {
+            Rating::Struggled => (1.0, (ease - 0.2).max(MIN_EASE)),
+            Rating::Okay => ((interval * 1.2).max(1.0), ease),
+            Rating::NailedIt if self.reviews == 0 => (3.0, (ease + 0.1).min(MAX_EASE)),
+            Rating::NailedIt => ((interval * ease).max(2.0), (ease + 0.1).min(MAX_EASE)),
+        };
+
+        self.previous = Some(self.state);
+        self.state = CardState {
+            interval,
+            ease,
+            due: today.checked_add_days(Days::new(interval.round() as u64)).unwrap_or(today),
+        };
+        self.reviews += 1;
+        self.last_rating = rating;
+        self.last_reviewed = today;
+    }
+}
+
+// What a daily queue is made of
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct DailyQueue {
+    pub songs: Vec<SongRecord>,
+    pub due: usize,
+    pub new: usize,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ReviewSchedule {
+    cards: BTreeMap<String, ReviewCard>, // by file name
+}
+
+impl ReviewSchedule {
+    fn path() -> Result<PathBuf> {
+        let config_path = confy::get_configuration_file_path("drum-weaver", None)?;
+        Ok(config_path.with_file_name("reviews.json"))
+    }
+
+    pub fn load() -> ReviewSchedule {
+        let path = match Self::path() {
+            Ok(path) if path.exists() => path,
+            _ => return ReviewSchedule::default(),
+        };
+
+        match fs::read_to_string(&path)
+            .map_err(|err| err.to_string())
+            .and_then(|contents| serde_json::from_str(&contents).map_err(|err| err.to_string()))
+        {
+            Ok(schedule) => schedule,
+            Err(err) => {
+                warn!("Unable to read {}: {}", path.display(), err);
+                ReviewSchedule::default()
+            }
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        fs::write(Self::path()?, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn review(&mut self, file_name: &str, rating: Rating, today: NaiveDate) {
+        let card = self.cards.entry(file_name.to_string()).or_insert_with(|| ReviewCard {
+            introduced: today,
+            last_reviewed: today,
+            reviews: 0,
+            last_rating: rating,
+            state: CardState {
+                interval: 0.0,
+                ease: STARTING_EASE,
+                due: today,
+            },
+            previous: None,
+        });
+        card.rate(rating, today);
+    }
+
+    pub fn mastered(&self) -> usize {
+        self.cards.values().filter(|card| card.is_mastered()).count()
+    }
+
+    // The songs due today, the ones we struggled with and the most overdue first, then new songs from the candidates.
+    // Songs introduced today count against the new ones.
+    pub fn daily_queue(&self, today: NaiveDate, library: &[SongRecord], candidates: &[SongRecord], daily_reviews: usize, new_per_day: usize) -> DailyQueue {
+        let mut due = self.cards.iter().filter(|(_, card)| card.state.due <= today).collect::<Vec<_>>();
+        due.sort_by_key(|(_, card)| (card.last_rating != Rating::Struggled, card.state.due));
+
+        let mut songs = due
+            .iter()
+            .filter_map(|(file_name, _)| library.iter().find(|song| song.file_name == **file_name).cloned())
+            .take(daily_reviews)
+            .collect::<Vec<_>>();
+        let due = songs.len();
+
+        let introduced_today = self.cards.values().filter(|card| card.introduced == today).count();
+        let unseen = candidates.iter().filter(|song| !self.cards.contains_key(&song.file_name)).collect::<Vec<_>>();
+        let new = unseen
+            .choose_multiple(&mut rand::thread_rng(), new_per_day.saturating_sub(introduced_today))
+            .map(|song| (*song).clone())
+            .collect::<Vec<_>>();
+        let new_count = new.len();
+        songs.extend(new);
+
+        DailyQueue { songs, due, new: new_count }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(offset: u64) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 1, 1).unwrap().checked_add_days(Days::new(offset)).unwrap()
+    }
+
+    fn song(file_name: &str) -> SongRecord {
+        SongRecord {
+            file_name: file_name.to_string(),
+            genre: "Rock".to_string(),
+            year: "1999".to_string(),
+            artist: "Artist".to_string(),
+            title: file_name.to_string(),
+            album: "Album".to_string(),
+            length: "3:30".to_string(),
+            bpm: 120,
+            folder: "Rock".to_string(),
+        }
+    }
+
+    fn state(interval: f64, ease: f64, due: NaiveDate) -> CardState {
+        CardState { interval, ease, due }
+    }
+
+    fn file_names(songs: &[SongRecord]) -> Vec<&str> {
+        songs.iter().map(|song| song.file_name.as_str()).collect()
+    }
+
+    fn card<'a>(schedule: &'a ReviewSchedule, file_name: &str) -> &'a ReviewCard {
+        &schedule.cards[file_name]
+    }
+
+    #[test]
+    fn first_ratings() {
+        let mut schedule = ReviewSchedule::default();
+        schedule.review("nailed", Rating::NailedIt, day(0));
+        schedule.review("okay", Rating::Okay, day(0));
+        schedule.review("struggled", Rating::Struggled, day(0));
+
+        assert_eq!(card(&schedule, "nailed").state, state(3.0, 2.6, day(3)));
+        assert_eq!(card(&schedule, "okay").state, state(1.0, 2.5, day(1)));
+        assert_eq!(card(&schedule, "struggled").state, state(1.0, 2.3, day(1)));
+    }
+
+    #[test]
+    fn intervals_and_ease_grow_with_each_nailed_review() {
+        let mut schedule = ReviewSchedule::default();
+        schedule.review("song", Rating::NailedIt, day(0));
+        schedule.review("song", Rating::NailedIt, day(3));
+        let nailed = card(&schedule, "song").state;
+        assert!((nailed.interval - 7.8).abs() < 1e-9);
+        assert!((nailed.ease - 2.7).abs() < 1e-9);
+        assert_eq!(nailed.due, day(11));
+
+        schedule.review("song", Rating::Okay, day(11));
+        let okay = card(&schedule, "song").state;
+        assert!((okay.interval - 9.36).abs() < 1e-9);
+        assert!((okay.ease - 2.7).abs() < 1e-9);
+
+        // struggling starts over, and the ease never drops below its floor
+        for offset in 12..20 {
+            schedule.review("song", Rating::Struggled, day(offset));
+        }
+        assert_eq!(card(&schedule, "song").state, state(1.0, MIN_EASE, day(20)));
+        assert_eq!(card(&schedule, "song").reviews, 11);
+    }
+
+    #[test]
+    fn rating_again_the_same_day_replaces_the_rating() {
+        let mut schedule = ReviewSchedule::default();
+        schedule.review("song", Rating::NailedIt, day(0));
+        schedule.review("song", Rating::NailedIt, day(3));
+        let before = card(&schedule, "song").clone();
+
+        // a routine plays it three times on day 8
+        schedule.review("song", Rating::NailedIt, day(8));
+        schedule.review("song", Rating::NailedIt, day(8));
+        schedule.review("song", Rating::Struggled, day(8));
+        let after = card(&schedule, "song");
+        assert_eq!(after.reviews, before.reviews + 1);
+        assert_eq!(after.last_rating, Rating::Struggled);
+        assert_eq!(after.previous, Some(before.state));
+        assert_eq!(after.state, state(1.0, before.state.ease - 0.2, day(9)));
+
+        // a new card rated twice on its first day is still on its first review
+        schedule.review("new", Rating::Struggled, day(8));
+        schedule.review("new", Rating::NailedIt, day(8));
+        assert_eq!(card(&schedule, "new").reviews, 1);
+        assert_eq!(card(&schedule, "new").state, state(3.0, 2.6, day(11)));
+    }
+
+    #[test]
+    fn nailed_songs_become_mastered() {
+        let mut schedule = ReviewSchedule::default();
+        let mut today = day(0);
+        let mut reviews = 0;
+        while !card_is_mastered(&schedule, "song") {
+            schedule.review("song", Rating::NailedIt, today);
+            today = card(&schedule, "song").state.due;
+            reviews += 1;
+        }
+        assert_eq!(reviews, 5); // 3, 7.8, 21.06, 58.97 and then 171 days
+        assert!(card(&schedule, "song").state.interval >= MASTERED_DAYS);
+        assert_eq!(schedule.mastered(), 1);
+
+        schedule.review("song", Rating::Struggled, today);
+        assert_eq!(schedule.mastered(), 0);
+    }
+
+    fn card_is_mastered(schedule: &ReviewSchedule, file_name: &str) -> bool {
+        schedule.cards.get(file_name).is_some_and(|card| card.is_mastered())
+    }
+
+    #[test]
+    fn struggled_songs_come_first_then_the_most_overdue() {
+        let mut schedule = ReviewSchedule::default();
+        schedule.review("overdue", Rating::Okay, day(0)); // due day 1
+        schedule.review("due", Rating::NailedIt, day(0)); // due day 3
+        schedule.review("struggled", Rating::Struggled, day(2)); // due day 3
+        schedule.review("later", Rating::NailedIt, day(2)); // due day 5
+        schedule.review("gone", Rating::Okay, day(0)); // no longer in the library
+        let library = ["overdue", "due", "struggled", "later"].map(song);
+
+        let queue = schedule.daily_queue(day(3), &library, &[], 10, 0);
+        assert_eq!(file_names(&queue.songs), ["struggled", "overdue", "due"]);
+        assert_eq!((queue.due, queue.new), (3, 0));
+
+        let queue = schedule.daily_queue(day(3), &library, &[], 2, 0);
+        assert_eq!(file_names(&queue.songs), ["struggled", "overdue"]);
+    }
+
+    #[test]
+    fn new_songs_count_the_ones_introduced_today() {
+        let mut schedule = ReviewSchedule::default();
+        schedule.review("old", Rating::Okay, day(0));
+        let candidates = ["old", "a", "b", "c", "d"].map(song);
+
+        // nothing introduced yet today, so the full quota of unseen songs
+        let queue = schedule.daily_queue(day(5), &candidates, &candidates, 10, 3);
+        assert_eq!((queue.due, queue.new), (1, 3));
+        assert_eq!(file_names(&queue.songs)[0], "old");
+        assert!(file_names(&queue.songs)[1..].iter().all(|file_name| ["a", "b", "c", "d"].contains(file_name)));
+
+        // one new song was played today, so one less comes in
+        schedule.review("a", Rating::Okay, day(5));
+        let queue = schedule.daily_queue(day(5), &candidates, &candidates, 10, 3);
+        assert_eq!(queue.new, 2);
+        assert!(!file_names(&queue.songs).contains(&"a"));
+
+        // and none once the quota is used up, or when every candidate has been seen
+        schedule.review("b", Rating::Okay, day(5));
+        schedule.review("c", Rating::Okay, day(5));
+        assert_eq!(schedule.daily_queue(day(5), &candidates, &candidates, 10, 3).new, 0);
+        schedule.review("d", Rating::Okay, day(6));
+        assert_eq!(schedule.daily_queue(day(7), &candidates, &candidates, 10, 3).new, 0);
+    }
+}
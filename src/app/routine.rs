@@ -0,0 +1,454 @@
+// Practice routines: a plan of steps that drives the player, e.g. a click-only warm-up, then a song
+// played a few times while the speed climbs, then a few random songs of a genre.
+// Each routine is a TOML or JSON file in the routines folder beside the configuration.
+use std::{
+    fs,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Local};
+use color_eyre::eyre::{eyre, Result};
+use log::{info, warn};
+use rand::seq::SliceRandom;
+use serde::Deserialize;
+
+use drum_weaver::{beats::DEFAULT_BEATS_PER_BAR, history::RoutineRecord, library::SongRecord};
+
+// Written the first time the routines folder is created, as a starting point
+const EXAMPLE_ROUTINE: &str = r#"# Steps run in order. Speeds are percentages of the original.
+name = "Example"
+
+[[steps]]
+kind = "click"
+minutes = 5
+bpm = 90
+
+[[steps]]
+kind = "song"
+song = "Lycanthrope"
+plays = 3
+start_speed = 80
+end_speed = 100
+
+[[steps]]
+kind = "random"
+count = 3
+genre = "rock"
+min_bpm = 120
+max_bpm = 140
+"#;
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct Routine {
+    #[serde(default)]
+    pub name: String, // the file name when missing
+    pub steps: Vec<RoutineStep>,
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RoutineStep {
+    // Only the click, for as long as given
+    Click {
+        minutes: f64,
+        bpm: usize,
+        #[serde(default = "default_beats_per_bar")]
+        beats_per_bar: usize,
+    },
+    // One song, matched by title or file name, played a number of times with the speed stepping up between plays
+    Song {
+        song: String,
+        #[serde(default = "default_plays")]
+        plays: usize,
+        #[serde(default = "default_speed")]
+        start_speed: f64, // percent
+        #[serde(default = "default_speed")]
+        end_speed: f64,
+    },
+    // Songs picked at random from those that match
+    Random {
+        count: usize,
+        genre: Option<String>,
+        min_bpm: Option<usize>,
+        max_bpm: Option<usize>,
+    },
+}
+
+fn default_beats_per_bar() -> usize {
+    DEFAULT_BEATS_PER_BAR
+}
+
+fn default_plays() -> usize {
+    1
+}
+
+fn default_speed() -> f64 {
+    100.0
+}
+
+impl RoutineStep {
+    pub fn describe(&self) -> String {
+        match self {
+            RoutineStep::Click { minutes, bpm, .. } => format!("{} min of click at {} BPM", minutes, bpm),
+            RoutineStep::Song { song, plays, start_speed, end_speed } if start_speed != end_speed => format!("{} x{} from {:.0}% to {:.0}%", song, plays, start_speed, end_speed),
+            RoutineStep::Song { song, plays, start_speed, .. } => format!("{} x{} at {:.0}%", song, plays, start_speed),
+            RoutineStep::Random { count, genre, min_bpm, max_bpm } => {
+                let mut description = format!("{} random", count);
+                if let Some(genre) = genre {
+                    description.push_str(&format!(" {}", genre));
+                }
+                description.push_str(" songs");
+                match (min_bpm, max_bpm) {
+                    (Some(min), Some(max)) => description.push_str(&format!(" between {} and {} BPM", min, max)),
+                    (Some(min), None) => description.push_str(&format!(" over {} BPM", min)),
+                    (None, Some(max)) => description.push_str(&format!(" under {} BPM", max)),
+                    (None, None) => {}
+                }
+                description
+            }
+        }
+    }
+}
+
+impl Routine {
+    fn folder() -> Result<PathBuf> {
+        let config_path = confy::get_configuration_file_path("drum-weaver", None)?;
+        let folder = config_path.with_file_name("routines");
+        if !folder.exists() {
+            fs::create_dir_all(&folder)?;
+            fs::write(folder.join("example.toml"), EXAMPLE_ROUTINE)?;
+        }
+        Ok(folder)
+    }
+
+    // Every routine we can read, by name. The ones we can't are logged and left out.
+    pub fn load_all() -> Result<Vec<Routine>> {
+        let mut routines = vec![];
+        for entry in fs::read_dir(Self::folder()?)? {
+            let path = entry?.path();
+            match Self::load(&path) {
+                Ok(Some(routine)) => routines.push(routine),
+                Ok(None) => {} // not a routine
+                Err(err) => warn!("Unable to read the routine {}: {}", path.display(), err),
+            }
+        }
+        routines.sort_by_key(|routine| routine.name.to_lowercase());
+        Ok(routines)
+    }
+
+    fn load(path: &PathBuf) -> Result<Option<Routine>> {
+        let contents = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("toml") | Some("json") => fs::read_to_string(path)?,
+            _ => return Ok(None),
+        };
+        let mut routine: Routine = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("toml") => toml::from_str(&contents)?,
+            _ => serde_json::from_str(&contents)?,
+        };
+
+        if routine.name.is_empty() {
+            routine.name = path.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+        }
+        if routine.steps.is_empty() {
+            return Err(eyre!("{} has no steps", routine.name));
+        }
+        Ok(Some(routine))
+    }
+
+    pub fn find(name: &str) -> Result<Routine> {
+        Self::load_all()?
+            .into_iter()
+            .find(|routine| routine.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| eyre!("There is no routine called {}", name))
+    }
+}
+
+// What the app should do next for the routine
+#[derive(Debug, Clone, PartialEq)]
+pub enum RoutineAction {
+    PlayClick(usize, usize), // bpm, beats per bar. Runs until the step is over.
+    Play(SongRecord, f64),   // at this speed, 1.0 being the original
+    Finished,
+}
+
+// A routine being worked through
+pub struct RoutineRun {
+    pub routine: Routine,
+    pub step: usize,
+    started: DateTime<Local>,
+    step_started: Instant,
+    songs: Vec<SongRecord>, // what the current step plays, in order
+    plays: usize,           // of those, how many have been played
+}
+
+impl RoutineRun {
+    pub fn start(routine: Routine, library: &[SongRecord]) -> (RoutineRun, RoutineAction) {
+        info!("Starting the routine {}", routine.name);
+        let mut run = RoutineRun {
+            routine,
+            step: 0,
+            started: Local::now(),
+            step_started: Instant::now(),
+            songs: vec![],
+            plays: 0,
+        };
+        let action = run.begin_step(library);
+        (run, action)
+    }
+
+    pub fn current_step(&self) -> Option<&RoutineStep> {
+        self.routine.steps.get(self.step)
+    }
+
+    // Steps that can't be played, e.g. a song that isn't in the library, are skipped
+    fn begin_step(&mut self, library: &[SongRecord]) -> RoutineAction {
+        self.step_started = Instant::now();
+        self.plays = 0;
+
+        while let Some(step) = self.current_step().cloned() {
+            match step {
+                RoutineStep::Click { bpm, beats_per_bar, .. } => {
+                    self.songs = vec![];
+                    return RoutineAction::PlayClick(bpm, beats_per_bar);
+                }
+                RoutineStep::Song { song, plays, .. } => {
+                    let wanted = song.to_lowercase();
+                    match library.iter().find(|record| record.title.to_lowercase() == wanted || record.file_name.to_lowercase() == wanted) {
+                        Some(record) => self.songs = vec![record.clone(); plays.max(1)],
+                        None => warn!("Skipping step {} of {}: there is no song called {}", self.step + 1, self.routine.name, song),
+                    }
+                }
+                RoutineStep::Random { count, genre, min_bpm, max_bpm } => {
+                    let genre = genre.map(|genre| genre.to_lowercase());
+                    let matches = library
+                        .iter()
+                        .filter(|record| genre.as_ref().is_none_or(|genre| record.genre.to_lowercase().contains(genre)))
+                        .filter(|record| min_bpm.is_none_or(|min| record.bpm >= min) && max_bpm.is_none_or(|max| record.bpm <= max))
+                        .collect::<Vec<_>>();
+                    self.songs = matches.choose_multiple(&mut rand::thread_rng(), count).map(|record| (*record).clone()).collect();
+                    if self.songs.is_empty() {
+                        warn!("Skipping step {} of {}: no songs match", self.step + 1, self.routine.name);
+                    }
+                }
+            }
+
+            if let Some(action) = self.next_song() {
+                return action;
+            }
+            self.step += 1;
+        }
+
+        RoutineAction::Finished
+    }
+
+    fn next_song(&self) -> Option<RoutineAction> {
+        let song = self.songs.get(self.plays)?.clone();
+        Some(RoutineAction::Play(song, self.speed_of(self.plays)))
+    }
+
+    // Song steps climb evenly from the start speed to the end speed over their plays
+    fn speed_of(&self, play: usize) -> f64 {
+        match self.current_step() {
+            Some(RoutineStep::Song { plays, start_speed, end_speed, .. }) => {
+                let progress = if *plays > 1 { play as f64 / (*plays - 1) as f64 } else { 0.0 };
+                (start_speed + (end_speed - start_speed) * progress) / 100.0
+            }
+            _ => 1.0,
+        }
+    }
+
+    // Called when a song the routine started has finished
+    pub fn song_ended(&mut self, library: &[SongRecord]) -> Option<RoutineAction> {
+        if matches!(self.current_step(), Some(RoutineStep::Click { .. }) | None) {
+            return None;
+        }
+
+        self.plays += 1;
+        if let Some(action) = self.next_song() {
+            return Some(action);
+        }
+        self.step += 1;
+        Some(self.begin_step(library))
+    }
+
+    // Called regularly. Moves on once a click step has run its time.
+    pub fn tick(&mut self, library: &[SongRecord]) -> Option<RoutineAction> {
+        match self.current_step() {
+            Some(RoutineStep::Click { .. }) if self.step_remaining(Duration::ZERO).is_zero() => {
+                self.step += 1;
+                Some(self.begin_step(library))
+            }
+            _ => None,
+        }
+    }
+
+    // Roughly how long the current step has left, given what's left of the song playing now
+    pub fn step_remaining(&self, song_remaining: Duration) -> Duration {
+        match self.current_step() {
+            Some(RoutineStep::Click { minutes, .. }) => Duration::from_secs_f64(minutes.max(0.0) * 60.0).saturating_sub(self.step_started.elapsed()),
+            Some(_) => {
+                let later = (self.plays + 1..self.songs.len())
+                    .filter_map(|play| self.songs[play].duration().map(|duration| duration.div_f64(self.speed_of(play).max(0.1))))
+                    .sum::<Duration>();
+                song_remaining + later
+            }
+            None => Duration::ZERO,
+        }
+    }
+
+    pub fn record(&self, finished: bool) -> RoutineRecord {
+        RoutineRecord {
+            name: self.routine.name.clone(),
+            started: self.started,
+            ended: Local::now(),
+            steps: self.routine.steps.len(),
+            completed_steps: self.step.min(self.routine.steps.len()),
+            finished,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn song(title: &str, genre: &str, bpm: usize) -> SongRecord {
+        SongRecord {
+            file_name: format!("{}.7z", title.to_lowercase()),
+            genre: genre.to_string(),
+            year: "1999".to_string(),
+            artist: "Artist".to_string(),
+            title: title.to_string(),
+            album: "Album".to_string(),
+            length: "3:00".to_string(),
+            bpm,
+            folder: genre.to_string(),
+        }
+    }
+
+    fn library() -> Vec<SongRecord> {
+        vec![song("Lycanthrope", "Rock", 130), song("Ballad", "Pop", 70), song("Stomp", "Rock", 125)]
+    }
+
+    fn routine(steps: Vec<RoutineStep>) -> Routine {
+        Routine { name: "Test".to_string(), steps }
+    }
+
+    fn click(minutes: f64) -> RoutineStep {
+        RoutineStep::Click { minutes, bpm: 90, beats_per_bar: 4 }
+    }
+
+    fn lycanthrope(plays: usize) -> RoutineStep {
+        RoutineStep::Song {
+            song: "lycanthrope".to_string(),
+            plays,
+            start_speed: 80.0,
+            end_speed: 100.0,
+        }
+    }
+
+    fn played(action: Option<RoutineAction>) -> (String, f64) {
+        match action {
+            Some(RoutineAction::Play(song, speed)) => (song.title, speed),
+            other => panic!("expected a song, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn click_steps_run_until_their_time_is_up() {
+        let library = library();
+        let (mut run, action) = RoutineRun::start(routine(vec![click(1.0), lycanthrope(1)]), &library);
+        assert_eq!(action, RoutineAction::PlayClick(90, 4));
+
+        assert_eq!(run.tick(&library), None);
+        assert!(run.step_remaining(Duration::ZERO) > Duration::from_secs(59));
+        // songs ending during a click step are none of its business
+        assert_eq!(run.song_ended(&library), None);
+        assert_eq!(run.step, 0);
+
+        run.step_started = Instant::now() - Duration::from_secs(61);
+        assert_eq!(run.step_remaining(Duration::ZERO), Duration::ZERO);
+        assert_eq!(played(run.tick(&library)), ("Lycanthrope".to_string(), 0.8));
+        assert_eq!(run.step, 1);
+    }
+
+    #[test]
+    fn song_steps_climb_in_speed_and_move_on_when_the_last_play_ends() {
+        let library = library();
+        let (mut run, action) = RoutineRun::start(routine(vec![lycanthrope(3), click(5.0)]), &library);
+        assert_eq!(played(Some(action)), ("Lycanthrope".to_string(), 0.8));
+        // two more plays of three minutes, at 90% and 100%
+        let later = Duration::from_secs(180).div_f64(0.9) + Duration::from_secs(180);
+        assert_eq!(run.step_remaining(Duration::from_secs(60)), Duration::from_secs(60) + later);
+
+        // ticks only move click steps along
+        assert_eq!(run.tick(&library), None);
+        assert_eq!(played(run.song_ended(&library)), ("Lycanthrope".to_string(), 0.9));
+        assert_eq!(played(run.song_ended(&library)), ("Lycanthrope".to_string(), 1.0));
+        assert_eq!(run.step_remaining(Duration::from_secs(60)), Duration::from_secs(60));
+        assert_eq!(run.song_ended(&library), Some(RoutineAction::PlayClick(90, 4)));
+        assert_eq!(run.step, 1);
+    }
+
+    #[test]
+    fn random_steps_pick_from_the_matching_songs() {
+        let library = library();
+        let step = RoutineStep::Random {
+            count: 5,
+            genre: Some("rock".to_string()),
+            min_bpm: Some(120),
+            max_bpm: None,
+        };
+        let (mut run, action) = RoutineRun::start(routine(vec![step]), &library);
+
+        let mut titles = vec![played(Some(action)), played(run.song_ended(&library))]
+            .into_iter()
+            .map(|(title, speed)| {
+                assert_eq!(speed, 1.0);
+                title
+            })
+            .collect::<Vec<_>>();
+        titles.sort();
+        assert_eq!(titles, vec!["Lycanthrope", "Stomp"]);
+        assert_eq!(run.song_ended(&library), Some(RoutineAction::Finished));
+    }
+
+    #[test]
+    fn steps_that_cannot_play_are_skipped() {
+        let library = library();
+        let missing = RoutineStep::Song {
+            song: "Nowhere".to_string(),
+            plays: 2,
+            start_speed: 100.0,
+            end_speed: 100.0,
+        };
+        let nothing = RoutineStep::Random {
+            count: 2,
+            genre: Some("jazz".to_string()),
+            min_bpm: None,
+            max_bpm: None,
+        };
+        let (run, action) = RoutineRun::start(routine(vec![missing, nothing, lycanthrope(1)]), &library);
+        assert_eq!(played(Some(action)), ("Lycanthrope".to_string(), 0.8));
+        assert_eq!(run.step, 2);
+    }
+
+    #[test]
+    fn finishing_completes_every_step() {
+        let library = library();
+        let (mut run, _) = RoutineRun::start(routine(vec![lycanthrope(1), click(0.0)]), &library);
+        assert_eq!(run.record(false).completed_steps, 0);
+
+        assert_eq!(run.song_ended(&library), Some(RoutineAction::PlayClick(90, 4)));
+        assert_eq!(run.tick(&library), Some(RoutineAction::Finished));
+        assert_eq!(run.current_step(), None);
+        assert_eq!(run.step_remaining(Duration::from_secs(60)), Duration::ZERO);
+        // nothing more happens once the routine is over
+        assert_eq!(run.tick(&library), None);
+        assert_eq!(run.song_ended(&library), None);
+
+        let record = run.record(true);
+        assert_eq!((record.steps, record.completed_steps, record.finished), (2, 2, true));
+    }
+}
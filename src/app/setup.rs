@@ -1,13 +1,13 @@
 use std::{
     sync::{Arc, Mutex},
     thread,
-    time::{Duration, Instant},
+    time::Duration,
 };
 
 use crossterm::event::{self, Event as CrosstermEvent, MouseButton, MouseEventKind};
 use log::info;
 
-use crate::app::library::Library;
+use drum_weaver::library::Library;
 
 use super::{App, InputEvent, UiEvent};
 
@@ -19,9 +19,8 @@ pub trait UiSetupTrait {
 
 impl UiSetupTrait for App {
     // We intercept ctrl+c and send a quit event to ensure clean shutdown
-    // We set up a thread to handle UI events
+    // We set up a thread to handle UI events. It sleeps until there is one; ticks come from the run loop.
     fn setup_ui_signal_loop(&mut self) {
-        let tick_rate = Duration::from_millis(500);
         let ui_command_sender = Arc::new(Mutex::new(self.ui_command_sender.clone()));
 
         // clear the event queue
@@ -32,34 +31,27 @@ impl UiSetupTrait for App {
 
         // create our transmit-receive loop
         thread::spawn(move || {
-            let mut last_tick = Instant::now();
-
             loop {
-                let timeout = tick_rate.checked_sub(last_tick.elapsed()).unwrap_or_else(|| Duration::from_secs(1));
+                let event = event::read().expect("can read events");
                 let ui_command_sender = ui_command_sender.lock().unwrap();
 
-                if event::poll(timeout).expect("Polling works") {
-                    match event::read().expect("can read events") {
-                        CrosstermEvent::Key(key) => {
-                            //if key.kind == KeyEventKind::Release {
-                                info!("Key released: {:?}", key);
-                                ui_command_sender.send(UiEvent::Input(InputEvent::Key(key))).expect("can send events");
-                            //} else {
-                            //    info!("Key not released: {:?}", key);
-                            //}
-                        }
-                        CrosstermEvent::Mouse(me) => {
-                            if me.kind == MouseEventKind::Up(MouseButton::Left) {
-                                ui_command_sender.send(UiEvent::Input(InputEvent::Mouse(me))).expect("can send events");
-                            }
-                        }
-                        // handle other types of events if needed
-                        _ => {}
+                match event {
+                    CrosstermEvent::Key(key) => {
+                        //if key.kind == KeyEventKind::Release {
+                            info!("Key released: {:?}", key);
+                            ui_command_sender.send(UiEvent::Input(InputEvent::Key(key))).expect("can send events");
+                        //} else {
+                        //    info!("Key not released: {:?}", key);
+                        //}
                     }
-                }
-
-                if last_tick.elapsed() >= tick_rate && ui_command_sender.send(UiEvent::Input(InputEvent::Tick)).is_ok() {
-                    last_tick = Instant::now();
+                    CrosstermEvent::Mouse(me) if me.kind == MouseEventKind::Up(MouseButton::Left) => {
+                        ui_command_sender.send(UiEvent::Input(InputEvent::Mouse(me))).expect("can send events");
+                    }
+                    CrosstermEvent::Resize(..) => {
+                        ui_command_sender.send(UiEvent::Input(InputEvent::Resize)).expect("can send events");
+                    }
+                    // handle other types of events if needed
+                    _ => {}
                 }
             }
         });
@@ -8,11 +8,18 @@ pub struct CustomGauge<'a> {
     max_value: f64,
     style: Style,
     block: Option<Block<'a>>,
+    markers: Vec<(f64, String)>, // value and name
 }
 
 impl<'a> CustomGauge<'a> {
     pub fn new(value: f64, max_value: f64, style: Style) -> CustomGauge<'a> {
-        CustomGauge { value, max_value, style, block: None }
+        CustomGauge {
+            value,
+            max_value,
+            style,
+            block: None,
+            markers: Vec::new(),
+        }
     }
 }
 
@@ -45,6 +52,14 @@ impl<'a> Widget for CustomGauge<'a> {
             buf.get_mut(x, gauge_area.top() + 1).set_symbol("█").set_style(style);
         }
 
+        // each marker shows as the first letter of its name, over the bar
+        let marker_style = Style::default().fg(Color::Black).bg(Color::Yellow);
+        for (value, name) in self.markers.iter() {
+            let x = gauge_area.left() + ((gauge_area.width as f64) * (value / self.max_value)).min(gauge_area.width as f64 - 1.0) as u16;
+            let initial = name.chars().next().map(|c| c.to_uppercase().to_string()).unwrap_or_else(|| "|".to_string());
+            buf.get_mut(x, gauge_area.top()).set_symbol(&initial).set_style(marker_style);
+        }
+
         // let pos_x = (area.width / 2) as u16;
         // let pos_y = area.top();
 
@@ -63,6 +78,11 @@ impl<'a> CustomGauge<'a> {
         self.block = Some(block);
         self
     }
+
+    pub fn markers(mut self, markers: Vec<(f64, String)>) -> Self {
+        self.markers = markers;
+        self
+    }
 }
 
 // Function to perform linear interpolation (lerp) for colors
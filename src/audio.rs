@@ -1,9 +1,12 @@
+//! Decoding songs into memory and playing them on an output device, through cpal or, with the `jack` feature, JACK.
+//! An [`AudioPlayer`] plays a current [`Song`] and queues the next, at any speed, on any of the device's channels.
 #![allow(dead_code)]
 // this is player-rs. As soon as i get permission on gitlab to fork, I'll make the change there.
 // The modification allows a device to be passed in the player allowing different tracks on different
 // output devices.
 use std::collections::VecDeque;
 use std::num::Wrapping;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::{self, Receiver, SyncSender, TryRecvError};
 use std::sync::{Arc, Mutex, RwLock};
 use std::thread;
@@ -11,7 +14,7 @@ use std::time::Duration;
 
 use color_eyre::eyre::{ensure, Report, Result};
 use cpal::traits::{DeviceTrait, StreamTrait};
-use cpal::{Device, FrameCount, FromSample, OutputCallbackInfo, Sample, SampleFormat, SizedSample, Stream, StreamConfig, SupportedBufferSize, SupportedStreamConfigRange};
+use cpal::{BufferSize, Device, FrameCount, FromSample, OutputCallbackInfo, Sample, SampleFormat, SizedSample, Stream, StreamConfig, SupportedBufferSize, SupportedStreamConfigRange};
 use log::{debug, error, info, warn};
 use rubato::{InterpolationParameters, InterpolationType, Resampler, SincFixedOut, WindowFunction};
 use symphonia::core::audio::SampleBuffer;
@@ -57,8 +60,12 @@ struct DecodingSong {
 }
 
 const MAXIMUM_SPEED_ADJUSTMENT_FACTOR: f64 = 2.0;
-const MINIMUM_PLAYBACK_SPEED: f64 = 1.0 / MAXIMUM_SPEED_ADJUSTMENT_FACTOR;
-const MAXIMUM_PLAYBACK_SPEED: f64 = 1.0 * MAXIMUM_SPEED_ADJUSTMENT_FACTOR;
+// The most frames a callback is expected to ask for when the device doesn't say
+const MIX_FRAMES: u32 = 8192;
+/// The slowest a song can play, as a multiple of its original speed
+pub const MINIMUM_PLAYBACK_SPEED: f64 = 1.0 / MAXIMUM_SPEED_ADJUSTMENT_FACTOR;
+/// The fastest a song can play, as a multiple of its original speed
+pub const MAXIMUM_PLAYBACK_SPEED: f64 = 1.0 * MAXIMUM_SPEED_ADJUSTMENT_FACTOR;
 
 impl DecodingSong {
     fn new(song: &Song, initial_pos: Duration, player_sample_rate: usize, player_channel_count: usize, expected_buffer_size: usize, initial_playback_speed: f64) -> Result<DecodingSong> {
@@ -306,7 +313,9 @@ impl DecodingSong {
     }
 }
 
-type PlaybackState = (DecodingSong, Duration);
+// One decoding song per voice, each with its own position. Every voice follows the same transport
+// and the first one decides the position and when playback is done.
+type PlaybackState = Vec<(DecodingSong, Duration)>;
 
 #[derive(Clone)]
 struct PlayerState {
@@ -318,6 +327,11 @@ struct PlayerState {
     buffer_size: u32,
     volume_adjustment: Arc<RwLock<f32>>,
     playback_speed: Arc<RwLock<f64>>,
+    channel_gains: Arc<RwLock<Vec<f32>>>,
+    voice_gains: Arc<RwLock<Vec<Vec<f32>>>>,
+    channel_map: Arc<RwLock<Vec<Option<usize>>>>,
+    peak: Arc<RwLock<f32>>,    // the loudest sample written since the level was last read
+    mix: Arc<Mutex<Vec<f32>>>, // where the voices are mixed, sized ahead so the audio callback doesn't allocate
 }
 
 impl PlayerState {
@@ -331,9 +345,21 @@ impl PlayerState {
             buffer_size,
             volume_adjustment: Arc::new(RwLock::new(1.0)),
             playback_speed: Arc::new(RwLock::new(1.0)),
+            channel_gains: Arc::new(RwLock::new(Vec::new())),
+            voice_gains: Arc::new(RwLock::new(Vec::new())),
+            channel_map: Arc::new(RwLock::new(Vec::new())),
+            peak: Arc::new(RwLock::new(0.0)),
+            mix: Arc::new(Mutex::new(Vec::new())),
         })
     }
-    fn write_samples<T>(&self, data: &mut [T], _info: &OutputCallbackInfo)
+    // Makes room to mix a callback of this many samples. A larger one than expected grows it in the callback, once.
+    fn reserve_mix(&self, samples: usize) {
+        let mut mix = self.mix.lock().unwrap();
+        if mix.len() < samples {
+            mix.resize(samples, 0.0);
+        }
+    }
+    fn write_samples<T>(&self, data: &mut [T])
     where
         T: Sample + FromSample<f32>,
     {
@@ -343,37 +369,75 @@ impl PlayerState {
         if *self.playing.read().unwrap() {
             let playback_speed = *self.playback_speed.read().unwrap();
             let volume_adjustment = *self.volume_adjustment.read().unwrap();
+            let channel_gains = self.channel_gains.read().unwrap();
+            let voice_gains = self.voice_gains.read().unwrap();
+            let channel_map = self.channel_map.read().unwrap();
             let mut playback = self.playback.write().unwrap();
             if playback.is_none() {
-                if let Some((new_samples, new_pos)) = self.next_samples.write().unwrap().take() {
-                    *playback = Some((new_samples, new_pos));
+                if let Some(new_voices) = self.next_samples.write().unwrap().take() {
+                    *playback = Some(new_voices);
                 }
             }
             let mut done = false;
-            if let Some((decoding_song, sample_pos)) = playback.as_mut() {
-                let mut neg_offset = 0;
+            let mut peak = 0.0f32;
+            if let Some(voices) = playback.as_mut() {
                 let data_len = data.len();
-                let (mut samples, mut new_pos, mut is_final) = decoding_song.read_samples(*sample_pos, data_len, playback_speed, volume_adjustment);
-                for (i, sample) in data.iter_mut().enumerate() {
-                    if i >= samples.len() {
-                        if let Some((next_samples, next_pos)) = self.next_samples.write().unwrap().take() {
-                            *decoding_song = next_samples;
-                            neg_offset = i;
-                            *sample_pos = next_pos;
-                            (samples, new_pos, is_final) = decoding_song.read_samples(*sample_pos, data_len - neg_offset, playback_speed, volume_adjustment);
-                        } else {
-                            break;
-                        }
-                    }
-                    *sample = T::from_sample(samples[i - neg_offset]);
+                let mut mix = self.mix.lock().unwrap();
+                if mix.len() < data_len {
+                    mix.resize(data_len, 0.0);
                 }
-                *sample_pos = new_pos;
+                let mix = &mut mix[..data_len];
+                mix.fill(0.0);
+
+                let (written, is_final) = self.mix_voices(voices, mix, 0, &voice_gains, playback_speed, volume_adjustment);
                 done = is_final;
+                // the song ran out partway through, so the next one carries on from there without a gap
+                if written < data_len {
+                    if let Some(mut next_voices) = self.next_samples.write().unwrap().take() {
+                        (_, done) = self.mix_voices(&mut next_voices, mix, written, &voice_gains, playback_speed, volume_adjustment);
+                        *voices = next_voices;
+                    }
+                }
+
+                for (i, sample) in data.iter_mut().enumerate() {
+                    let channel = i % self.channel_count;
+                    let gain = channel_gains.get(channel).copied().unwrap_or(1.0);
+                    // the channel map picks which mixed channel feeds each device channel
+                    let mixed = match channel_map.get(channel) {
+                        None => mix[i],
+                        Some(Some(from)) => mix[i - channel + from % self.channel_count],
+                        Some(None) => 0.0,
+                    };
+                    *sample = T::from_sample(mixed * gain);
+                    peak = peak.max((mixed * gain).abs());
+                }
             }
             if done {
                 *playback = None;
             }
+            let mut level = self.peak.write().unwrap();
+            *level = level.max(peak);
+        }
+    }
+    // Adds every voice into the mix from the offset on and moves them along. Returns how far the first voice, which the
+    // others follow, got and whether it has finished.
+    fn mix_voices(&self, voices: &mut PlaybackState, mix: &mut [f32], offset: usize, voice_gains: &[Vec<f32>], playback_speed: f64, volume_adjustment: f32) -> (usize, bool) {
+        let mut written = mix.len();
+        let mut done = false;
+        for (voice, (decoding_song, sample_pos)) in voices.iter_mut().enumerate() {
+            let (samples, new_pos, is_final) = decoding_song.read_samples(*sample_pos, mix.len() - offset, playback_speed, volume_adjustment);
+            let gains = voice_gains.get(voice);
+            for (i, sample) in samples.iter().take(mix.len() - offset).enumerate() {
+                let gain = gains.and_then(|g| g.get((offset + i) % self.channel_count)).copied().unwrap_or(1.0);
+                mix[offset + i] += sample * gain;
+            }
+            *sample_pos = new_pos;
+            if voice == 0 {
+                written = offset + samples.len().min(mix.len() - offset);
+                done = is_final;
+            }
         }
+        (written, done)
     }
     fn decode_song(&self, song: &Song, initial_pos: Duration) -> Result<DecodingSong> {
         DecodingSong::new(song, initial_pos, self.sample_rate, self.channel_count, self.buffer_size as usize, *self.playback_speed.read().unwrap())
@@ -387,6 +451,15 @@ impl PlayerState {
     fn get_volume_adjustment(&self) -> f32 {
         *self.volume_adjustment.read().unwrap()
     }
+    fn set_channel_gains(&self, gains: Vec<f32>) {
+        *self.channel_gains.write().unwrap() = gains;
+    }
+    fn set_voice_gains(&self, gains: Vec<Vec<f32>>) {
+        *self.voice_gains.write().unwrap() = gains;
+    }
+    fn set_channel_map(&self, map: Vec<Option<usize>>) {
+        *self.channel_map.write().unwrap() = map;
+    }
     fn stop(&self) {
         *self.next_samples.write().unwrap() = None;
         *self.playback.write().unwrap() = None;
@@ -396,37 +469,41 @@ impl PlayerState {
         *self.playback.write().unwrap() = None;
     }
     fn play_song(&self, song: &Song, time: Option<Duration>) -> Result<()> {
+        self.play_songs(&[song], time)
+    }
+    fn play_songs(&self, songs: &[&Song], time: Option<Duration>) -> Result<()> {
+        ensure!(!songs.is_empty(), "Nothing to play.");
         let initial_pos = time.unwrap_or_default();
-        let samples = self.decode_song(song, initial_pos)?;
-
-        // seek to the last x seconds
-        //let seek_pos = samples.song_length
-        //	.checked_sub(Duration::from_secs(10))
-        //	.unwrap_or_default();
+        let voices = songs.iter().map(|song| Ok((self.decode_song(song, initial_pos)?, initial_pos))).collect::<Result<Vec<_>>>()?;
 
-        *self.next_samples.write().unwrap() = Some((samples, initial_pos));
-
-        // self.seek(seek_pos);
+        *self.next_samples.write().unwrap() = Some(voices);
 
         Ok(())
     }
     fn set_playing(&self, playing: bool) {
         *self.playing.write().unwrap() = playing;
     }
+    fn take_peak(&self) -> f32 {
+        std::mem::take(&mut *self.peak.write().unwrap())
+    }
     fn get_position(&self) -> Option<(Duration, Duration)> {
-        self.playback.read().unwrap().as_ref().map(|(samples, pos)| (*pos, samples.song_length))
+        self.playback
+            .read()
+            .unwrap()
+            .as_ref()
+            .and_then(|voices| voices.first())
+            .map(|(samples, pos)| (*pos, samples.song_length))
     }
     fn seek(&self, time: Duration) -> bool {
         let (mut playback, mut next_song) = (self.playback.write().unwrap(), self.next_samples.write().unwrap());
-        if let Some((_, pos)) = playback.as_mut() {
-            *pos = time;
-            true
-        } else if let Some((_, pos)) = next_song.as_mut() {
+        let voices = match (playback.as_mut(), next_song.as_mut()) {
+            (Some(voices), _) | (None, Some(voices)) => voices,
+            (None, None) => return false,
+        };
+        for (_, pos) in voices.iter_mut() {
             *pos = time;
-            true
-        } else {
-            false
         }
+        true
     }
     #[allow(dead_code)]
     fn force_remove_next_song(&self) {
@@ -443,18 +520,42 @@ impl PlayerState {
 pub struct AudioPlayer {
     _stream: Box<dyn StreamTrait>,
     player_state: PlayerState,
+    stream_error: StreamError,
+}
+
+// Set from the stream's error callback, which also wakes whoever asked to be told
+#[derive(Clone, Default)]
+struct StreamError {
+    is_set: Arc<AtomicBool>,
+    wake: Arc<Mutex<Option<crossbeam_channel::Sender<()>>>>,
+}
+
+impl StreamError {
+    fn set(&self) {
+        self.is_set.store(true, Ordering::SeqCst);
+        if let Some(wake) = self.wake.lock().unwrap().as_ref() {
+            wake.send(()).ok();
+        }
+    }
+
+    fn is_set(&self) -> bool {
+        self.is_set.load(Ordering::SeqCst)
+    }
 }
 
 impl AudioPlayer {
-    /// Creates a new [Player] to play [Song]s. If specified, the player will attempt to use one of
+    /// Creates a new [AudioPlayer] to play [Song]s. If specified, the player will attempt to use one of
     /// the specified sampling rates. If not specified or the list is empty, the preferred rates
     /// are 48000 and 44100.
     ///
     /// If none of the preferred sampling rates are available, the closest available rate to the
     /// first preferred rate will be selected.
     ///
+    /// If a preferred buffer size (in frames) is given, the stream is opened with a fixed buffer of
+    /// that size, clamped to what the device supports.
+    ///
     /// On Linux, this prefers `pipewire`, `jack`, and `pulseaudio` devices over `alsa`.
-    pub fn new(preferred_sampling_rates: Option<Vec<u32>>, device: &Device) -> Result<AudioPlayer> {
+    pub fn new(preferred_sampling_rates: Option<Vec<u32>>, preferred_buffer_size: Option<u32>, device: &Device) -> Result<AudioPlayer> {
         let mut supported_configs = device.supported_output_configs()?.collect::<Vec<_>>();
         let preferred_sampling_rates = preferred_sampling_rates.filter(|given_rates| !given_rates.is_empty()).unwrap_or(vec![48000, 44100]);
         let preferred_sampling_rate = preferred_sampling_rates[0];
@@ -487,49 +588,131 @@ impl AudioPlayer {
         let sample_format = supported_config.sample_format();
         let sample_rate = supported_config.sample_rate().0;
         let channel_count = supported_config.channels();
-        let buffer_size = match supported_config.buffer_size() {
-            SupportedBufferSize::Range { min, .. } => (*min).max(1024) * 2,
-            SupportedBufferSize::Unknown => 1024 * 2,
+        let fixed_buffer_size = preferred_buffer_size.map(|preferred| match supported_config.buffer_size() {
+            SupportedBufferSize::Range { min, max } => preferred.clamp(*min, *max),
+            SupportedBufferSize::Unknown => preferred,
+        });
+        let buffer_size = match (fixed_buffer_size, supported_config.buffer_size()) {
+            (Some(fixed), _) => fixed * 2,
+            (None, SupportedBufferSize::Range { min, .. }) => (*min).max(1024) * 2,
+            (None, SupportedBufferSize::Unknown) => 1024 * 2,
+        };
+        let supported_config_buffer_max = match supported_config.buffer_size() {
+            SupportedBufferSize::Range { max, .. } => Some(*max),
+            SupportedBufferSize::Unknown => None,
         };
-        let config = supported_config.into();
+        let mut config: StreamConfig = supported_config.into();
+        if let Some(fixed) = fixed_buffer_size {
+            config.buffer_size = BufferSize::Fixed(fixed);
+        }
         let player_state = PlayerState::new(channel_count as u32, sample_rate, buffer_size)?;
-        info!("SR, CC, SF: {}, {}, {:?}", sample_rate, channel_count, sample_format);
-        fn build_stream<T>(device: &Device, config: &StreamConfig, player_state: PlayerState) -> Result<Stream>
+        let callback_frames = match (fixed_buffer_size, supported_config_buffer_max) {
+            (Some(fixed), _) => fixed,
+            (None, Some(max)) => max.min(MIX_FRAMES),
+            (None, None) => MIX_FRAMES,
+        };
+        player_state.reserve_mix(callback_frames as usize * channel_count as usize);
+        info!("SR, CC, SF, BS: {}, {}, {:?}, {:?}", sample_rate, channel_count, sample_format, fixed_buffer_size);
+        let stream_error = StreamError::default();
+        fn build_stream<T>(device: &Device, config: &StreamConfig, player_state: PlayerState, stream_error: StreamError) -> Result<Stream>
         where
             T: SizedSample + FromSample<f32>,
         {
-            let err_fn = |err| error!("A playback error has occurred! {}", err);
-            let stream = device.build_output_stream(config, move |data, info| player_state.write_samples::<T>(data, info), err_fn, None)?;
+            // Stream errors usually mean the device went away. Flag it so the owner can reattach.
+            let err_fn = move |err| {
+                error!("A playback error has occurred! {}", err);
+                stream_error.set();
+            };
+            let stream = device.build_output_stream(config, move |data, _info: &OutputCallbackInfo| player_state.write_samples::<T>(data), err_fn, None)?;
             // Not all platforms (*cough cough* windows *cough*) automatically run the stream upon creation, so do that here.
             stream.play()?;
             Ok(stream)
         }
         let stream = {
             let player_state = player_state.clone();
+            let stream_error = stream_error.clone();
             match sample_format {
-                SampleFormat::I8 => build_stream::<i8>(device, &config, player_state)?,
-                SampleFormat::I16 => build_stream::<i16>(device, &config, player_state)?,
-                SampleFormat::I32 => build_stream::<i32>(device, &config, player_state)?,
-                SampleFormat::I64 => build_stream::<i64>(device, &config, player_state)?,
-                SampleFormat::U8 => build_stream::<u8>(device, &config, player_state)?,
-                SampleFormat::U16 => build_stream::<u16>(device, &config, player_state)?,
-                SampleFormat::U32 => build_stream::<u32>(device, &config, player_state)?,
-                SampleFormat::U64 => build_stream::<u64>(device, &config, player_state)?,
-                SampleFormat::F32 => build_stream::<f32>(device, &config, player_state)?,
-                SampleFormat::F64 => build_stream::<f64>(device, &config, player_state)?,
+                SampleFormat::I8 => build_stream::<i8>(device, &config, player_state, stream_error)?,
+                SampleFormat::I16 => build_stream::<i16>(device, &config, player_state, stream_error)?,
+                SampleFormat::I32 => build_stream::<i32>(device, &config, player_state, stream_error)?,
+                SampleFormat::I64 => build_stream::<i64>(device, &config, player_state, stream_error)?,
+                SampleFormat::U8 => build_stream::<u8>(device, &config, player_state, stream_error)?,
+                SampleFormat::U16 => build_stream::<u16>(device, &config, player_state, stream_error)?,
+                SampleFormat::U32 => build_stream::<u32>(device, &config, player_state, stream_error)?,
+                SampleFormat::U64 => build_stream::<u64>(device, &config, player_state, stream_error)?,
+                SampleFormat::F32 => build_stream::<f32>(device, &config, player_state, stream_error)?,
+                SampleFormat::F64 => build_stream::<f64>(device, &config, player_state, stream_error)?,
                 sample_format => Err(Report::msg(format!("Unsupported sample format '{sample_format}'")))?,
             }
         };
         Ok(AudioPlayer {
             _stream: Box::new(stream),
             player_state,
+            stream_error,
         })
     }
+    /// Creates a new [AudioPlayer] as a JACK client with named output ports instead of a [cpal] stream.
+    ///
+    /// The ports are called `<port_prefix>_L` and `<port_prefix>_R`. Each one is connected to the
+    /// ports listed for it in `connections`, or to the physical playback ports in order if none are listed.
+    #[cfg(feature = "jack")]
+    pub fn new_jack(port_prefix: &str, connections: &std::collections::HashMap<String, Vec<String>>) -> Result<AudioPlayer> {
+        use jack::{AudioOut, Client, ClientOptions, PortFlags, PortSpec};
+
+        let (client, status) = Client::new(&format!("drum_weaver_{port_prefix}"), ClientOptions::NO_START_SERVER)?;
+        info!("JACK client {} opened with status {:?}", client.name(), status);
+
+        let port_names = [format!("{port_prefix}_L"), format!("{port_prefix}_R")];
+        let ports = port_names.iter().map(|name| client.register_port(name, AudioOut)).collect::<std::result::Result<Vec<_>, _>>()?;
+        let full_port_names = ports.iter().map(|port| port.name()).collect::<std::result::Result<Vec<_>, _>>()?;
+
+        let physical_ports = client.ports(None, Some(AudioOut.jack_port_type()), PortFlags::IS_INPUT | PortFlags::IS_PHYSICAL);
+
+        let sample_rate = client.sample_rate() as u32;
+        let buffer_size = client.buffer_size() * port_names.len() as u32;
+        let player_state = PlayerState::new(port_names.len() as u32, sample_rate, buffer_size)?;
+        player_state.reserve_mix(buffer_size as usize);
+        info!("SR, CC, BS: {}, {}, {}", sample_rate, port_names.len(), client.buffer_size());
+
+        let stream_error = StreamError::default();
+        let process = JackProcess {
+            player_state: player_state.clone(),
+            ports,
+            buffer: vec![0.0; buffer_size as usize],
+        };
+        let notifications = JackNotifications { stream_error: stream_error.clone() };
+        let async_client = client.activate_async(notifications, process)?;
+
+        // ports can only be connected once the client is active
+        for (idx, (short_name, full_name)) in port_names.iter().zip(full_port_names.iter()).enumerate() {
+            let destinations = match connections.get(short_name) {
+                Some(destinations) => destinations.clone(),
+                None => physical_ports.get(idx).cloned().into_iter().collect(),
+            };
+            for destination in destinations {
+                if let Err(err) = async_client.as_client().connect_ports_by_name(full_name, &destination) {
+                    warn!("Unable to connect {} to {}: {}", full_name, destination, err);
+                }
+            }
+        }
+
+        Ok(AudioPlayer {
+            _stream: Box::new(JackStream(async_client)),
+            player_state,
+            stream_error,
+        })
+    }
+    /// Stand-in used when built without the `jack` feature.
+    #[cfg(not(feature = "jack"))]
+    pub fn new_jack(_port_prefix: &str, _connections: &std::collections::HashMap<String, Vec<String>>) -> Result<AudioPlayer> {
+        Err(Report::msg("Built without JACK support. Enable the jack feature."))
+    }
     /// Set the playback speed (This will also affect song pitch)
     pub fn set_playback_speed(&self, speed: f64) {
         self.player_state.set_playback_speed(speed);
     }
 
+    /// The playback speed, 1.0 being the original
     pub fn get_playback_speed(&self) -> f64 {
         *self.player_state.playback_speed.read().unwrap()
     }
@@ -545,10 +728,18 @@ impl AudioPlayer {
         self.player_state.play_song(song, start_time)?;
         Ok(())
     }
+    /// Start playing several songs at once, mixed into this output and sharing one transport. Optionally start playing in the middle of the songs.
+    ///
+    /// The first song decides the playback position and when playback ends. See also [`set_voice_gains`](AudioPlayer::set_voice_gains)
+    pub fn play_songs_now(&self, songs: &[&Song], start_time: Option<Duration>) -> Result<()> {
+        self.player_state.stop();
+        self.player_state.play_songs(songs, start_time)?;
+        Ok(())
+    }
     /// Used to replace the next song, or the current song if there is no next song. Optionally start playing in the middle of the song.
     ///
     /// This will remove the current song if no next song exists to avoid a race condition in case the current song ends after you have determined that the next song must be replaced but before you call this function.
-    /// See also [`force_remove_next_song`](Player::force_remove_next_song)
+    /// See also [`force_remove_next_song`](AudioPlayer::force_remove_next_song)
     #[allow(dead_code)]
     pub fn force_replace_next_song(&self, song: &Song, start_time: Option<Duration>) -> Result<()> {
         self.player_state.force_remove_next_song();
@@ -558,7 +749,7 @@ impl AudioPlayer {
     /// Used to remove the next song, or the current song if there is no next song.
     ///
     /// This will remove the current song if no next song exists to avoid a race condition in case the current song ends after you have determined that the next song must be replaced but before you call this function.
-    /// See also [`force_replace_next_song`](Player::force_replace_next_song)
+    /// See also [`force_replace_next_song`](AudioPlayer::force_replace_next_song)
     #[allow(dead_code)]
     pub fn force_remove_next_song(&self) -> Result<()> {
         self.player_state.force_remove_next_song();
@@ -566,7 +757,7 @@ impl AudioPlayer {
     }
     /// Stop playing any songs and remove a next song if it has been queued.
     ///
-    /// Note that this does not pause playback (use [`set_playing`](Player::set_playing)), meaning new songs will play upon adding them.
+    /// Note that this does not pause playback (use [`set_playing`](AudioPlayer::set_playing)), meaning new songs will play upon adding them.
     pub fn stop(&self) {
         self.player_state.stop();
     }
@@ -577,9 +768,9 @@ impl AudioPlayer {
     pub fn skip(&self) {
         self.player_state.skip();
     }
-    /// Return the current playback position, if there is currently a song playing (see [`has_current_song`](Player::has_current_song))
+    /// Return the current playback position, if there is currently a song playing (see [`has_current_song`](AudioPlayer::has_current_song))
     ///
-    /// See also [`seek`](Player::seek)
+    /// See also [`seek`](AudioPlayer::seek)
     pub fn get_playback_position(&self) -> Option<(Duration, Duration)> {
         self.player_state.get_position()
     }
@@ -588,37 +779,75 @@ impl AudioPlayer {
     /// Returns whether the seek was successful (whether there was a song to seek).
     /// Note that seeking past the end of the song will be successful and will cause playback to begin at the _beginning_ of the next song.
     ///
-    /// See also [`get_playback_position`](Player::get_playback_position)
+    /// See also [`get_playback_position`](AudioPlayer::get_playback_position)
     pub fn seek(&self, time: Duration) -> bool {
         self.player_state.seek(time)
     }
 
+    /// Scales the volume of everything played, from 0.0 to 2.0. Anything else is ignored.
     pub fn set_volume_adjustment(&self, volume: f32) {
         if (0.0..=2.0).contains(&volume) {
             self.player_state.set_volume_adjustment(volume);
         }
     }
 
+    /// The volume scale set with [`set_volume_adjustment`](AudioPlayer::set_volume_adjustment)
     pub fn get_volume_adjustment(&self) -> f32 {
         self.player_state.get_volume_adjustment()
     }
 
+    /// Returns the loudest sample played since the last call, 1.0 being full scale.
+    pub fn take_peak(&self) -> f32 {
+        self.player_state.take_peak()
+    }
+
+    /// Sets a gain per output channel, applied after the volume adjustment. Channels without an entry play at full gain.
+    ///
+    /// Used to route a song to specific channels of a multichannel device. An empty list restores every channel.
+    pub fn set_channel_gains(&self, gains: Vec<f32>) {
+        self.player_state.set_channel_gains(gains);
+    }
+
+    /// Sets the per channel gains of each voice, in the order the songs were given to [`play_songs_now`](AudioPlayer::play_songs_now).
+    ///
+    /// Voices or channels without an entry play at full gain.
+    pub fn set_voice_gains(&self, gains: Vec<Vec<f32>>) {
+        self.player_state.set_voice_gains(gains);
+    }
+
+    /// Sets which mixed channel plays on each device channel. `None` silences a device channel.
+    ///
+    /// Device channels without an entry play their own mixed channel. An empty map restores that for every channel.
+    pub fn set_channel_map(&self, map: Vec<Option<usize>>) {
+        self.player_state.set_channel_map(map);
+    }
+
+    /// Returns the number of channels the output stream was opened with.
+    pub fn channel_count(&self) -> usize {
+        self.player_state.channel_count
+    }
+
+    /// Returns the sample rate the output stream was opened with.
+    pub fn sample_rate(&self) -> usize {
+        self.player_state.sample_rate
+    }
+
     /// Sets whether playback is enabled or not, without touching the song queue.
     ///
-    /// See also [`is_playing`](Player::is_playing)
+    /// See also [`is_playing`](AudioPlayer::is_playing)
     pub fn set_playing(&self, playing: bool) {
         self.player_state.set_playing(playing);
     }
     /// Returns whether playback is currently paused.
     ///
-    /// See also [`set_playing`](Player::set_playing)
+    /// See also [`set_playing`](AudioPlayer::set_playing)
     pub fn is_playing(&self) -> bool {
         *self.player_state.playing.read().unwrap()
     }
     /// Returns whether there is a song queued to play next after the current song has finished
     ///
-    /// If you want to check whether there is currently a song playing, use [`has_current_song`][Player::has_current_song] and [`is_playing`][Player::is_playing].
-    /// This should always be queried before calling [`play_song_next`](Player::play_song_next) if you do not intend on replacing the song currently in the queue.
+    /// If you want to check whether there is currently a song playing, use [`has_current_song`][AudioPlayer::has_current_song] and [`is_playing`][AudioPlayer::is_playing].
+    /// This should always be queried before calling [`play_song_next`](AudioPlayer::play_song_next) if you do not intend on replacing the song currently in the queue.
     #[allow(dead_code)]
     pub fn has_next_song(&self) -> bool {
         self.player_state.next_samples.read().expect("Next song mutex poisoned.").is_some()
@@ -629,11 +858,137 @@ impl AudioPlayer {
     pub fn has_current_song(&self) -> bool {
         self.player_state.playback.read().expect("Current song mutex poisoned.").is_some() || self.player_state.next_samples.read().expect("Next song mutex poisoned.").is_some()
     }
+    /// Returns whether the output stream has reported an error since it was created.
+    ///
+    /// This typically happens when the device is unplugged. The player should be recreated on another device.
+    pub fn has_stream_error(&self) -> bool {
+        self.stream_error.is_set()
+    }
+    /// Sends on `wake` when the stream reports an error, straight away if it already has, so the owner can sleep
+    /// rather than poll [`has_stream_error`](AudioPlayer::has_stream_error).
+    pub fn wake_on_stream_error(&self, wake: crossbeam_channel::Sender<()>) {
+        let mut waiting = self.stream_error.wake.lock().unwrap();
+        if self.stream_error.is_set() {
+            wake.send(()).ok();
+        }
+        *waiting = Some(wake);
+    }
+}
+
+// Fills the JACK port buffers from the same player state a cpal stream would use
+#[cfg(feature = "jack")]
+struct JackProcess {
+    player_state: PlayerState,
+    ports: Vec<jack::Port<jack::AudioOut>>,
+    buffer: Vec<f32>,
+}
+
+#[cfg(feature = "jack")]
+impl jack::ProcessHandler for JackProcess {
+    fn process(&mut self, _: &jack::Client, process_scope: &jack::ProcessScope) -> jack::Control {
+        let channel_count = self.ports.len();
+        self.buffer.resize(process_scope.n_frames() as usize * channel_count, 0.0);
+        self.player_state.write_samples::<f32>(&mut self.buffer);
+
+        // JACK ports are not interleaved
+        for (chan, port) in self.ports.iter_mut().enumerate() {
+            for (frame, sample) in port.as_mut_slice(process_scope).iter_mut().enumerate() {
+                *sample = self.buffer[frame * channel_count + chan];
+            }
+        }
+
+        jack::Control::Continue
+    }
+
+    // Called outside the process callback, so this is where the buffers grow
+    fn buffer_size(&mut self, _: &jack::Client, size: jack::Frames) -> jack::Control {
+        let samples = size as usize * self.ports.len();
+        self.buffer.resize(samples, 0.0);
+        self.player_state.reserve_mix(samples);
+        jack::Control::Continue
+    }
+}
+
+#[cfg(feature = "jack")]
+struct JackNotifications {
+    stream_error: StreamError,
+}
+
+#[cfg(feature = "jack")]
+impl jack::NotificationHandler for JackNotifications {
+    fn shutdown(&mut self, _status: jack::ClientStatus, _reason: &str) {
+        self.stream_error.set();
+    }
 }
 
-/// Represents a single song that has been decoded into memory, can be played in a <Player> struct.
+// Keeps the JACK client alive for as long as the player holds it
+#[cfg(feature = "jack")]
+struct JackStream(jack::AsyncClient<JackNotifications, JackProcess>);
+
+#[cfg(feature = "jack")]
+impl StreamTrait for JackStream {
+    fn play(&self) -> std::result::Result<(), cpal::PlayStreamError> {
+        Ok(())
+    }
+
+    fn pause(&self) -> std::result::Result<(), cpal::PauseStreamError> {
+        Ok(())
+    }
+}
+
+/// What a file's header says about its audio, for checking files without keeping their samples.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AudioInfo {
+    /// Frames a second
+    pub sample_rate: u32,
+    /// Channels in the file
+    pub channel_count: usize,
+    /// Frames in the file, when the header says
+    pub frames: Option<u64>,
+    /// Bits per sample, when the format has a fixed size
+    pub bits_per_sample: Option<u32>,
+}
+
+impl AudioInfo {
+    /// Reads the header. With `decode`, every packet is decoded as well and thrown away, and the file must have every frame its header promises.
+    pub fn inspect(reader: Box<dyn MediaSource>, hint: &Hint, decode: bool) -> Result<AudioInfo> {
+        let media_source_stream = MediaSourceStream::new(reader, MediaSourceStreamOptions::default());
+        let mut probe_result = default::get_probe().format(hint, media_source_stream, &FormatOptions::default(), &MetadataOptions::default())?;
+        let codec_params = probe_result.format.default_track().ok_or_else(|| Report::msg("No default track in media file."))?.codec_params.clone();
+        let info = AudioInfo {
+            sample_rate: codec_params.sample_rate.ok_or_else(|| Report::msg("No sample rate in media file."))?,
+            channel_count: codec_params.channels.map(|channels| channels.count()).unwrap_or(0),
+            frames: codec_params.n_frames,
+            bits_per_sample: codec_params.bits_per_sample,
+        };
+        if !decode {
+            return Ok(info);
+        }
+
+        let mut decoder = default::get_codecs().make(&codec_params, &DecoderOptions::default())?;
+        let mut decoded_frames = 0;
+        loop {
+            match probe_result.format.next_packet() {
+                Ok(packet) => decoded_frames += decoder.decode(&packet)?.frames() as u64,
+                Err(SymphoniaError::IoError(_)) => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        if let Some(frames) = info.frames {
+            ensure!(decoded_frames >= frames, "Only {} of {} frames decode.", decoded_frames, frames);
+        }
+        Ok(info)
+    }
+
+    /// How many bytes of audio data the header promises, when it says
+    pub fn data_len(&self) -> Option<u64> {
+        Some(self.frames? * self.channel_count as u64 * self.bits_per_sample? as u64 / 8)
+    }
+}
+
+/// Represents a single song that has been decoded into memory, can be played by an [AudioPlayer].
 ///
-/// The data in the song is stored in an <Arc> so cloning a song is a lightweight operation.
+/// The data in the song is stored in an [Arc] so cloning a song is a lightweight operation.
 #[derive(Debug, Clone)]
 pub struct Song {
     samples: Arc<Vec<Vec<f32>>>,
@@ -645,6 +1000,12 @@ pub struct Song {
 impl Song {
     /// Creates a new song using a reader of some kind and a type hint (the Symphonia hint type has been reexported at the crate root for convenience), as well as an optional volume adjustment (used for e.g. replay gain).
     pub fn new(reader: Box<dyn MediaSource>, hint: &Hint, volume_adjustment: Option<f32>) -> Result<Song> {
+        Self::new_with_progress(reader, hint, volume_adjustment, &mut |_, _| true)
+    }
+    /// Like [`new`](Song::new), calling `progress` with the frames decoded so far and the total, when the format knows it, after every packet.
+    ///
+    /// Decoding stops with an error as soon as `progress` returns false.
+    pub fn new_with_progress(reader: Box<dyn MediaSource>, hint: &Hint, volume_adjustment: Option<f32>, progress: &mut dyn FnMut(u64, Option<u64>) -> bool) -> Result<Song> {
         let media_source_stream = MediaSourceStream::new(reader, MediaSourceStreamOptions::default());
         let mut probe_result = default::get_probe().format(
             hint,
@@ -659,12 +1020,15 @@ impl Song {
             &probe_result.format.default_track().ok_or_else(|| Report::msg("No default track in media file."))?.codec_params,
             &DecoderOptions::default(),
         )?;
+        let total_frames = probe_result.format.default_track().and_then(|track| track.codec_params.n_frames);
+        let mut decoded_frames = 0;
         let mut song: Option<(Vec<Vec<f32>>, u32, usize)> = None;
         loop {
             match probe_result.format.next_packet() {
                 Ok(packet) => {
                     let decoded = decoder.decode(&packet)?;
                     let spec = *decoded.spec();
+                    decoded_frames += decoded.frames() as u64;
                     let song_samples = if let Some((samples, sample_rate, channel_count)) = &mut song {
                         ensure!(spec.rate == *sample_rate, "Sample rate of decoded does not match previous sample rate.");
                         ensure!(spec.channels.count() == *channel_count, "Channel count of decoded does not match previous channel count.");
@@ -684,6 +1048,7 @@ impl Song {
                     } else {
                         warn!("Empty packet encountered while loading song!");
                     }
+                    ensure!(progress(decoded_frames, total_frames), "Decoding was cancelled.");
                 }
                 Err(SymphoniaError::IoError(_)) => break,
                 Err(e) => return Err(e.into()),
@@ -697,6 +1062,17 @@ impl Song {
         })
         .ok_or_else(|| Report::msg("No song data decoded."))
     }
+    /// Creates a [Song] from samples that are already in memory, one [Vec] per channel. Used for generated audio such as test tones.
+    pub fn from_samples(samples: Vec<Vec<f32>>, sample_rate: u32) -> Result<Song> {
+        ensure!(!samples.is_empty(), "A song needs at least one channel.");
+        let channel_count = samples.len();
+        Ok(Song {
+            samples: Arc::new(samples),
+            sample_rate,
+            channel_count,
+            volume_adjustment: 1.0,
+        })
+    }
     /// Creates a [Song] by reading data from a file and using the file's extension as a format type hint. Takes an optional volume adjustment (used for e.g. replay gain)
     pub fn from_file<P: AsRef<std::path::Path>>(path: P, volume_adjustment: Option<f32>) -> Result<Song> {
         let mut hint = Hint::new();
@@ -706,9 +1082,25 @@ impl Song {
         Self::new(Box::new(std::fs::File::open(path)?), &hint, volume_adjustment)
     }
 
+    /// The volume adjustment given when the song was created
     pub fn get_volume_adjustment(&self) -> f32 {
         self.volume_adjustment
     }
+
+    /// Returns how long the song plays at normal speed.
+    pub fn duration(&self) -> Duration {
+        DecodingSong::frame_to_duration(self.samples[0].len(), self.sample_rate as u64)
+    }
+
+    /// Returns the decoded samples, one vector per channel.
+    pub fn samples(&self) -> &[Vec<f32>] {
+        &self.samples
+    }
+
+    /// The sample rate the song was decoded at
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
 }
 
 // #[cfg(any(target_os = "linux", target_os = "dragonfly", target_os = "freebsd", target_os = "netbsd"))]
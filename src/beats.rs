@@ -0,0 +1,429 @@
+//! Where the beats and bars of a song fall, so the UI can show bar:beat and flash on every beat.
+//! The grid comes from the onsets in the click file. When the click has none worth trusting, the song's BPM is used instead.
+use std::time::Duration;
+
+use log::{info, warn};
+
+use super::audio::Song;
+
+/// Used when the click has no accents to count bars from. Nearly everything in the library is in 4.
+pub const DEFAULT_BEATS_PER_BAR: usize = 4;
+
+const WINDOW: Duration = Duration::from_millis(5); // resolution of the energy envelope
+const MIN_BEAT_GAP: Duration = Duration::from_millis(100); // 600 BPM, anything closer is the same click ringing
+const ONSET_THRESHOLD: f32 = 0.3; // of the loudest window
+const ACCENT_RATIO: f32 = 1.2; // how much louder than a typical click a downbeat is
+const MIN_ONSETS: usize = 8;
+const BAR_GRACE: Duration = Duration::from_millis(500); // going back this soon after a bar starts goes to the bar before
+
+/// Where in the song a position is, as a count of bars and beats
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BarBeat {
+    /// 1-based. Bar 0 is a pickup before the first downbeat.
+    pub bar: usize,
+    /// 1-based within the bar
+    pub beat: usize,
+    /// Beats in the current bar
+    pub beats_per_bar: usize,
+    /// How far into the beat, 0 to 1
+    pub phase: f32,
+    /// Of the current beat
+    pub bpm: f64,
+}
+
+impl BarBeat {
+    /// Whether it is the first beat of its bar
+    pub fn is_downbeat(&self) -> bool {
+        self.beat == 1
+    }
+}
+
+/// One bar of the tempo map
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TempoBar {
+    /// Where the bar starts in the song
+    pub start: Duration,
+    /// Clicks per minute
+    pub bpm: f64,
+    /// Beats in the bar
+    pub beats_per_bar: usize,
+}
+
+impl TempoBar {
+    /// Only a guess, the clicks don't say what note value they are.
+    /// Six, nine or twelve clicks to a bar are most likely eighths in a compound meter.
+    pub fn time_signature(&self) -> (usize, usize) {
+        match self.beats_per_bar {
+            6 | 9 | 12 => (self.beats_per_bar, 8),
+            beats => (beats, 4),
+        }
+    }
+}
+
+/// Where every beat and bar of a song falls
+#[derive(Debug, Clone)]
+pub struct BeatGrid {
+    beats: Vec<Duration>,
+    downbeats: Vec<usize>, // indices into beats where a bar starts. Never empty.
+}
+
+impl BeatGrid {
+    /// Prefers the click file, since songs drift from their nominal BPM
+    pub fn for_song(click: &Song, bpm: usize) -> Option<BeatGrid> {
+        match BeatGrid::from_click(click) {
+            Some(grid) => {
+                info!("Beat grid from click: {} beats in {} bars", grid.beats.len(), grid.downbeats.len());
+                Some(grid)
+            }
+            None => {
+                warn!("No usable onsets in the click. Using {} BPM for the beat grid.", bpm);
+                BeatGrid::from_bpm(bpm, DEFAULT_BEATS_PER_BAR, click.duration())
+            }
+        }
+    }
+
+    /// A steady grid from a tempo, starting at the top of the song. None when the tempo is unknown.
+    pub fn from_bpm(bpm: usize, beats_per_bar: usize, duration: Duration) -> Option<BeatGrid> {
+        if bpm == 0 {
+            return None;
+        }
+
+        let beat_length = Duration::from_secs_f64(60.0 / bpm as f64);
+        let count = ((duration.as_secs_f64() / beat_length.as_secs_f64()).ceil() as u32).max(1);
+        Some(BeatGrid {
+            beats: (0..count).map(|beat| beat_length * beat).collect(),
+            downbeats: Self::regular_bars(0, beats_per_bar.max(1), count as usize),
+        })
+    }
+
+    /// Finds the clicks by their energy rising through a threshold, then the bars from which of them are accented
+    pub fn from_click(click: &Song) -> Option<BeatGrid> {
+        let channels = click.samples();
+        let window = (click.sample_rate() as usize * WINDOW.as_millis() as usize / 1000).max(1);
+        let frames = channels.first()?.len();
+
+        let envelope = (0..frames / window)
+            .map(|w| {
+                let range = w * window..(w + 1) * window;
+                channels.iter().map(|channel| channel[range.clone()].iter().map(|s| s.abs()).fold(0.0, f32::max)).fold(0.0, f32::max)
+            })
+            .collect::<Vec<_>>();
+
+        let loudest = envelope.iter().cloned().fold(0.0, f32::max);
+        if loudest <= 0.0 {
+            return None;
+        }
+
+        let threshold = loudest * ONSET_THRESHOLD;
+        let min_gap = (MIN_BEAT_GAP.as_millis() / WINDOW.as_millis()) as usize;
+        let mut onsets: Vec<(usize, f32)> = Vec::new(); // window and peak level
+        for (w, level) in envelope.iter().enumerate() {
+            let is_rising = *level >= threshold && (w == 0 || envelope[w - 1] < threshold);
+            if is_rising && onsets.last().is_none_or(|(last, _)| w - last >= min_gap) {
+                let peak = envelope[w..(w + min_gap / 2).min(envelope.len())].iter().cloned().fold(0.0, f32::max);
+                onsets.push((w, peak));
+            }
+        }
+
+        if onsets.len() < MIN_ONSETS {
+            return None;
+        }
+
+        Some(BeatGrid {
+            beats: onsets.iter().map(|(w, _)| WINDOW * *w as u32).collect(),
+            downbeats: Self::find_bars(&onsets.iter().map(|(_, peak)| *peak).collect::<Vec<_>>()),
+        })
+    }
+
+    // Accented clicks mark the downbeats, so songs that change meter keep their bar lines
+    fn find_bars(peaks: &[f32]) -> Vec<usize> {
+        let mut sorted = peaks.to_vec();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        let typical = sorted[sorted.len() / 2];
+
+        let accents = peaks.iter().enumerate().filter(|(_, peak)| **peak >= typical * ACCENT_RATIO).map(|(idx, _)| idx).collect::<Vec<_>>();
+        if accents.len() < 2 {
+            return Self::regular_bars(0, DEFAULT_BEATS_PER_BAR, peaks.len());
+        }
+
+        let gaps = accents.windows(2).map(|pair| pair[1] - pair[0]).collect::<Vec<_>>();
+        if gaps.iter().all(|gap| (2..=12).contains(gap)) {
+            return accents;
+        }
+
+        // Some accents are noise. The most common gap wins, as long as most bars agree.
+        let count_of = |bar: usize| gaps.iter().filter(|gap| **gap == bar).count();
+        let bar = (2..=12).max_by_key(|bar| count_of(*bar)).unwrap_or(DEFAULT_BEATS_PER_BAR);
+        if count_of(bar) * 4 < gaps.len() * 3 {
+            return Self::regular_bars(0, DEFAULT_BEATS_PER_BAR, peaks.len());
+        }
+
+        let first = accents.windows(2).find(|pair| pair[1] - pair[0] == bar).map(|pair| pair[0]).unwrap_or(0);
+        Self::regular_bars(first % bar, bar, peaks.len())
+    }
+
+    fn regular_bars(first: usize, beats_per_bar: usize, beat_count: usize) -> Vec<usize> {
+        let bars = (first..beat_count).step_by(beats_per_bar).collect::<Vec<_>>();
+        if bars.is_empty() {
+            vec![first]
+        } else {
+            bars
+        }
+    }
+
+    // Bars after the last downbeat keep its length
+    fn last_bar_length(&self) -> usize {
+        match self.downbeats.as_slice() {
+            [.., previous, last] => last - previous,
+            _ => DEFAULT_BEATS_PER_BAR,
+        }
+    }
+
+    /// None before the first click
+    pub fn position_at(&self, position: Duration) -> Option<BarBeat> {
+        let idx = self.beats.partition_point(|beat| *beat <= position).checked_sub(1)?;
+
+        // past the last click the beats carry on at the average tempo
+        let average = (*self.beats.last()? - self.beats[0]) / (self.beats.len() as u32 - 1).max(1);
+        let (idx, start, length) = match self.beats.get(idx + 1) {
+            Some(next) => (idx, self.beats[idx], *next - self.beats[idx]),
+            None if average.is_zero() => (idx, self.beats[idx], Duration::from_secs(1)),
+            None => {
+                let extra = ((position - self.beats[idx]).as_secs_f64() / average.as_secs_f64()) as usize;
+                (idx + extra, self.beats[idx] + average * extra as u32, average)
+            }
+        };
+
+        let phase = ((position - start).as_secs_f64() / length.as_secs_f64()).clamp(0.0, 1.0) as f32;
+        let bpm = 60.0 / length.as_secs_f64();
+
+        let bar_idx = self.downbeats.partition_point(|downbeat| *downbeat <= idx);
+        let (bar, beat, beats_per_bar) = match bar_idx.checked_sub(1) {
+            None => (0, idx + 1, self.downbeats[0]), // the pickup
+            Some(last) => {
+                let downbeat = self.downbeats[last];
+                let bar_length = self.downbeats.get(last + 1).map(|next| next - downbeat).unwrap_or_else(|| self.last_bar_length());
+                let offset = idx - downbeat;
+                (bar_idx + offset / bar_length, offset % bar_length + 1, bar_length)
+            }
+        };
+
+        Some(BarBeat { bar, beat, beats_per_bar, phase, bpm })
+    }
+
+    /// A bar, numbered as [`position_at`](BeatGrid::position_at) numbers them
+    pub fn bar(&self, bar: usize) -> Option<TempoBar> {
+        let has_pickup = self.downbeats[0] > 0;
+        let idx = if has_pickup { bar } else { bar.saturating_sub(1) };
+        self.tempo_map().get(idx).copied()
+    }
+
+    /// Where a bar starts, numbered as [`bar`](BeatGrid::bar) numbers them
+    pub fn bar_start(&self, bar: usize) -> Option<Duration> {
+        self.bar(bar).map(|bar| bar.start)
+    }
+
+    /// Where a seek of some bars from the position lands. Going back first returns to the start of the current bar.
+    /// None when that is past the last bar.
+    pub fn seek_bars(&self, position: Duration, bars: i32) -> Option<Duration> {
+        let starts = self.tempo_map().iter().map(|bar| bar.start).collect::<Vec<_>>();
+        let current = starts.partition_point(|start| *start <= position).saturating_sub(1);
+        let into_bar = position.saturating_sub(*starts.get(current)?);
+
+        let mut target = current as i64 + bars as i64;
+        if bars < 0 && into_bar > BAR_GRACE {
+            target += 1;
+        }
+
+        starts.get(target.max(0) as usize).copied()
+    }
+
+    /// The tempo of every bar, including a pickup and the bars after the last accent
+    pub fn tempo_map(&self) -> Vec<TempoBar> {
+        let mut bar_starts = self.downbeats.clone();
+        if bar_starts[0] > 0 {
+            bar_starts.insert(0, 0);
+        }
+        let last_bar_length = self.last_bar_length();
+        while let Some(next) = bar_starts.last().map(|last| last + last_bar_length).filter(|next| *next < self.beats.len()) {
+            bar_starts.push(next);
+        }
+
+        let mut bars = vec![];
+        for (idx, start) in bar_starts.iter().enumerate() {
+            let end = bar_starts.get(idx + 1).copied().unwrap_or(start + last_bar_length);
+            let last_beat = end.min(self.beats.len() - 1); // the final bar may be cut short
+            if last_beat <= *start {
+                continue;
+            }
+
+            let length = self.beats[last_beat] - self.beats[*start];
+            bars.push(TempoBar {
+                start: self.beats[*start],
+                bpm: 60.0 * (last_beat - start) as f64 / length.as_secs_f64(),
+                beats_per_bar: end - start,
+            });
+        }
+        bars
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 8000;
+
+    // A click track with a short burst at each of the clicks, given as the time and how loud
+    fn click_track(clicks: &[(Duration, f32)], tail: Duration) -> Song {
+        let length = clicks.last().map(|(at, _)| *at).unwrap_or_default() + tail;
+        let mut samples = vec![0.0; (length.as_secs_f64() * SAMPLE_RATE as f64) as usize];
+        for (at, level) in clicks {
+            let start = (at.as_secs_f64() * SAMPLE_RATE as f64).round() as usize;
+            for (idx, sample) in samples[start..start + SAMPLE_RATE as usize / 100].iter_mut().enumerate() {
+                *sample = if idx % 2 == 0 { *level } else { -*level };
+            }
+        }
+        Song::from_samples(vec![samples.clone(), samples], SAMPLE_RATE).unwrap()
+    }
+
+    // Bars of clicks at a tempo from the start, with the downbeats accented
+    fn bars(start: Duration, bpm: f64, beats_per_bar: usize, bar_count: usize) -> Vec<(Duration, f32)> {
+        let beat = Duration::from_secs_f64(60.0 / bpm);
+        (0..beats_per_bar * bar_count)
+            .map(|idx| (start + beat * idx as u32, if idx % beats_per_bar == 0 { 1.0 } else { 0.5 }))
+            .collect()
+    }
+
+    fn assert_near(found: Duration, expected: Duration) {
+        let error = (found.as_secs_f64() - expected.as_secs_f64()).abs();
+        assert!(error <= WINDOW.as_secs_f64(), "{:?} instead of {:?}", found, expected);
+    }
+
+    fn assert_bpm(found: f64, expected: f64) {
+        assert!((found - expected).abs() < expected * 0.01, "{} BPM instead of {}", found, expected);
+    }
+
+    fn grid(clicks: &[(Duration, f32)]) -> BeatGrid {
+        BeatGrid::from_click(&click_track(clicks, Duration::from_secs(1))).expect("a grid from the clicks")
+    }
+
+    #[test]
+    fn steady_four_four() {
+        let map = grid(&bars(Duration::ZERO, 120.0, 4, 8)).tempo_map();
+        assert_eq!(map.len(), 8);
+        for (idx, bar) in map.iter().enumerate() {
+            assert_near(bar.start, Duration::from_secs(2) * idx as u32);
+            assert_eq!(bar.beats_per_bar, 4);
+            assert_eq!(bar.time_signature(), (4, 4));
+        }
+        // the last bar is cut short, so its tempo comes from the clicks it has
+        for bar in map.iter() {
+            assert_bpm(bar.bpm, 120.0);
+        }
+    }
+
+    #[test]
+    fn tempo_change() {
+        let mut clicks = bars(Duration::ZERO, 120.0, 4, 4);
+        clicks.extend(bars(Duration::from_secs(8), 90.0, 4, 4));
+        let map = grid(&clicks).tempo_map();
+        assert_eq!(map.len(), 8);
+        for bar in map[..4].iter() {
+            assert_bpm(bar.bpm, 120.0);
+        }
+        assert_near(map[4].start, Duration::from_secs(8));
+        for bar in map[4..7].iter() {
+            assert_bpm(bar.bpm, 90.0);
+        }
+    }
+
+    #[test]
+    fn six_eight_accents() {
+        let map = grid(&bars(Duration::ZERO, 180.0, 6, 6)).tempo_map();
+        assert_eq!(map.len(), 6);
+        for bar in map.iter() {
+            assert_eq!(bar.beats_per_bar, 6);
+            assert_eq!(bar.time_signature(), (6, 8));
+        }
+        assert_near(map[1].start, Duration::from_secs(2));
+    }
+
+    #[test]
+    fn lead_in() {
+        let lead_in = Duration::from_millis(1500);
+        let grid = grid(&bars(lead_in, 120.0, 4, 4));
+        assert_eq!(grid.position_at(Duration::from_secs(1)), None);
+
+        let map = grid.tempo_map();
+        assert_eq!(map.len(), 4);
+        assert_near(map[0].start, lead_in);
+        assert_near(grid.bar_start(1).unwrap(), lead_in);
+
+        let position = grid.position_at(lead_in + Duration::from_millis(2750)).unwrap();
+        assert_eq!((position.bar, position.beat, position.beats_per_bar), (2, 2, 4));
+        assert!((position.phase - 0.5).abs() < 0.05);
+    }
+
+    #[test]
+    fn pickup_before_the_first_downbeat() {
+        let mut clicks = vec![(Duration::ZERO, 0.5), (Duration::from_millis(500), 0.5)];
+        clicks.extend(bars(Duration::from_secs(1), 120.0, 4, 4));
+        let grid = grid(&clicks);
+
+        let pickup = grid.position_at(Duration::from_millis(600)).unwrap();
+        assert_eq!((pickup.bar, pickup.beat), (0, 2));
+        assert_near(grid.bar_start(0).unwrap(), Duration::ZERO);
+        assert_near(grid.bar_start(1).unwrap(), Duration::from_secs(1));
+        assert_eq!(grid.tempo_map()[0].beats_per_bar, 2);
+    }
+
+    #[test]
+    fn seeking_back_returns_to_the_bar_start_first() {
+        let grid = grid(&bars(Duration::ZERO, 120.0, 4, 8)); // bars every 2 s
+        let bar = |idx: u32| Duration::from_secs(2) * idx;
+
+        // within the grace going back goes to the bar before, after it to the start of this one
+        assert_near(grid.seek_bars(bar(3) + Duration::from_millis(200), -1).unwrap(), bar(2));
+        assert_near(grid.seek_bars(bar(3) + Duration::from_secs(1), -1).unwrap(), bar(3));
+        assert_near(grid.seek_bars(bar(3) + Duration::from_secs(1), -2).unwrap(), bar(2));
+        assert_near(grid.seek_bars(bar(3) + Duration::from_secs(1), 1).unwrap(), bar(4));
+        assert_near(grid.seek_bars(bar(1), -5).unwrap(), bar(0));
+        assert_eq!(grid.seek_bars(bar(7), 1), None);
+    }
+
+    #[test]
+    fn no_accents_counts_in_four() {
+        let clicks = (0..12).map(|idx| (Duration::from_millis(500) * idx, 0.8)).collect::<Vec<_>>();
+        let map = grid(&clicks).tempo_map();
+        assert_eq!(map.len(), 3);
+        assert!(map.iter().all(|bar| bar.beats_per_bar == DEFAULT_BEATS_PER_BAR));
+        assert_near(map[1].start, Duration::from_secs(2));
+    }
+
+    #[test]
+    fn too_few_clicks_is_no_grid() {
+        let song = click_track(&bars(Duration::ZERO, 120.0, 4, 1), Duration::from_secs(1));
+        assert!(BeatGrid::from_click(&song).is_none());
+        assert!(BeatGrid::from_click(&click_track(&[], Duration::from_secs(2))).is_none());
+    }
+
+    #[test]
+    fn time_signature_guesses() {
+        let signature = |beats_per_bar| {
+            TempoBar {
+                start: Duration::ZERO,
+                bpm: 120.0,
+                beats_per_bar,
+            }
+            .time_signature()
+        };
+        assert_eq!(signature(3), (3, 4));
+        assert_eq!(signature(4), (4, 4));
+        assert_eq!(signature(5), (5, 4));
+        assert_eq!(signature(6), (6, 8));
+        assert_eq!(signature(7), (7, 4));
+        assert_eq!(signature(9), (9, 8));
+        assert_eq!(signature(12), (12, 8));
+    }
+}
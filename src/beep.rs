@@ -0,0 +1,120 @@
+//! The sounds the player makes itself: the beep, test signals for checking a device channel, and clicks generated from a
+//! tempo.
+use std::io::{Cursor, Read, Seek};
+use std::time::Duration;
+
+use color_eyre::eyre::Result;
+use symphonia::core::io::{MediaSource, MediaSourceStream, MediaSourceStreamOptions};
+use symphonia::core::probe::Hint;
+
+use super::audio::Song;
+
+/// The beep played when testing a channel, as a WAV file
+pub const BEEP_BYTES: &[u8] = include_bytes!("../assets/beep.wav");
+
+// Sample rate used for generated audio. The player resamples to the device rate.
+const GENERATED_SAMPLE_RATE: u32 = 48000;
+
+/// What to play when testing a device channel
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TestSignal {
+    /// The beep
+    Beep,
+    /// A second of a 440 Hz sine
+    Tone,
+}
+
+/// A [`MediaSource`] over audio bundled into the binary
+pub struct BeepMediaSource {
+    data: &'static [u8],
+    cursor: Cursor<&'static [u8]>,
+}
+
+impl BeepMediaSource {
+    /// Reads the given bytes, which have to hold a whole file
+    pub fn new(data: &'static [u8]) -> Self {
+        Self { data, cursor: Cursor::new(data) }
+    }
+}
+
+impl Read for BeepMediaSource {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, std::io::Error> {
+        self.cursor.read(buf)
+    }
+}
+
+impl Seek for BeepMediaSource {
+    fn seek(&mut self, pos: std::io::SeekFrom) -> Result<u64, std::io::Error> {
+        self.cursor.seek(pos)
+    }
+}
+
+impl MediaSource for BeepMediaSource {
+    fn is_seekable(&self) -> bool {
+        false
+    }
+
+    fn byte_len(&self) -> Option<u64> {
+        Some(self.data.len() as u64)
+    }
+}
+
+/// Decodes the embedded beep
+pub fn beep_song() -> Result<Song> {
+    let beep_source = Box::new(BeepMediaSource::new(BEEP_BYTES));
+    let beep_options = MediaSourceStreamOptions { buffer_len: 64 * 1024 };
+
+    let beep_stream = MediaSourceStream::new(beep_source, beep_options);
+    Song::new(Box::new(beep_stream), &Hint::new(), None)
+}
+
+/// A mono sine wave with short fades so it doesn't click
+pub fn tone_song(frequency: f32, duration: Duration) -> Result<Song> {
+    let frame_count = (duration.as_secs_f32() * GENERATED_SAMPLE_RATE as f32) as usize;
+    let fade_frames = (GENERATED_SAMPLE_RATE / 100) as usize; // 10ms
+
+    let samples = (0..frame_count)
+        .map(|frame| {
+            let t = frame as f32 / GENERATED_SAMPLE_RATE as f32;
+            let fade = (frame.min(frame_count - frame) as f32 / fade_frames as f32).min(1.0);
+            (2.0 * std::f32::consts::PI * frequency * t).sin() * 0.5 * fade
+        })
+        .collect();
+
+    Song::from_samples(vec![samples], GENERATED_SAMPLE_RATE)
+}
+
+/// Sample rate of the click. Lower than the test signals since it lasts as long as the song.
+pub const CLICK_SAMPLE_RATE: u32 = 24000;
+
+/// A metronome click at the given tempo, with the first beat of every bar accented
+pub fn click_song(bpm: usize, beats_per_bar: usize, duration: Duration) -> Result<Song> {
+    let frame_count = (duration.as_secs_f64() * CLICK_SAMPLE_RATE as f64) as usize;
+    let beat_frames = (60.0 / bpm.max(1) as f64 * CLICK_SAMPLE_RATE as f64) as usize;
+
+    let mut samples = vec![0.0; frame_count];
+    for (beat, start) in (0..frame_count).step_by(beat_frames.max(1)).enumerate() {
+        let frequency = if beat % beats_per_bar.max(1) == 0 { 1500.0 } else { 1000.0 };
+        add_click(&mut samples, start, frequency, 0.6);
+    }
+
+    Song::from_samples(vec![samples], CLICK_SAMPLE_RATE)
+}
+
+/// Mixes a single decaying click into the samples, starting at the given frame
+pub fn add_click(samples: &mut [f32], start: usize, frequency: f32, level: f32) {
+    let click_frames = (CLICK_SAMPLE_RATE / 40) as usize; // 25ms
+    for (frame, sample) in samples.iter_mut().skip(start).take(click_frames).enumerate() {
+        let t = frame as f32 / CLICK_SAMPLE_RATE as f32;
+        let decay = 1.0 - frame as f32 / click_frames as f32;
+        *sample = (*sample + (2.0 * std::f32::consts::PI * frequency * t).sin() * level * decay).clamp(-1.0, 1.0);
+    }
+}
+
+/// The song for a test signal
+pub fn test_signal_song(signal: TestSignal) -> Result<Song> {
+    match signal {
+        TestSignal::Beep => beep_song(),
+        TestSignal::Tone => tone_song(440.0, Duration::from_secs(1)),
+    }
+}
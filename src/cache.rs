@@ -0,0 +1,163 @@
+//! Songs are extracted into a cache folder rather than next to their archives, so the library can be read-only,
+//! e.g. on a NAS or a USB drive. The cache is kept under a size limit. Once over it, the songs used longest ago go first,
+//! going by the play history and when they were extracted. Pinned songs are never evicted.
+use std::{
+    collections::BTreeSet,
+    env, fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::SystemTime,
+};
+
+use color_eyre::eyre::Result;
+use lazy_static::lazy_static;
+use log::{info, warn};
+
+use super::{history::History, loader::is_extracting};
+
+/// The cache's size limit unless the user sets one, 20 GB
+pub const DEFAULT_QUOTA_MB: u64 = 20 * 1024;
+
+/// Where songs are extracted to, and how much of the disk they may take
+#[derive(Debug, Clone, PartialEq)]
+pub struct CacheSettings {
+    /// The folder songs are extracted into, one folder each
+    pub folder: PathBuf,
+    /// The size limit, in megabytes
+    pub quota_mb: u64,
+    /// Songs that are never evicted, by file name
+    pub pinned: BTreeSet<String>,
+}
+
+impl Default for CacheSettings {
+    fn default() -> Self {
+        CacheSettings {
+            folder: CacheSettings::default_folder(),
+            quota_mb: DEFAULT_QUOTA_MB,
+            pinned: BTreeSet::new(),
+        }
+    }
+}
+
+impl CacheSettings {
+    /// Beside the configuration, or the temp folder when that can't be found
+    pub fn default_folder() -> PathBuf {
+        match confy::get_configuration_file_path("drum-weaver", None) {
+            Ok(config_path) => config_path.with_file_name("cache"),
+            Err(_) => env::temp_dir().join("drum-weaver"),
+        }
+    }
+}
+
+lazy_static! {
+    static ref SETTINGS: Mutex<CacheSettings> = Mutex::new(CacheSettings::default());
+}
+
+// A song's folder in the cache
+struct CachedSong {
+    file_name: String,
+    path: PathBuf,
+    size: u64,
+    last_used: SystemTime,
+}
+
+/// Sets where songs are extracted to from now on, and the limit the cache is kept to
+pub fn set_cache(settings: CacheSettings) {
+    *SETTINGS.lock().unwrap() = settings;
+}
+
+/// The folder songs are extracted into
+pub fn cache_folder() -> PathBuf {
+    SETTINGS.lock().unwrap().folder.clone()
+}
+
+/// Where a song is extracted to
+pub fn song_folder(file_name: &str) -> PathBuf {
+    SETTINGS.lock().unwrap().folder.join(file_name)
+}
+
+/// Evicts the songs used longest ago until the cache fits its limit. The songs in `keep` stay whatever happens.
+pub fn enforce_quota(keep: &[&str]) -> Result<()> {
+    let settings = SETTINGS.lock().unwrap().clone();
+    let quota = settings.quota_mb * 1024 * 1024;
+    let mut songs = cached_songs(&settings.folder)?;
+    let mut total = songs.iter().map(|song| song.size).sum::<u64>();
+    if total <= quota {
+        return Ok(());
+    }
+
+    let last_played = History::load().last_played();
+    for song in songs.iter_mut() {
+        if let Some(played) = last_played.get(&song.file_name) {
+            song.last_used = song.last_used.max(SystemTime::from(*played));
+        }
+    }
+    songs.sort_by_key(|song| song.last_used);
+
+    for song in songs {
+        if total <= quota {
+            break;
+        }
+        if settings.pinned.contains(&song.file_name) || keep.contains(&song.file_name.as_str()) || is_extracting(&song.file_name) {
+            continue;
+        }
+        match fs::remove_dir_all(&song.path) {
+            Ok(()) => {
+                info!("Evicted {} from the cache", song.file_name);
+                total -= song.size;
+            }
+            Err(err) => warn!("Unable to evict {}: {}", song.path.display(), err),
+        }
+    }
+
+    if total > quota {
+        warn!("The cache is still over {} MB. The rest is pinned or in use.", settings.quota_mb);
+    }
+    Ok(())
+}
+
+/// Removes every song that isn't pinned or being extracted. Returns how many songs and bytes went.
+pub fn clear_cache() -> Result<(usize, u64)> {
+    let settings = SETTINGS.lock().unwrap().clone();
+    let mut removed = (0, 0);
+    for song in cached_songs(&settings.folder)? {
+        if settings.pinned.contains(&song.file_name) || is_extracting(&song.file_name) {
+            continue;
+        }
+        match fs::remove_dir_all(&song.path) {
+            Ok(()) => removed = (removed.0 + 1, removed.1 + song.size),
+            Err(err) => warn!("Unable to remove {}: {}", song.path.display(), err),
+        }
+    }
+    info!("Cleared {} songs from the cache", removed.0);
+    Ok(removed)
+}
+
+// The folder is only as old as its last file, so the time it was modified is when the song was extracted
+fn cached_songs(folder: &Path) -> Result<Vec<CachedSong>> {
+    if !folder.exists() {
+        return Ok(vec![]);
+    }
+
+    let mut songs = vec![];
+    for entry in fs::read_dir(folder)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if !metadata.is_dir() {
+            continue;
+        }
+
+        let size = fs::read_dir(entry.path())?
+            .filter_map(|file| file.ok()?.metadata().ok())
+            .filter(|metadata| metadata.is_file())
+            .map(|metadata| metadata.len())
+            .sum();
+        songs.push(CachedSong {
+            file_name: entry.file_name().to_string_lossy().to_string(),
+            path: entry.path(),
+            size,
+            last_used: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+        });
+    }
+    Ok(songs)
+}
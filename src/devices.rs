@@ -0,0 +1,229 @@
+//! The audio hosts and their output devices: finding a device by name, what each supports, and the sample rate and
+//! buffer size picked for it.
+use cpal::traits::DeviceTrait;
+use cpal::traits::HostTrait;
+use cpal::{Device, Host, HostId, SupportedBufferSize};
+use lazy_static::lazy_static;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+/// An output device of the current host and what it supports
+#[derive(Debug, Deserialize, Clone)]
+#[allow(dead_code)]
+pub struct DeviceDetail {
+    /// The device's name, which is how it is remembered
+    pub name: String,
+    /// Its place in the host's list of output devices
+    pub position: usize,
+    /// Each output configuration it supports
+    pub configs: Vec<DeviceConfigDetail>,
+}
+
+/// One entry of supported_output_configs, flattened for display
+#[derive(Debug, Deserialize, Clone)]
+pub struct DeviceConfigDetail {
+    /// Channels in this configuration
+    pub channels: u16,
+    /// The lowest sample rate it supports
+    pub min_sample_rate: u32,
+    /// The highest sample rate it supports
+    pub max_sample_rate: u32,
+    /// The sample format, e.g. f32 or i16
+    pub sample_format: String,
+    /// None when the host doesn't report it
+    pub buffer_size: Option<(u32, u32)>,
+}
+
+/// What the user prefers for a device. Anything left empty is picked automatically.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct DevicePreference {
+    /// None picks the device's default
+    pub sample_rate: Option<u32>,
+    /// Frames per buffer. None picks the device's default.
+    pub buffer_size: Option<u32>,
+}
+
+// Sample rates and buffer sizes offered when cycling preferences in the device view
+const COMMON_SAMPLE_RATES: [u32; 6] = [44100, 48000, 88200, 96000, 176400, 192000];
+const COMMON_BUFFER_SIZES: [u32; 6] = [128, 256, 512, 1024, 2048, 4096];
+
+impl DeviceDetail {
+    /// The most channels any of its configurations has
+    pub fn max_channels(&self) -> u16 {
+        self.configs.iter().map(|c| c.channels).max().unwrap_or(0)
+    }
+
+    /// The common sample rates this device claims to support
+    pub fn sample_rates(&self) -> Vec<u32> {
+        COMMON_SAMPLE_RATES
+            .iter()
+            .copied()
+            .filter(|rate| self.configs.iter().any(|c| (c.min_sample_rate..=c.max_sample_rate).contains(rate)))
+            .collect()
+    }
+
+    /// The common buffer sizes this device claims to support. If the host doesn't say, we offer them all.
+    pub fn buffer_sizes(&self) -> Vec<u32> {
+        let ranges = self.configs.iter().filter_map(|c| c.buffer_size).collect::<Vec<_>>();
+        COMMON_BUFFER_SIZES
+            .iter()
+            .copied()
+            .filter(|size| ranges.is_empty() || ranges.iter().any(|(min, max)| (min..=max).contains(&size)))
+            .collect()
+    }
+}
+
+impl DeviceConfigDetail {
+    /// A line for the device view, e.g. "2 ch | f32 | 44100-96000 Hz | buffer 64-4096"
+    pub fn describe(&self) -> String {
+        let rates = if self.min_sample_rate == self.max_sample_rate {
+            format!("{} Hz", self.min_sample_rate)
+        } else {
+            format!("{}-{} Hz", self.min_sample_rate, self.max_sample_rate)
+        };
+
+        let buffer = match self.buffer_size {
+            Some((min, max)) => format!("buffer {}-{}", min, max),
+            None => "buffer unknown".to_string(),
+        };
+
+        format!("{} ch | {} | {} | {}", self.channels, self.sample_format, rates, buffer)
+    }
+}
+
+/// Picks the next value in a list of options, where None (automatic) comes before the first option
+pub fn cycle_preference(current: Option<u32>, options: &[u32]) -> Option<u32> {
+    match current.and_then(|value| options.iter().position(|o| *o == value)) {
+        None if current.is_none() => options.first().copied(),
+        Some(idx) if idx + 1 < options.len() => Some(options[idx + 1]),
+        _ => None,
+    }
+}
+
+lazy_static! {
+    static ref DEVICES: Mutex<Vec<DeviceDetail>> = Mutex::new(Vec::new());
+    static ref HOST_ID: Mutex<Option<HostId>> = Mutex::new(None); // None means the platform default
+}
+
+/// The audio host APIs compiled in and available on this machine (WASAPI, ASIO, ALSA, JACK, ...)
+pub fn available_hosts() -> Vec<String> {
+    cpal::available_hosts().iter().map(|id| id.name().to_string()).collect()
+}
+
+/// Switches the host API used for every device lookup. Unknown names fall back to the platform default.
+pub fn set_host(name: Option<&str>) {
+    let host_id = name.and_then(|name| cpal::available_hosts().into_iter().find(|id| id.name() == name));
+    if name.is_some() && host_id.is_none() {
+        warn!("Host {:?} is not available. Using the default host.", name);
+    }
+
+    *HOST_ID.lock().unwrap() = host_id;
+    DEVICES.lock().unwrap().clear();
+}
+
+/// The host picked with [`set_host`], or the platform default
+pub fn current_host() -> Host {
+    let host_id = *HOST_ID.lock().unwrap();
+    match host_id.map(cpal::host_from_id) {
+        Some(Ok(host)) => host,
+        Some(Err(err)) => {
+            warn!("Unable to open host: {}. Using the default host.", err);
+            cpal::default_host()
+        }
+        None => cpal::default_host(),
+    }
+}
+
+/// The name of the [`current_host`]
+pub fn current_host_name() -> String {
+    current_host().id().name().to_string()
+}
+
+/// Under JACK we register our own named ports rather than going through a cpal device
+pub fn is_jack_host() -> bool {
+    cfg!(feature = "jack") && current_host_name() == "JACK"
+}
+
+/// Returns the last scanned list of output devices, scanning the host if we haven't yet
+pub fn read_devices() -> Vec<DeviceDetail> {
+    let devices = DEVICES.lock().unwrap().clone();
+
+    if devices.is_empty() {
+        return rescan_devices();
+    }
+
+    devices
+}
+
+/// Asks the host for its current output devices and replaces the cached list.
+/// Used when devices are plugged in or removed while the app is running.
+pub fn rescan_devices() -> Vec<DeviceDetail> {
+    let host = current_host();
+    let available_devices = match host.output_devices() {
+        Ok(devices) => devices.collect::<Vec<_>>(),
+        Err(err) => {
+            warn!("Unable to enumerate output devices: {}", err);
+            Vec::new()
+        }
+    };
+
+    let mut scanned = Vec::new();
+    for (position, device) in available_devices.iter().enumerate() {
+        if let Ok(name) = device.name() {
+            info!("Found device: {}", name);
+            let configs = read_device_configs(device);
+            scanned.push(DeviceDetail { name, position, configs });
+        }
+    }
+
+    let mut devices = DEVICES.lock().unwrap();
+    *devices = scanned;
+    devices.clone()
+}
+
+fn read_device_configs(device: &Device) -> Vec<DeviceConfigDetail> {
+    let supported_configs = match device.supported_output_configs() {
+        Ok(configs) => configs,
+        Err(err) => {
+            warn!("Unable to read configurations for device: {}", err);
+            return Vec::new();
+        }
+    };
+
+    supported_configs
+        .map(|config| DeviceConfigDetail {
+            channels: config.channels(),
+            min_sample_rate: config.min_sample_rate().0,
+            max_sample_rate: config.max_sample_rate().0,
+            sample_format: config.sample_format().to_string(),
+            buffer_size: match config.buffer_size() {
+                SupportedBufferSize::Range { min, max } => Some((*min, *max)),
+                SupportedBufferSize::Unknown => None,
+            },
+        })
+        .collect()
+}
+
+/// Looks up a live output device by name. Returns None if it is not currently attached.
+pub fn find_device(name: &str) -> Option<Device> {
+    let host = current_host();
+    let mut available_devices = host.output_devices().ok()?;
+    available_devices.find(|d| d.name().ok().as_deref() == Some(name))
+}
+
+/// The host's default output, if it has one
+pub fn default_device() -> Option<Device> {
+    current_host().default_output_device()
+}
+
+/// Resolves the configured device, falling back to the default output when it is missing.
+/// The returned flag is true when the fallback was used.
+pub fn resolve_device(name: Option<&str>) -> Option<(Device, bool)> {
+    if let Some(name) = name {
+        if let Some(device) = find_device(name) {
+            return Some((device, false));
+        }
+    }
+
+    default_device().map(|device| (device, name.is_some()))
+}
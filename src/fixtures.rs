@@ -0,0 +1,24 @@
+// Test fixtures, shared by the library's tests and the app's. The app includes this file with #[path].
+
+// A PCM WAV at 44.1 kHz, little endian, from interleaved samples
+pub fn wav(channels: u16, bits: u16, samples: &[i32]) -> Vec<u8> {
+    let bytes_per_sample = bits as usize / 8;
+    let data_len = (samples.len() * bytes_per_sample) as u32;
+    let mut wav = Vec::new();
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    wav.extend_from_slice(&channels.to_le_bytes());
+    wav.extend_from_slice(&44100u32.to_le_bytes());
+    wav.extend_from_slice(&(44100 * channels as u32 * bytes_per_sample as u32).to_le_bytes()); // bytes a second
+    wav.extend_from_slice(&(channels * bytes_per_sample as u16).to_le_bytes()); // bytes a frame
+    wav.extend_from_slice(&bits.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_len.to_le_bytes());
+    for sample in samples {
+        wav.extend_from_slice(&sample.to_le_bytes()[..bytes_per_sample]);
+    }
+    wav
+}
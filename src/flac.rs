@@ -0,0 +1,636 @@
+// A small FLAC encoder, enough to keep songs losslessly in a little over half the space of their WAVs.
+// Blocks are 4096 frames. Each channel takes whichever fixed predictor suits the block best, stereo songs store the
+// difference of the channels alongside one of them or their average when that is smaller, and the residual is Rice coded
+// in partitions. What goes in is fingerprinted, so the file can be decoded again and checked before anything is let go of.
+use std::io::{Seek, SeekFrom, Write};
+
+use color_eyre::eyre::{bail, ensure, Report, Result};
+use symphonia::core::{
+    audio::{AudioBufferRef, SampleBuffer},
+    codecs::DecoderOptions,
+    errors::Error as SymphoniaError,
+    formats::FormatOptions,
+    io::{MediaSource, MediaSourceStream, MediaSourceStreamOptions},
+    meta::MetadataOptions,
+};
+use symphonia::default;
+
+use super::audio::Hint;
+
+const BLOCK_SIZE: usize = 4096;
+const MAX_PARTITION_ORDER: u32 = 8;
+const MAX_RICE_PARAMETER: u32 = 14; // 15 means the partition is stored raw
+const MAX_BITS: u32 = 24;
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+// Converts integer PCM, e.g. a WAV, to FLAC. Calls progress with the frames so far and the total, when it is known.
+// Also returns the fingerprint of what was encoded, to check the file against.
+pub fn transcode<W: Write + Seek>(source: Box<dyn MediaSource>, hint: &Hint, out: W, progress: &mut dyn FnMut(u64, Option<u64>) -> bool) -> Result<(W, Fingerprint)> {
+    let mut out = Some(out);
+    let mut writer = None;
+    let mut fingerprint = Fingerprint::new();
+    read_pcm(source, hint, &mut |format, samples| {
+        if writer.is_none() {
+            writer = Some(FlacWriter::new(out.take().unwrap(), format.sample_rate, format.channels, format.bits)?);
+        }
+        let writer = writer.as_mut().unwrap();
+        ensure!(writer.bits == format.bits && writer.channels == format.channels, "The format changes partway through.");
+        writer.write(samples)?;
+
+        fingerprint.add(format, samples);
+        ensure!(progress(fingerprint.frames(), format.total_frames), "Converting was cancelled.");
+        Ok(())
+    })?;
+
+    Ok((writer.ok_or_else(|| Report::msg("No song data decoded."))?.finish()?, fingerprint))
+}
+
+// Decodes the source to compare with what transcode returned
+pub fn fingerprint(source: Box<dyn MediaSource>, hint: &Hint) -> Result<Fingerprint> {
+    let mut fingerprint = Fingerprint::new();
+    read_pcm(source, hint, &mut |format, samples| {
+        fingerprint.add(format, samples);
+        Ok(())
+    })?;
+    Ok(fingerprint)
+}
+
+// The format and every sample of a stream, hashed, to tell a FLAC holds exactly the song it was made from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fingerprint {
+    sample_rate: u32,
+    channels: usize,
+    bits: u32,
+    samples: u64,
+    hash: u64, // FNV-1a, a sample at a time
+}
+
+impl Fingerprint {
+    fn new() -> Self {
+        Fingerprint {
+            sample_rate: 0,
+            channels: 0,
+            bits: 0,
+            samples: 0,
+            hash: FNV_OFFSET,
+        }
+    }
+
+    fn add(&mut self, format: &PcmFormat, samples: &[i32]) {
+        (self.sample_rate, self.channels, self.bits) = (format.sample_rate, format.channels, format.bits);
+        self.samples += samples.len() as u64;
+        for sample in samples {
+            self.hash = (self.hash ^ *sample as u32 as u64).wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    fn frames(&self) -> u64 {
+        self.samples / self.channels.max(1) as u64
+    }
+}
+
+struct PcmFormat {
+    sample_rate: u32,
+    channels: usize,
+    bits: u32,
+    total_frames: Option<u64>, // when it is known
+}
+
+// Decodes the source a packet at a time, handing on interleaved samples at their own bit depth
+fn read_pcm(source: Box<dyn MediaSource>, hint: &Hint, each: &mut dyn FnMut(&PcmFormat, &[i32]) -> Result<()>) -> Result<()> {
+    let media_source_stream = MediaSourceStream::new(source, MediaSourceStreamOptions::default());
+    let mut probe_result = default::get_probe().format(hint, media_source_stream, &FormatOptions::default(), &MetadataOptions::default())?;
+    let codec_params = probe_result.format.default_track().ok_or_else(|| Report::msg("No default track in media file."))?.codec_params.clone();
+    let mut decoder = default::get_codecs().make(&codec_params, &DecoderOptions::default())?;
+
+    loop {
+        let packet = match probe_result.format.next_packet() {
+            Ok(packet) => packet,
+            Err(SymphoniaError::IoError(_)) => break,
+            Err(e) => return Err(e.into()),
+        };
+        let decoded = decoder.decode(&packet)?;
+        if decoded.frames() == 0 {
+            continue;
+        }
+
+        // samples come out scaled to 32 bits, whatever they were. FLAC always comes out as 32 bits, so its stream info tells.
+        let buffer_bits = match decoded {
+            AudioBufferRef::U8(_) | AudioBufferRef::S8(_) => 8,
+            AudioBufferRef::U16(_) | AudioBufferRef::S16(_) => 16,
+            AudioBufferRef::U24(_) | AudioBufferRef::S24(_) => 24,
+            AudioBufferRef::U32(_) | AudioBufferRef::S32(_) => 32,
+            AudioBufferRef::F32(_) | AudioBufferRef::F64(_) => bail!("Floating point samples can't be stored as FLAC."),
+        };
+        let bits = codec_params.bits_per_sample.filter(|bits| *bits < buffer_bits).unwrap_or(buffer_bits);
+        let spec = *decoded.spec();
+        let mut samples = SampleBuffer::<i32>::new(decoded.frames() as u64, spec);
+        samples.copy_interleaved_ref(decoded);
+
+        let format = PcmFormat {
+            sample_rate: spec.rate,
+            channels: spec.channels.count(),
+            bits,
+            total_frames: codec_params.n_frames,
+        };
+        let shift = 32 - bits;
+        each(&format, &samples.samples().iter().map(|sample| sample >> shift).collect::<Vec<_>>())?;
+    }
+    Ok(())
+}
+
+pub struct FlacWriter<W: Write + Seek> {
+    out: W,
+    sample_rate: u32,
+    channels: usize,
+    bits: u32,
+    pending: Vec<Vec<i32>>, // per channel, less than a block
+    frame_number: u64,
+    total_frames: u64,
+    frame_sizes: Option<(usize, usize)>, // smallest and largest, in bytes
+}
+
+impl<W: Write + Seek> FlacWriter<W> {
+    pub fn new(mut out: W, sample_rate: u32, channels: usize, bits: u32) -> Result<Self> {
+        ensure!((1..=8).contains(&channels), "FLAC holds 1 to 8 channels, not {}.", channels);
+        ensure!((4..=MAX_BITS).contains(&bits), "Only 4 to {} bit samples can be stored as FLAC, not {}.", MAX_BITS, bits);
+        ensure!(sample_rate > 0 && sample_rate < 1 << 20, "{} Hz can't be stored as FLAC.", sample_rate);
+
+        out.write_all(b"fLaC")?;
+        let mut writer = FlacWriter {
+            out,
+            sample_rate,
+            channels,
+            bits,
+            pending: vec![Vec::with_capacity(BLOCK_SIZE); channels],
+            frame_number: 0,
+            total_frames: 0,
+            frame_sizes: None,
+        };
+        writer.write_stream_info()?; // again once the totals are known
+        Ok(writer)
+    }
+
+    // Interleaved samples
+    pub fn write(&mut self, samples: &[i32]) -> Result<()> {
+        for frame in samples.chunks_exact(self.channels) {
+            for (channel, sample) in frame.iter().enumerate() {
+                self.pending[channel].push(*sample);
+            }
+            if self.pending[0].len() == BLOCK_SIZE {
+                self.write_pending()?;
+            }
+        }
+        Ok(())
+    }
+
+    // Writes what is left and fills in the stream info
+    pub fn finish(mut self) -> Result<W> {
+        if !self.pending[0].is_empty() {
+            self.write_pending()?;
+        }
+        self.out.seek(SeekFrom::Start(4))?;
+        self.write_stream_info()?;
+        self.out.seek(SeekFrom::End(0))?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+
+    fn write_stream_info(&mut self) -> Result<()> {
+        let (min_frame_size, max_frame_size) = self.frame_sizes.unwrap_or((0, 0)); // 0 is unknown
+        let mut info = BitWriter::default();
+        info.write(1, 1); // the last metadata block
+        info.write(0, 7); // STREAMINFO
+        info.write(34, 24);
+        info.write(BLOCK_SIZE as u64, 16); // smallest block, not counting the last
+        info.write(BLOCK_SIZE as u64, 16);
+        info.write(min_frame_size as u64, 24);
+        info.write(max_frame_size as u64, 24);
+        info.write(self.sample_rate as u64, 20);
+        info.write(self.channels as u64 - 1, 3);
+        info.write(self.bits as u64 - 1, 5);
+        info.write(self.total_frames, 36);
+        info.write(0, 64); // no MD5
+        info.write(0, 64);
+        self.out.write_all(&info.bytes)?;
+        Ok(())
+    }
+
+    fn write_pending(&mut self) -> Result<()> {
+        let block = self.pending.iter_mut().map(std::mem::take).collect::<Vec<_>>();
+
+        let (assignment, subframes) = if self.channels == 2 {
+            stereo_subframes(&block[0], &block[1], self.bits)
+                .into_iter()
+                .min_by_key(|(_, subframes)| subframes[0].len() + subframes[1].len())
+                .unwrap()
+        } else {
+            (self.channels as u64 - 1, block.iter().map(|channel| encode_subframe(channel, self.bits)).collect())
+        };
+        self.write_frame(block[0].len(), assignment, &subframes)
+    }
+
+    fn write_frame(&mut self, block_size: usize, assignment: u64, subframes: &[BitWriter]) -> Result<()> {
+        let mut frame = BitWriter::default();
+        frame.write(0b11111111111110, 14); // sync
+        frame.write(0, 1);
+        frame.write(0, 1); // fixed block size
+        frame.write(0b0111, 4); // the block size comes after the frame number, in 16 bits
+        frame.write(0b0000, 4); // the sample rate and size are in the stream info
+        frame.write(assignment, 4);
+        frame.write(0b000, 3);
+        frame.write(0, 1);
+        write_utf8(&mut frame, self.frame_number);
+        frame.write(block_size as u64 - 1, 16);
+        frame.write(crc8(&frame.bytes) as u64, 8);
+        for subframe in subframes.iter() {
+            frame.append(subframe);
+        }
+        frame.align();
+        frame.write(crc16(&frame.bytes) as u64, 16);
+
+        self.out.write_all(&frame.bytes)?;
+        let size = frame.bytes.len();
+        self.frame_sizes = Some(self.frame_sizes.map_or((size, size), |(min, max)| (min.min(size), max.max(size))));
+        self.frame_number += 1;
+        self.total_frames += block_size as u64;
+        Ok(())
+    }
+}
+
+// Every way to store a stereo block, by channel assignment: independent channels, or the difference of the channels, which
+// needs a bit more, with the left, the right or their average
+fn stereo_subframes(left: &[i32], right: &[i32], bits: u32) -> Vec<(u64, Vec<BitWriter>)> {
+    let side = left.iter().zip(right.iter()).map(|(left, right)| left - right).collect::<Vec<_>>();
+    let mid = left.iter().zip(right.iter()).map(|(left, right)| (left + right) >> 1).collect::<Vec<_>>();
+    let (left, right) = (encode_subframe(left, bits), encode_subframe(right, bits));
+    let (side, mid) = (encode_subframe(&side, bits + 1), encode_subframe(&mid, bits));
+    vec![
+        (0b0001, vec![left.clone(), right.clone()]),
+        (0b1000, vec![left, side.clone()]),
+        (0b1001, vec![side.clone(), right]),
+        (0b1010, vec![mid, side]),
+    ]
+}
+
+// The smallest of a constant, a fixed predictor of order 0 to 4, and the samples as they are
+fn encode_subframe(samples: &[i32], bits: u32) -> BitWriter {
+    let mut out = BitWriter::default();
+    if samples.iter().all(|sample| *sample == samples[0]) {
+        out.write(0, 1);
+        out.write(0b000000, 6); // constant
+        out.write(0, 1); // no wasted bits
+        out.write_signed(samples[0] as i64, bits);
+        return out;
+    }
+
+    let (order, residual) = (0..=4.min(samples.len() - 1))
+        .map(|order| (order, fixed_residual(samples, order)))
+        .min_by_key(|(_, residual)| residual.iter().map(|value| value.unsigned_abs()).sum::<u64>())
+        .unwrap();
+    out.write(0, 1);
+    out.write(0b001000 | order as u64, 6); // fixed
+    out.write(0, 1);
+    for sample in samples[..order].iter() {
+        out.write_signed(*sample as i64, bits);
+    }
+    write_residual(&mut out, &residual, order, samples.len());
+
+    if out.len() > 8 + samples.len() * bits as usize {
+        out = BitWriter::default();
+        out.write(0, 1);
+        out.write(0b000001, 6); // verbatim
+        out.write(0, 1);
+        for sample in samples.iter() {
+            out.write_signed(*sample as i64, bits);
+        }
+    }
+    out
+}
+
+fn fixed_residual(samples: &[i32], order: usize) -> Vec<i64> {
+    let s = |idx: usize| samples[idx] as i64;
+    (order..samples.len())
+        .map(|i| match order {
+            0 => s(i),
+            1 => s(i) - s(i - 1),
+            2 => s(i) - 2 * s(i - 1) + s(i - 2),
+            3 => s(i) - 3 * s(i - 1) + 3 * s(i - 2) - s(i - 3),
+            _ => s(i) - 4 * s(i - 1) + 6 * s(i - 2) - 4 * s(i - 3) + s(i - 4),
+        })
+        .collect()
+}
+
+// Rice coded, split into the partitions that code smallest. The first partition is short of the warm-up samples.
+fn write_residual(out: &mut BitWriter, residual: &[i64], order: usize, block_size: usize) {
+    let folded = residual
+        .iter()
+        .map(|value| if *value >= 0 { (*value as u64) << 1 } else { ((-*value as u64) << 1) - 1 })
+        .collect::<Vec<_>>();
+
+    let max_order = (0..=MAX_PARTITION_ORDER)
+        .take_while(|p| block_size.is_multiple_of(1 << p) && (block_size >> p) > order)
+        .last()
+        .unwrap_or(0);
+    let partition_len = |p: u32, partition: usize| (block_size >> p) - if partition == 0 { order } else { 0 };
+
+    // the sum and length of each of the smallest partitions, merged pairwise for each order below
+    let mut start = 0;
+    let mut partitions = (0..1 << max_order)
+        .map(|partition| {
+            let len = partition_len(max_order, partition);
+            let sum = folded[start..start + len].iter().sum::<u64>();
+            start += len;
+            (sum, len)
+        })
+        .collect::<Vec<_>>();
+    let mut best: Option<(u64, u32, Vec<u32>)> = None;
+    for p in (0..=max_order).rev() {
+        let parameters = partitions.iter().map(|(sum, len)| rice_parameter(*sum, *len)).collect::<Vec<_>>();
+        let cost = parameters.iter().map(|(_, cost)| 4 + cost).sum::<u64>();
+        if best.as_ref().is_none_or(|(best_cost, ..)| cost < *best_cost) {
+            best = Some((cost, p, parameters.into_iter().map(|(parameter, _)| parameter).collect()));
+        }
+        partitions = partitions
+            .chunks(2)
+            .map(|pair| pair.iter().fold((0, 0), |(sum, len), partition| (sum + partition.0, len + partition.1)))
+            .collect();
+    }
+    let (_, p, parameters) = best.unwrap();
+
+    out.write(0b00, 2); // Rice coding with 4 bit parameters
+    out.write(p as u64, 4);
+    let mut start = 0;
+    for (partition, parameter) in parameters.into_iter().enumerate() {
+        out.write(parameter as u64, 4);
+        let len = partition_len(p, partition);
+        for value in folded[start..start + len].iter() {
+            out.write_unary(value >> parameter);
+            out.write(*value, parameter);
+        }
+        start += len;
+    }
+}
+
+// The parameter, and about how many bits it codes the partition in
+fn rice_parameter(sum: u64, len: usize) -> (u32, u64) {
+    (0..=MAX_RICE_PARAMETER)
+        .map(|parameter| (parameter, len as u64 * (parameter as u64 + 1) + (sum >> parameter)))
+        .min_by_key(|(_, cost)| *cost)
+        .unwrap()
+}
+
+// Frame numbers are coded like UTF-8, stretched to 36 bits
+fn write_utf8(out: &mut BitWriter, value: u64) {
+    if value < 0x80 {
+        out.write(value, 8);
+        return;
+    }
+    let extra = match value {
+        0..=0x7FF => 1,
+        0x800..=0xFFFF => 2,
+        0x10000..=0x1FFFFF => 3,
+        0x200000..=0x3FFFFFF => 4,
+        0x4000000..=0x7FFFFFFF => 5,
+        _ => 6,
+    };
+    let lead = !(0xFFu64 >> (extra + 1)) & 0xFF;
+    out.write(lead | (value >> (6 * extra)), 8);
+    for idx in (0..extra).rev() {
+        out.write(0x80 | ((value >> (6 * idx)) & 0x3F), 8);
+    }
+}
+
+fn crc8(data: &[u8]) -> u8 {
+    let mut crc = 0u8;
+    for byte in data {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 { (crc << 1) ^ 0x07 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0u16;
+    for byte in data {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x8005 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+// Most significant bit first
+#[derive(Default, Clone)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    pending: u64, // bits not yet making up a byte
+    pending_bits: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u64, bits: u32) {
+        if bits > 32 {
+            self.write(value >> 32, bits - 32);
+            self.write(value & 0xFFFF_FFFF, 32);
+            return;
+        }
+        self.pending = (self.pending << bits) | (value & ((1 << bits) - 1));
+        self.pending_bits += bits;
+        while self.pending_bits >= 8 {
+            self.pending_bits -= 8;
+            self.bytes.push((self.pending >> self.pending_bits) as u8);
+        }
+        self.pending &= (1 << self.pending_bits) - 1;
+    }
+
+    fn write_signed(&mut self, value: i64, bits: u32) {
+        self.write(value as u64, bits);
+    }
+
+    // That many zeros, then a one
+    fn write_unary(&mut self, mut zeros: u64) {
+        while zeros >= 32 {
+            self.write(0, 32);
+            zeros -= 32;
+        }
+        self.write(1, zeros as u32 + 1);
+    }
+
+    fn append(&mut self, other: &BitWriter) {
+        for byte in other.bytes.iter() {
+            self.write(*byte as u64, 8);
+        }
+        self.write(other.pending, other.pending_bits);
+    }
+
+    fn align(&mut self) {
+        if self.pending_bits > 0 {
+            self.write(0, 8 - self.pending_bits);
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.bytes.len() * 8 + self.pending_bits as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+    use crate::fixtures::wav;
+
+    // A tone with some noise on it, like music as far as the predictors go
+    fn signal(frames: usize, bits: u32, seed: u64) -> Vec<i32> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let amplitude = (1 << (bits - 2)) as f64;
+        (0..frames)
+            .map(|frame| ((frame as f64 * 0.031).sin() * amplitude) as i32 + rng.gen_range(-(1 << (bits - 8))..(1 << (bits - 8))))
+            .collect()
+    }
+
+    fn interleave(left: &[i32], right: &[i32]) -> Vec<i32> {
+        left.iter().zip(right.iter()).flat_map(|(left, right)| [*left, *right]).collect()
+    }
+
+    fn encode(channels: usize, bits: u32, samples: &[i32]) -> Vec<u8> {
+        let mut writer = FlacWriter::new(Cursor::new(Vec::new()), 44100, channels, bits).unwrap();
+        writer.write(samples).unwrap();
+        writer.finish().unwrap().into_inner()
+    }
+
+    // A single frame with the stereo channels stored as asked
+    fn encode_stereo_as(assignment: u64, left: &[i32], right: &[i32], bits: u32) -> Vec<u8> {
+        let mut writer = FlacWriter::new(Cursor::new(Vec::new()), 44100, 2, bits).unwrap();
+        let (_, subframes) = stereo_subframes(left, right, bits).into_iter().find(|(candidate, _)| *candidate == assignment).unwrap();
+        writer.write_frame(left.len(), assignment, &subframes).unwrap();
+        writer.finish().unwrap().into_inner()
+    }
+
+    // Through symphonia's FLAC decoder, as the player reads them
+    fn decode(flac: Vec<u8>) -> (u32, usize, u32, Vec<i32>) {
+        let mut hint = Hint::new();
+        hint.with_extension("flac");
+        let mut decoded = (0, 0, 0, Vec::new());
+        read_pcm(Box::new(Cursor::new(flac)), &hint, &mut |format, samples| {
+            (decoded.0, decoded.1, decoded.2) = (format.sample_rate, format.channels, format.bits);
+            decoded.3.extend_from_slice(samples);
+            Ok(())
+        })
+        .unwrap();
+        decoded
+    }
+
+    fn assert_round_trip(channels: usize, bits: u32, samples: &[i32]) {
+        let (sample_rate, decoded_channels, decoded_bits, decoded) = decode(encode(channels, bits, samples));
+        assert_eq!((sample_rate, decoded_channels, decoded_bits), (44100, channels, bits));
+        assert_eq!(decoded.len(), samples.len());
+        assert!(decoded == samples, "the decoded samples differ");
+    }
+
+    // The channel assignment in the header of the first frame, right after the stream info
+    fn first_assignment(flac: &[u8]) -> u64 {
+        (flac[4 + 4 + 34 + 3] >> 4) as u64
+    }
+
+    #[test]
+    fn mono_round_trips() {
+        assert_round_trip(1, 16, &signal(BLOCK_SIZE * 3, 16, 1));
+    }
+
+    #[test]
+    fn stereo_round_trips() {
+        let left = signal(BLOCK_SIZE * 3, 16, 2);
+        let right = left.iter().zip(signal(BLOCK_SIZE * 3, 10, 3)).map(|(left, noise)| left / 2 + noise).collect::<Vec<_>>();
+        assert_round_trip(2, 16, &interleave(&left, &right));
+    }
+
+    #[test]
+    fn final_short_block_round_trips() {
+        let left = signal(BLOCK_SIZE * 2 + 123, 16, 4);
+        let right = signal(BLOCK_SIZE * 2 + 123, 16, 5);
+        assert_round_trip(2, 16, &interleave(&left, &right));
+        assert_round_trip(1, 16, &signal(1, 16, 6));
+    }
+
+    #[test]
+    fn every_stereo_assignment_round_trips() {
+        for bits in [16, 24] {
+            for len in [BLOCK_SIZE, 1000] {
+                let left = signal(len, bits, 7);
+                let right = signal(len, bits, 8);
+                for assignment in [0b0001, 0b1000, 0b1001, 0b1010] {
+                    let flac = encode_stereo_as(assignment, &left, &right, bits);
+                    assert_eq!(first_assignment(&flac), assignment);
+                    let (_, _, _, decoded) = decode(flac);
+                    assert!(decoded == interleave(&left, &right), "assignment {:04b} at {} bits, {} frames", assignment, bits, len);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn similar_channels_store_their_difference() {
+        let left = signal(BLOCK_SIZE, 16, 9);
+        let right = left.iter().map(|sample| sample + 1).collect::<Vec<_>>();
+        let flac = encode(2, 16, &interleave(&left, &right));
+        assert_ne!(first_assignment(&flac), 0b0001);
+        assert!(decode(flac).3 == interleave(&left, &right));
+    }
+
+    #[test]
+    fn constant_and_silent_blocks_round_trip() {
+        let mut left = vec![0; BLOCK_SIZE];
+        left.extend(vec![1234; BLOCK_SIZE]);
+        left.extend(vec![-(1 << 23); BLOCK_SIZE]);
+        left.extend(signal(BLOCK_SIZE, 24, 10));
+        let mut right = vec![0; BLOCK_SIZE];
+        right.extend(signal(BLOCK_SIZE, 24, 11));
+        right.extend(vec![(1 << 23) - 1; BLOCK_SIZE]);
+        right.extend(vec![-1; BLOCK_SIZE]);
+        assert_round_trip(1, 24, &left);
+        assert_round_trip(2, 24, &interleave(&left, &right));
+    }
+
+    #[test]
+    fn full_scale_noise_round_trips() {
+        for bits in [16, 24] {
+            let mut rng = StdRng::seed_from_u64(bits as u64);
+            let samples = (0..(BLOCK_SIZE * 2 + 7) * 2).map(|_| rng.gen_range(-(1 << (bits - 1))..(1 << (bits - 1)))).collect::<Vec<_>>();
+            assert_round_trip(2, bits, &samples);
+        }
+    }
+
+    #[test]
+    fn transcoded_wav_matches_its_fingerprint() {
+        let left = signal(BLOCK_SIZE * 2 + 500, 16, 12);
+        let right = signal(BLOCK_SIZE * 2 + 500, 16, 13);
+        let samples = interleave(&left, &right);
+        for bits in [16, 24] {
+            let samples = samples.iter().map(|sample| sample << (bits - 16)).collect::<Vec<_>>();
+            let mut hint = Hint::new();
+            hint.with_extension("wav");
+            let (out, expected) = transcode(Box::new(Cursor::new(wav(2, bits, &samples))), &hint, Cursor::new(Vec::new()), &mut |_, _| true).unwrap();
+            let flac = out.into_inner();
+            assert_eq!(decode(flac.clone()).3, samples);
+
+            let mut flac_hint = Hint::new();
+            flac_hint.with_extension("flac");
+            assert_eq!(fingerprint(Box::new(Cursor::new(flac.clone())), &flac_hint).unwrap(), expected);
+
+            // a damaged frame is dropped or fails to decode, either way the fingerprint doesn't match
+            let mut damaged = flac;
+            let middle = damaged.len() / 2;
+            damaged[middle] ^= 0x10;
+            assert!(fingerprint(Box::new(Cursor::new(damaged)), &flac_hint).map_or(true, |found| found != expected));
+        }
+    }
+}
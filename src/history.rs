@@ -0,0 +1,306 @@
+//! A log of every play, for the Insights view: practice time per day and week, the most practiced songs,
+//! how their speed has come along and how many days in a row we've practiced.
+//! Appended to history.jsonl beside the configuration, one play per line, so a crash loses at most the current song.
+use std::{
+    collections::HashMap,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Datelike, Days, Local, NaiveDate};
+use color_eyre::eyre::{eyre, Result};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use super::player::SongStub;
+
+/// One play of a song, as written to history.jsonl
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct PlayRecord {
+    /// The song's file name, which identifies it
+    pub file_name: String,
+    /// The song's title when it was played
+    pub title: String,
+    /// The song's artist when it was played
+    pub artist: String,
+    /// When the song started
+    pub started: DateTime<Local>,
+    /// When it stopped, however it stopped
+    pub ended: DateTime<Local>,
+    /// Seconds actually playing, not paused or counting in
+    pub played: f64,
+    /// Averaged over the time played
+    pub speed: f64,
+    /// How many times a loop went back to its start
+    pub loop_count: usize,
+    /// How many times we jumped around in the song
+    pub seeks: usize,
+}
+
+/// A play in progress. The player ticks it as it goes and turns it into a record once the song stops.
+pub(crate) struct PlaySession {
+    stub: SongStub,
+    started: DateTime<Local>,
+    last_tick: Instant,
+    played: Duration,
+    speed_seconds: f64, // speed times seconds played, for the average
+    loop_count: usize,
+    seeks: usize,
+}
+
+impl PlaySession {
+    pub fn new(stub: &SongStub) -> Self {
+        PlaySession {
+            stub: stub.clone(),
+            started: Local::now(),
+            last_tick: Instant::now(),
+            played: Duration::ZERO,
+            speed_seconds: 0.0,
+            loop_count: 0,
+            seeks: 0,
+        }
+    }
+
+    pub fn tick(&mut self, is_playing: bool, speed: f64) {
+        let elapsed = self.last_tick.elapsed();
+        self.last_tick = Instant::now();
+        if is_playing {
+            self.played += elapsed;
+            self.speed_seconds += speed * elapsed.as_secs_f64();
+        }
+    }
+
+    pub fn seeked(&mut self) {
+        self.seeks += 1;
+    }
+
+    pub fn looped(&mut self) {
+        self.loop_count += 1;
+    }
+
+    pub fn finish(self) -> PlayRecord {
+        let played = self.played.as_secs_f64();
+        PlayRecord {
+            file_name: self.stub.file_name,
+            title: self.stub.title,
+            artist: self.stub.artist,
+            started: self.started,
+            ended: Local::now(),
+            played,
+            speed: if played > 0.0 { self.speed_seconds / played } else { 1.0 },
+            loop_count: self.loop_count,
+            seeks: self.seeks,
+        }
+    }
+}
+
+/// How far we got through a practice routine
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct RoutineRecord {
+    /// The routine's name
+    pub name: String,
+    /// When it started
+    pub started: DateTime<Local>,
+    /// When it finished or was stopped
+    pub ended: DateTime<Local>,
+    /// Steps in the routine
+    pub steps: usize,
+    /// Steps done, including ones skipped because they couldn't play
+    pub completed_steps: usize,
+    /// False when stopped early
+    pub finished: bool,
+}
+
+/// What the Insights view shows for a song
+#[derive(Debug, Clone, PartialEq)]
+pub struct SongStats {
+    /// The song's title
+    pub title: String,
+    /// The song's artist
+    pub artist: String,
+    /// Time spent playing it
+    pub played: Duration,
+    /// How many times it was played
+    pub plays: usize,
+    /// The fastest of each day the song was played, oldest first
+    pub speeds: Vec<(NaiveDate, f64)>,
+}
+
+/// Every play so far, read from history.jsonl beside the configuration
+#[derive(Debug, Default)]
+pub struct History {
+    records: Vec<PlayRecord>, // oldest first
+}
+
+impl History {
+    fn path() -> Result<PathBuf> {
+        Self::path_of("history.jsonl")
+    }
+
+    fn path_of(file_name: &str) -> Result<PathBuf> {
+        let config_path = confy::get_configuration_file_path("drum-weaver", None)?;
+        Ok(config_path.with_file_name(file_name))
+    }
+
+    fn append_line<T: Serialize>(path: PathBuf, value: &T) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(file, "{}", serde_json::to_string(value)?)?;
+        Ok(())
+    }
+
+    /// Lines we can't read are skipped, so one bad line doesn't cost the rest
+    pub fn load() -> History {
+        let path = match Self::path() {
+            Ok(path) => path,
+            Err(err) => {
+                warn!("Unable to locate the history: {}", err);
+                return History::default();
+            }
+        };
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(_) => return History::default(), // nothing played yet
+        };
+
+        let mut records = vec![];
+        for (idx, line) in contents.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+            match serde_json::from_str::<PlayRecord>(line) {
+                Ok(record) => records.push(record),
+                Err(err) => warn!("Skipping line {} of {}: {}", idx + 1, path.display(), err),
+            }
+        }
+        records.sort_by_key(|record| record.started);
+        History { records }
+    }
+
+    /// Writes the play to the end of the file and adds it
+    pub fn append(&mut self, record: PlayRecord) -> Result<()> {
+        Self::append_line(Self::path()?, &record)?;
+        self.records.push(record);
+        Ok(())
+    }
+
+    /// Routines are kept apart from the plays, in routines.jsonl
+    pub fn append_routine(&self, record: &RoutineRecord) -> Result<()> {
+        Self::append_line(Self::path_of("routines.jsonl")?, record)
+    }
+
+    /// When each song was last played, by file name
+    pub fn last_played(&self) -> HashMap<String, DateTime<Local>> {
+        let mut last_played = HashMap::new();
+        for record in self.records.iter() {
+            last_played.insert(record.file_name.clone(), record.ended); // oldest first, so the latest wins
+        }
+        last_played
+    }
+
+    fn played_by_day(&self) -> HashMap<NaiveDate, Duration> {
+        let mut days = HashMap::new();
+        for record in self.records.iter() {
+            *days.entry(record.started.date_naive()).or_insert(Duration::ZERO) += Duration::from_secs_f64(record.played.max(0.0));
+        }
+        days
+    }
+
+    /// The last few days up to today, oldest first. Days without practice are included.
+    pub fn daily_totals(&self, today: NaiveDate, days: u64) -> Vec<(NaiveDate, Duration)> {
+        let played = self.played_by_day();
+        (0..days)
+            .rev()
+            .filter_map(|ago| today.checked_sub_days(Days::new(ago)))
+            .map(|day| (day, played.get(&day).copied().unwrap_or_default()))
+            .collect()
+    }
+
+    /// The last few weeks, each by the Monday it starts on, oldest first
+    pub fn weekly_totals(&self, today: NaiveDate, weeks: u64) -> Vec<(NaiveDate, Duration)> {
+        let this_week = today - chrono::Duration::days(today.weekday().num_days_from_monday() as i64);
+        let mut totals = (0..weeks)
+            .rev()
+            .filter_map(|ago| this_week.checked_sub_days(Days::new(ago * 7)))
+            .map(|monday| (monday, Duration::ZERO))
+            .collect::<Vec<_>>();
+
+        for (day, played) in self.played_by_day() {
+            if let Some((_, total)) = totals.iter_mut().find(|(monday, _)| *monday <= day && day < *monday + chrono::Duration::days(7)) {
+                *total += played;
+            }
+        }
+        totals
+    }
+
+    /// By total time played, most first
+    pub fn most_practiced(&self, count: usize) -> Vec<SongStats> {
+        let mut songs: HashMap<&str, SongStats> = HashMap::new();
+        for record in self.records.iter() {
+            let stats = songs.entry(record.file_name.as_str()).or_insert_with(|| SongStats {
+                title: record.title.clone(),
+                artist: record.artist.clone(),
+                played: Duration::ZERO,
+                plays: 0,
+                speeds: vec![],
+            });
+            stats.played += Duration::from_secs_f64(record.played.max(0.0));
+            stats.plays += 1;
+
+            let day = record.started.date_naive();
+            match stats.speeds.last_mut() {
+                Some((last_day, speed)) if *last_day == day => *speed = speed.max(record.speed),
+                _ => stats.speeds.push((day, record.speed)),
+            }
+        }
+
+        let mut songs = songs.into_values().collect::<Vec<_>>();
+        songs.sort_by(|a, b| b.played.cmp(&a.played).then_with(|| a.title.cmp(&b.title)));
+        songs.truncate(count);
+        songs
+    }
+
+    /// Days in a row with some practice: the run up to today (or yesterday, if today hasn't started yet) and the longest ever
+    pub fn streaks(&self, today: NaiveDate) -> (usize, usize) {
+        let played = self.played_by_day();
+        let mut days = played.iter().filter(|(_, played)| !played.is_zero()).map(|(day, _)| *day).collect::<Vec<_>>();
+        days.sort();
+
+        let mut longest = 0;
+        let mut run = 0;
+        let mut previous: Option<NaiveDate> = None;
+        for day in days.iter() {
+            run = match previous {
+                Some(previous) if previous.succ_opt() == Some(*day) => run + 1,
+                _ => 1,
+            };
+            longest = longest.max(run);
+            previous = Some(*day);
+        }
+
+        let yesterday = today.pred_opt();
+        let current = match days.last() {
+            Some(last) if *last == today || Some(*last) == yesterday => run,
+            _ => 0,
+        };
+        (current, longest)
+    }
+
+    /// Writes every play as CSV or JSON, depending on the extension. Returns where it went.
+    pub fn export(&self, extension: &str) -> Result<PathBuf> {
+        let path = Self::path_of(&format!("practice_history.{}", extension))?;
+        match extension {
+            "csv" => {
+                let mut writer = csv::Writer::from_path(&path)?;
+                for record in self.records.iter() {
+                    writer.serialize(record)?;
+                }
+                writer.flush()?;
+            }
+            "json" => fs::write(&path, serde_json::to_string_pretty(&self.records)?)?,
+            _ => return Err(eyre!("Can't export the history as {}", extension)),
+        }
+        Ok(path)
+    }
+}
@@ -0,0 +1,54 @@
+//! The Drum Weaver engine: plays drumless tracks and their clicks through a mix of outputs, and knows the song library
+//! and the audio devices. The terminal UI is one front end to it; others, and tests, drive it the same way.
+//!
+//! - [`player`]: a [`PlayerHandle`](player::PlayerHandle) takes [`PlayerCommand`](player::PlayerCommand)s and answers
+//!   with [`PlayerEvent`](player::PlayerEvent)s, including the playback status while a song plays.
+//! - [`library`]: the song catalog as [`SongRecord`](library::SongRecord)s, searching it, and the songs left out of it.
+//! - [`devices`]: the audio hosts and their output devices, and the sample rate and buffer size picked for each.
+//! - [`audio`] and [`beep`]: decoding, output streams, and the sounds the player makes itself.
+//!
+//! The other modules support those: loading songs out of their archives into a cache, beats and tempo, the mixer, the
+//! practice history and each song's practice settings. Settings and data files live beside the `drum-weaver`
+//! configuration.
+//!
+//! ```no_run
+//! use drum_weaver::{
+//!     library::Library,
+//!     player::{OutputConfig, PlayerCommand, PlayerEvent, PlayerHandle, SongStub},
+//! };
+//!
+//! let mut library = Library::new("/music/drumless".to_string());
+//! library.load_csv();
+//! library.search("rush");
+//!
+//! let player = PlayerHandle::start();
+//! player.send(PlayerCommand::SetOutputs(OutputConfig::defaults(None, None)));
+//! player.send(PlayerCommand::Play(SongStub::from_song_record(&library.get_songs()[0])));
+//! for event in player.events.iter() {
+//!     match event {
+//!         PlayerEvent::Status(status) => println!("{:?}", status.track_position),
+//!         PlayerEvent::LoadFailure(_, reason) => panic!("{}", reason),
+//!         PlayerEvent::Ended => break,
+//!         _ => {}
+//!     }
+//! }
+//! ```
+#![warn(missing_docs)]
+
+pub mod audio;
+pub mod beats;
+pub mod beep;
+pub mod cache;
+pub mod devices;
+#[cfg(test)]
+mod fixtures;
+pub(crate) mod flac;
+pub mod history;
+pub mod library;
+pub mod loader;
+pub mod metronome;
+pub(crate) mod midi;
+pub mod mixer;
+pub mod player;
+pub mod prefetch;
+pub mod song_data;
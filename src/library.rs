@@ -0,0 +1,215 @@
+//! The song catalog, as read from the bundled song list, searched and shuffled by the user, and the songs left out of
+//! it.
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::Duration,
+};
+
+use color_eyre::eyre::Result;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use super::player::SongStub;
+use lazy_static::lazy_static;
+
+/// A song of the catalog. Its track and click are in `<folder>/<file_name>.7z`, or extracted beside it or in the cache.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SongRecord {
+    /// The name of its archive, without the `.7z`, which is also how its files are named
+    pub file_name: String,
+    /// The genre, as the catalog has it
+    pub genre: String,
+    /// The year it came out
+    pub year: String,
+    /// The artist
+    pub artist: String,
+    /// The title
+    pub title: String,
+    /// The album it is from
+    pub album: String,
+    /// How long it plays, as m:ss
+    pub length: String,
+    /// Its tempo in beats per minute, 0 when unknown
+    pub bpm: usize,
+    /// The folder its archive is in. Relative to the library in the catalog, made absolute when read.
+    pub folder: String,
+}
+
+impl SongRecord {
+    /// The length is given as m:ss
+    pub fn duration(&self) -> Option<Duration> {
+        let (minutes, seconds) = self.length.trim().split_once(':')?;
+        Some(Duration::from_secs(minutes.parse::<u64>().ok()? * 60 + seconds.parse::<u64>().ok()?))
+    }
+}
+
+/// The songs of the catalog under a library folder, as searched, shuffled or cut down by the user
+#[derive(Clone)]
+pub struct Library {
+    /// The root path of the library (i.e. ...\Drumless)
+    pub path: String,
+    songs: Vec<SongRecord>,
+    original_songs: Vec<SongRecord>,
+}
+
+lazy_static! {
+    static ref SONGS: Mutex<Vec<SongRecord>> = Mutex::new(Vec::new());
+}
+
+impl Library {
+    /// An empty library. Load the songs with [`load_csv`](Library::load_csv).
+    pub fn new(path: String) -> Self {
+        Library {
+            path,
+            songs: Vec::new(),
+            original_songs: Vec::new(),
+        }
+    }
+
+    /// Reload the library from the embedded CSV file, leaving out the ignored songs
+    pub fn load_csv(&mut self) {
+        let ignored = IgnoreList::load();
+        self.songs = Self::read_catalog(&self.path);
+        self.songs.retain(|song| !ignored.contains(&song.file_name));
+        self.original_songs = self.songs.clone();
+
+        info!("Loaded {} songs from CSV, {} ignored", self.songs.len(), ignored.count());
+    }
+
+    /// Every song in the embedded CSV file, with its folder under the library path
+    pub fn read_catalog(base_path: &str) -> Vec<SongRecord> {
+        let file_contents: &str = include_str!("../assets/song_list.csv");
+        let mut reader = csv::Reader::from_reader(file_contents.as_bytes());
+
+        let mut songs = vec![];
+        for result in reader.deserialize() {
+            let mut song: SongRecord = result.unwrap();
+            let mut song_path = PathBuf::from(base_path);
+            song_path.push(&song.folder);
+            song.folder = song_path.display().to_string();
+            songs.push(song);
+        }
+        songs
+    }
+
+    /// Takes a song out of the library until it is loaded again
+    pub fn remove_song_by_stub(&mut self, stub: SongStub) {
+        if let Some(index) = self.songs.iter().position(|song| song.file_name == stub.file_name) {
+            self.songs.remove(index);
+        }
+        self.original_songs.retain(|song| song.file_name != stub.file_name);
+    }
+
+    /// The songs as shown: searched, shuffled and without the ones removed
+    pub fn get_songs(&self) -> &Vec<SongRecord> {
+        &self.songs
+    }
+
+    /// Every song, whatever the search
+    pub fn all_songs(&self) -> &Vec<SongRecord> {
+        &self.original_songs
+    }
+
+    /// Puts the songs shown in a random order
+    pub fn shuffle(&mut self) {
+        use rand::seq::SliceRandom;
+        let mut rng = rand::thread_rng();
+        self.songs.shuffle(&mut rng);
+    }
+
+    /// Songs whose title, artist or genre has the query in it. Terms after a `!` leave songs out, e.g. `rock !live`.
+    pub fn search(&mut self, query: &str) {
+        self.songs = self.original_songs.clone();
+
+        let terms: Vec<&str> = query.split('!').collect();
+
+        let include_term = terms[0].trim().to_lowercase();
+        let exclude_terms: Vec<String> = terms[1..].iter().map(|term| term.trim().to_lowercase()).collect();
+
+        self.songs.retain(|song| {
+            (song.title.to_lowercase().contains(&include_term) || song.artist.to_lowercase().contains(&include_term) || song.genre.to_lowercase().contains(&include_term))
+                && exclude_terms
+                    .iter()
+                    .all(|term| !song.title.to_lowercase().contains(term) && !song.artist.to_lowercase().contains(term) && !song.genre.to_lowercase().contains(term))
+        });
+
+        // self.songs.retain(|song| {
+        //     song.title.to_lowercase().contains(&query.to_lowercase()) || song.artist.to_lowercase().contains(&query.to_lowercase()) || song.genre.to_lowercase().contains(&query.to_lowercase())
+        // });
+    }
+
+    /// Every song again, in catalog order
+    pub fn reset(&mut self) {
+        self.songs = self.original_songs.clone();
+    }
+}
+
+/// Songs left out of the library, e.g. because their files are broken. Kept in ignored.json beside the configuration.
+/// Take a song out of the file to have it back.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct IgnoreList {
+    songs: BTreeSet<String>, // by file name
+}
+
+impl IgnoreList {
+    /// ignored.json beside the configuration
+    pub fn path() -> Result<PathBuf> {
+        let config_path = confy::get_configuration_file_path("drum-weaver", None)?;
+        Ok(config_path.with_file_name("ignored.json"))
+    }
+
+    /// The songs ignored so far. A file that can't be read counts as no songs.
+    pub fn load() -> IgnoreList {
+        match Self::path() {
+            Ok(path) => Self::load_from(&path),
+            Err(_) => IgnoreList::default(),
+        }
+    }
+
+    /// Like [`load`](IgnoreList::load), from a list kept somewhere else
+    pub fn load_from(path: &Path) -> IgnoreList {
+        if !path.exists() {
+            return IgnoreList::default();
+        }
+
+        match fs::read_to_string(path)
+            .map_err(|err| err.to_string())
+            .and_then(|contents| serde_json::from_str(&contents).map_err(|err| err.to_string()))
+        {
+            Ok(ignored) => ignored,
+            Err(err) => {
+                warn!("Unable to read {}: {}", path.display(), err);
+                IgnoreList::default()
+            }
+        }
+    }
+
+    /// Writes the list to ignored.json
+    pub fn save(&self) -> Result<()> {
+        self.save_to(&Self::path()?)
+    }
+
+    /// Writes the list somewhere else
+    pub fn save_to(&self, path: &Path) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// False when it was already ignored
+    pub fn add(&mut self, file_name: &str) -> bool {
+        self.songs.insert(file_name.to_string())
+    }
+
+    /// Whether the song is ignored
+    pub fn contains(&self, file_name: &str) -> bool {
+        self.songs.contains(file_name)
+    }
+
+    /// How many songs are ignored
+    pub fn count(&self) -> usize {
+        self.songs.len()
+    }
+}
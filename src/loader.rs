@@ -0,0 +1,592 @@
+//! Loads songs in the background, so the player keeps playing and answering while the next song is unpacked and decoded.
+//! Songs that aren't extracted are decoded straight out of their 7z file in memory, so playing needs no disk space.
+//! Songs in the cache are WAVs, or FLACs once converted to save space.
+//! Progress goes straight to the UI. A load is cancelled when it is dropped, e.g. because another song was picked.
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Cursor, Read, Seek, Write},
+    path::{Component, Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use color_eyre::eyre::{ensure, eyre, Report, Result};
+use crossbeam_channel::{bounded, Receiver, Sender};
+use log::{info, warn};
+use sevenz_rust::{Password, SevenZReader};
+use symphonia::core::io::MediaSource;
+use thiserror::Error;
+
+use super::{
+    audio::{Hint, Song},
+    cache::{self, enforce_quota},
+    flac::{fingerprint, transcode},
+    player::{PlayerEvent, SongStub},
+};
+
+const EXTRACT_CHUNK: usize = 256 * 1024;
+// Sizes come from the archive's header, so a broken one could ask for anything. Past this the buffer grows as it's read.
+const MAX_ENTRY_RESERVE: u64 = 128 * 1024 * 1024;
+const CLAIM_WAIT: Duration = Duration::from_millis(50);
+
+// Songs being extracted right now, by file name, so no two extractions write the same files
+static EXTRACTING: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// How far a song has got loading or converting, for the UI
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LoadProgress {
+    /// Before anything is read
+    Starting,
+    /// Bytes so far, of every file in the archive
+    Extracting(u64, u64),
+    /// Frames so far, of the track and click together
+    Decoding(u64, u64),
+    /// Frames so far, of the track and click together
+    Converting(u64, u64),
+}
+
+impl LoadProgress {
+    /// For the status line, e.g. "extracting 40%"
+    pub fn describe(&self) -> String {
+        match self {
+            LoadProgress::Starting => "starting".to_string(),
+            LoadProgress::Extracting(..) => format!("extracting {}%", self.percent().unwrap_or(0)),
+            LoadProgress::Decoding(..) => format!("decoding {}%", self.percent().unwrap_or(0)),
+            LoadProgress::Converting(..) => format!("converting {}%", self.percent().unwrap_or(0)),
+        }
+    }
+
+    // Only whole percents are worth telling the UI about
+    fn percent(&self) -> Option<u64> {
+        match self {
+            LoadProgress::Starting => None,
+            LoadProgress::Extracting(done, total) | LoadProgress::Decoding(done, total) | LoadProgress::Converting(done, total) => Some((done * 100).checked_div(*total).unwrap_or(0)),
+        }
+    }
+}
+
+/// Why a song didn't load, to show the user and to tell whether the song itself is at fault
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum LoadError {
+    /// The song's 7z file isn't there
+    #[error("{} is missing", .0.display())]
+    ArchiveMissing(PathBuf),
+    /// The 7z file can't be read
+    #[error("{} is corrupt: {1}", .0.display())]
+    ArchiveCorrupt(PathBuf, String),
+    /// The 7z file has no track in it
+    #[error("{} has no track", .0.display())]
+    TrackMissing(PathBuf),
+    /// The 7z file has no click in it
+    #[error("{} has no click track", .0.display())]
+    ClickMissing(PathBuf),
+    /// An extracted file can't be opened
+    #[error("{} can't be read: {1}", .0.display())]
+    Unreadable(PathBuf, String),
+    /// The track or click doesn't decode
+    #[error("the audio doesn't decode: {0}")]
+    Decode(String),
+    /// The song loaded but couldn't be played
+    #[error("the output device failed: {0}")]
+    Device(String),
+    /// Another song was picked before this one loaded
+    #[error("loading was cancelled")]
+    Cancelled,
+}
+
+impl LoadError {
+    /// What might fix it
+    pub fn hint(&self) -> &'static str {
+        match self {
+            LoadError::ArchiveMissing(_) => "Is the library folder right, and its drive connected?",
+            LoadError::ArchiveCorrupt(..) | LoadError::TrackMissing(_) | LoadError::ClickMissing(_) => "Download the song again.",
+            LoadError::Unreadable(..) | LoadError::Decode(_) => "Check the library with F5 to extract it again.",
+            LoadError::Device(_) => "Check the outputs in the Devices view.",
+            LoadError::Cancelled => "",
+        }
+    }
+
+    /// Rather than the devices, or a load that was cancelled
+    pub fn is_song_broken(&self) -> bool {
+        !matches!(self, LoadError::Device(_) | LoadError::Cancelled)
+    }
+}
+
+// A song being loaded on its own thread
+pub(crate) struct LoadJob {
+    pub stub: SongStub,
+    cancelled: Arc<AtomicBool>,
+    result_receiver: Receiver<Result<(Song, Song), LoadError>>, // the track and click
+}
+
+impl LoadJob {
+    pub fn start(stub: SongStub, player_event_sender: Sender<PlayerEvent>) -> LoadJob {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let (result_sender, result_receiver) = bounded(1);
+
+        let job_stub = stub.clone();
+        let job_cancelled = cancelled.clone();
+        thread::spawn(move || {
+            let mut last_progress = LoadProgress::Starting;
+            let mut report = |progress: LoadProgress| {
+                if progress.percent() != last_progress.percent() {
+                    last_progress = progress;
+                    player_event_sender.send(PlayerEvent::Loading(job_stub.clone(), progress)).unwrap();
+                }
+                !job_cancelled.load(Ordering::Relaxed)
+            };
+
+            player_event_sender.send(PlayerEvent::Loading(job_stub.clone(), LoadProgress::Starting)).unwrap();
+            let result = load_song(&job_stub, &mut report);
+            if job_cancelled.load(Ordering::Relaxed) {
+                info!("Stopped loading {}", job_stub.file_name);
+                return;
+            }
+            result_sender.send(result).ok();
+        });
+
+        LoadJob { stub, cancelled, result_receiver }
+    }
+
+    // A job for a song that was already decoded, e.g. by the prefetcher
+    pub fn ready(stub: SongStub, songs: (Song, Song)) -> LoadJob {
+        let (result_sender, result_receiver) = bounded(1);
+        result_sender.send(Ok(songs)).ok();
+        LoadJob {
+            stub,
+            cancelled: Arc::new(AtomicBool::new(false)),
+            result_receiver,
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    // The loaded track and click, once the job is done
+    pub fn try_result(&self) -> Option<Result<(Song, Song), LoadError>> {
+        self.result_receiver.try_recv().ok()
+    }
+}
+
+impl Drop for LoadJob {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+// 1. Use the track and click files if they were extracted. If not, read them out of the 7z file into memory
+// 2. Decode both
+pub(crate) fn load_song(stub: &SongStub, report: &mut dyn FnMut(LoadProgress) -> bool) -> Result<(Song, Song), LoadError> {
+    let (track_source, click_source, extension) = open_song(stub.folder.as_str(), stub.file_name.as_str(), report)?;
+
+    // the click is as long as the track, near enough, so the total starts out as twice the track
+    let mut track_frames = 0;
+    let mut cancelled = false;
+    let track = decode(track_source, extension, &mut |done, total| {
+        track_frames = done;
+        cancelled = !report(LoadProgress::Decoding(done, total.map(|total| total * 2).unwrap_or(0)));
+        !cancelled
+    })
+    .map_err(|err| decode_error(err, cancelled))?;
+    let click = decode(click_source, extension, &mut |done, total| {
+        cancelled = !report(LoadProgress::Decoding(track_frames + done, track_frames + total.unwrap_or(0)));
+        !cancelled
+    })
+    .map_err(|err| decode_error(err, cancelled))?;
+    Ok((track, click))
+}
+
+// Decoding stops with an error when the load is cancelled, which says nothing about the song
+fn decode_error(err: Report, cancelled: bool) -> LoadError {
+    if cancelled {
+        LoadError::Cancelled
+    } else {
+        LoadError::Decode(err.to_string())
+    }
+}
+
+// The track, the click, and the extension of what they are stored as
+type SongSources = (Box<dyn MediaSource>, Box<dyn MediaSource>, &'static str);
+
+fn open_song(song_folder: &str, song_title: &str, report: &mut dyn FnMut(LoadProgress) -> bool) -> Result<SongSources, LoadError> {
+    if is_extracted(song_folder, song_title) {
+        let (track_path, click_path) = file_paths(song_folder, song_title);
+        let extension = if is_flac(&track_path) { "flac" } else { "wav" };
+        let open = |path: PathBuf| File::open(&path).map_err(|err| LoadError::Unreadable(path, err.to_string()));
+        Ok((Box::new(open(track_path)?), Box::new(open(click_path)?), extension))
+    } else {
+        let (track_bytes, click_bytes) = unpack(song_folder, song_title, report)?;
+        Ok((Box::new(ArchiveMediaSource::new(track_bytes)), Box::new(ArchiveMediaSource::new(click_bytes)), "wav"))
+    }
+}
+
+/// Extracts the song unless its files are already there. Whoever comes second to a song waits for the first to finish.
+pub fn ensure_extracted(song_folder: &str, song_title: &str, report: &mut dyn FnMut(LoadProgress) -> bool) -> Result<()> {
+    if is_extracted(song_folder, song_title) {
+        return Ok(());
+    }
+
+    let _claim = ExtractionClaim::wait_for(song_title, report).ok_or_else(|| eyre!("Extracting {} was cancelled", song_title))?;
+    if is_extracted(song_folder, song_title) {
+        return Ok(());
+    }
+
+    info!("Track file does not exist. Decompressing.");
+    extract(song_folder, song_title, report)?;
+    info!("Decompression complete");
+
+    if let Err(err) = enforce_quota(&[song_title]) {
+        warn!("Unable to keep the cache to its limit: {:?}", err);
+    }
+    Ok(())
+}
+
+/// Stores the song as FLAC in the cache, from its extracted WAVs or straight from its 7z file. The WAVs in the cache go
+/// once both FLACs are complete. Songs extracted next to their archive are left as they are.
+pub fn ensure_converted(song_folder: &str, song_title: &str, report: &mut dyn FnMut(LoadProgress) -> bool) -> Result<()> {
+    if !needs_converting(song_folder, song_title) {
+        return Ok(());
+    }
+
+    let _claim = ExtractionClaim::wait_for(song_title, report).ok_or_else(|| eyre!("Converting {} was cancelled", song_title))?;
+    if !needs_converting(song_folder, song_title) {
+        return Ok(());
+    }
+
+    let destination = cache::song_folder(song_title);
+    let (track_source, click_source, _) = open_song(song_folder, song_title, report)?;
+    let (track_path, click_path) = paths_in(&destination, song_title, "flac");
+    fs::create_dir_all(&destination)?;
+
+    let mut track_frames = 0;
+    convert(track_source, &track_path, &mut |done, total| {
+        track_frames = done;
+        report(LoadProgress::Converting(done, total.map(|total| total * 2).unwrap_or(0)))
+    })?;
+    convert(click_source, &click_path, &mut |done, total| {
+        report(LoadProgress::Converting(track_frames + done, track_frames + total.unwrap_or(0)))
+    })?;
+
+    for path in <[PathBuf; 2]>::from(paths_in(&destination, song_title, "wav")) {
+        if path.exists() {
+            fs::remove_file(&path)?;
+        }
+    }
+    info!("Converted {} to FLAC", song_title);
+
+    if let Err(err) = enforce_quota(&[song_title]) {
+        warn!("Unable to keep the cache to its limit: {:?}", err);
+    }
+    Ok(())
+}
+
+/// Not there yet, or only as WAVs in the cache
+pub fn needs_converting(song_folder: &str, song_title: &str) -> bool {
+    let (track_path, click_path) = file_paths(song_folder, song_title);
+    let is_cached_wav = track_path.starts_with(cache::song_folder(song_title)) && !is_flac(&track_path);
+    !(track_path.exists() && click_path.exists()) || is_cached_wav
+}
+
+/// Whether both the track and the click are on disk, beside the archive or in the cache
+pub fn is_extracted(song_folder: &str, song_title: &str) -> bool {
+    let (track_path, click_path) = file_paths(song_folder, song_title);
+    track_path.exists() && click_path.exists()
+}
+
+// How much disk the song's files take once extracted, from the archive's header
+pub(crate) fn unpacked_size(song_folder: &str, song_title: &str) -> Result<u64> {
+    let reader = SevenZReader::open(archive_path(song_folder, song_title), Password::empty())?;
+    Ok(reader.archive().files.iter().map(|entry| entry.size()).sum())
+}
+
+pub(crate) fn is_extracting(song_title: &str) -> bool {
+    EXTRACTING.lock().unwrap().iter().any(|title| title == song_title)
+}
+
+// Held while a song is extracted
+struct ExtractionClaim(String);
+
+impl ExtractionClaim {
+    // None when cancelled while waiting
+    fn wait_for(song_title: &str, report: &mut dyn FnMut(LoadProgress) -> bool) -> Option<ExtractionClaim> {
+        loop {
+            {
+                let mut extracting = EXTRACTING.lock().unwrap();
+                if !extracting.iter().any(|title| title == song_title) {
+                    extracting.push(song_title.to_string());
+                    return Some(ExtractionClaim(song_title.to_string()));
+                }
+            }
+            if !report(LoadProgress::Starting) {
+                return None;
+            }
+            thread::sleep(CLAIM_WAIT);
+        }
+    }
+}
+
+impl Drop for ExtractionClaim {
+    fn drop(&mut self) {
+        EXTRACTING.lock().unwrap().retain(|title| *title != self.0);
+    }
+}
+
+/// The full paths for the main and click files, in the library folder when the song was extracted there
+/// before there was a cache, otherwise the FLACs or WAVs in the cache. It does not check if they exist.
+pub fn file_paths(song_folder: &str, song_title: &str) -> (PathBuf, PathBuf) {
+    let cache_folder = cache::song_folder(song_title);
+    for (track_path, click_path) in [paths_in(Path::new(song_folder), song_title, "wav"), paths_in(&cache_folder, song_title, "flac")] {
+        if track_path.exists() && click_path.exists() {
+            return (track_path, click_path);
+        }
+    }
+    paths_in(&cache_folder, song_title, "wav")
+}
+
+fn paths_in(folder: &Path, song_title: &str, extension: &str) -> (PathBuf, PathBuf) {
+    (folder.join(format!("{}.{}", song_title, extension)), folder.join(format!("{}_click.{}", song_title, extension)))
+}
+
+/// Whether the file is a FLAC, going by its extension
+pub fn is_flac(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "flac")
+}
+
+/// Where the song's 7z file is
+pub fn archive_path(song_folder: &str, song_title: &str) -> PathBuf {
+    Path::new(song_folder).join(format!("{}.7z", song_title))
+}
+
+// Written under a temporary name like extracted files, decoded again to check it holds exactly the samples that went in,
+// and renamed once it does
+fn convert(source: Box<dyn MediaSource>, path: &Path, progress: &mut dyn FnMut(u64, Option<u64>) -> bool) -> Result<()> {
+    let mut hint = Hint::new();
+    hint.with_extension("wav");
+    let partial_path = partial_path(path);
+    let written = || -> Result<()> {
+        let (_, expected) = transcode(source, &hint, BufWriter::new(File::create(&partial_path)?), progress)?;
+        let mut flac_hint = Hint::new();
+        flac_hint.with_extension("flac");
+        let found = fingerprint(Box::new(File::open(&partial_path)?), &flac_hint)?;
+        ensure!(found == expected, "{} doesn't decode to the samples it was made from", path.display());
+        Ok(())
+    };
+    if let Err(err) = written() {
+        if let Err(err) = fs::remove_file(&partial_path) {
+            warn!("Unable to remove {}: {}", partial_path.display(), err);
+        }
+        return Err(err);
+    }
+    fs::rename(&partial_path, path)?;
+    Ok(())
+}
+
+// Where an archive entry goes in the destination. Names that would leave it, e.g. ../x or /x, are refused.
+fn entry_path(destination: &Path, name: &str) -> io::Result<PathBuf> {
+    let name = Path::new(name);
+    if name.as_os_str().is_empty() || !name.components().all(|component| matches!(component, Component::Normal(_))) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("The archive entry {} is outside the song's folder", name.display())));
+    }
+    Ok(destination.join(name))
+}
+
+// The file's whole name and .part, so a.wav and a.flac don't share one
+fn partial_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".part");
+    path.with_file_name(name)
+}
+
+fn decode(source: Box<dyn MediaSource>, extension: &str, progress: &mut dyn FnMut(u64, Option<u64>) -> bool) -> Result<Song> {
+    let mut hint = Hint::new();
+    hint.with_extension(extension);
+    Song::new_with_progress(source, &hint, None, progress)
+}
+
+/// An archive entry read into memory. Like [`BeepMediaSource`](crate::beep::BeepMediaSource), but it owns its bytes.
+pub struct ArchiveMediaSource {
+    cursor: Cursor<Vec<u8>>,
+}
+
+impl ArchiveMediaSource {
+    /// Reads from the given bytes, which have to hold a whole file
+    pub fn new(data: Vec<u8>) -> Self {
+        Self { cursor: Cursor::new(data) }
+    }
+}
+
+impl Read for ArchiveMediaSource {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, std::io::Error> {
+        self.cursor.read(buf)
+    }
+}
+
+impl Seek for ArchiveMediaSource {
+    fn seek(&mut self, pos: std::io::SeekFrom) -> Result<u64, std::io::Error> {
+        self.cursor.seek(pos)
+    }
+}
+
+impl MediaSource for ArchiveMediaSource {
+    fn is_seekable(&self) -> bool {
+        true
+    }
+
+    fn byte_len(&self) -> Option<u64> {
+        Some(self.cursor.get_ref().len() as u64)
+    }
+}
+
+// Reads the track and click out of the song's 7z file into memory. Entries in a solid archive can only be read in order,
+// so anything else in it is read through as well.
+fn unpack(song_folder: &str, song_title: &str, report: &mut dyn FnMut(LoadProgress) -> bool) -> Result<(Vec<u8>, Vec<u8>), LoadError> {
+    let archive_path = archive_path(song_folder, song_title);
+    let (track_name, click_name) = (format!("{}.wav", song_title), format!("{}_click.wav", song_title));
+    if !archive_path.exists() {
+        return Err(LoadError::ArchiveMissing(archive_path));
+    }
+
+    let corrupt = |err: sevenz_rust::Error| LoadError::ArchiveCorrupt(archive_path.clone(), err.to_string());
+    let mut reader = SevenZReader::open(&archive_path, Password::empty()).map_err(corrupt)?;
+    let total = reader.archive().files.iter().map(|entry| entry.size()).sum::<u64>();
+    let mut done = 0;
+    let mut cancelled = false;
+    let (mut track, mut click) = (None, None);
+
+    reader
+        .for_each_entries(|entry, data| {
+            let is_wanted = entry.name() == track_name || entry.name() == click_name;
+            let mut bytes = if is_wanted {
+                Vec::with_capacity(entry.size().min(MAX_ENTRY_RESERVE) as usize)
+            } else {
+                Vec::new()
+            };
+            let mut sink = io::sink();
+            let out: &mut dyn Write = if is_wanted { &mut bytes } else { &mut sink };
+            if !copy_entry(data, out, &mut done, total, report).map_err(sevenz_rust::Error::io)? {
+                cancelled = true;
+                return Ok(false);
+            }
+
+            if entry.name() == track_name {
+                track = Some(bytes);
+            } else if entry.name() == click_name {
+                click = Some(bytes);
+            }
+            Ok(true)
+        })
+        .map_err(corrupt)?;
+
+    if cancelled {
+        return Err(LoadError::Cancelled);
+    }
+    match (track, click) {
+        (Some(track), Some(click)) => Ok((track, click)),
+        (None, _) => Err(LoadError::TrackMissing(archive_path)),
+        (_, None) => Err(LoadError::ClickMissing(archive_path)),
+    }
+}
+
+// Copies an archive entry in chunks, reporting as it goes. False when cancelled.
+fn copy_entry(data: &mut dyn Read, out: &mut dyn Write, done: &mut u64, total: u64, report: &mut dyn FnMut(LoadProgress) -> bool) -> io::Result<bool> {
+    let mut buffer = vec![0; EXTRACT_CHUNK];
+    loop {
+        let count = data.read(&mut buffer)?;
+        if count == 0 {
+            return Ok(true);
+        }
+        out.write_all(&buffer[..count])?;
+        *done += count as u64;
+        if !report(LoadProgress::Extracting(*done, total)) {
+            return Ok(false);
+        }
+    }
+}
+
+// Extracts the song's 7z file into its folder in the cache. Every file is written under a temporary name and renamed once complete,
+// so a cancelled or failed extraction never leaves a partial WAV behind to be played next time.
+fn extract(song_folder: &str, song_title: &str, report: &mut dyn FnMut(LoadProgress) -> bool) -> Result<()> {
+    let archive_path = archive_path(song_folder, song_title);
+    let destination = cache::song_folder(song_title);
+    fs::create_dir_all(&destination)?;
+
+    let mut reader = SevenZReader::open(&archive_path, Password::empty())?;
+    let total = reader.archive().files.iter().map(|entry| entry.size()).sum::<u64>();
+    let mut done = 0;
+    let mut cancelled = false;
+
+    reader.for_each_entries(|entry, data| {
+        let path = entry_path(&destination, entry.name()).map_err(sevenz_rust::Error::io)?;
+        if entry.is_directory() {
+            fs::create_dir_all(&path).map_err(sevenz_rust::Error::io)?;
+            return Ok(true);
+        }
+
+        let partial_path = partial_path(&path);
+        let mut file = BufWriter::new(File::create(&partial_path).map_err(sevenz_rust::Error::io)?);
+        cancelled = !copy_entry(data, &mut file, &mut done, total, report).map_err(sevenz_rust::Error::io)?;
+        file.flush().map_err(sevenz_rust::Error::io)?;
+        drop(file);
+
+        if cancelled {
+            if let Err(err) = fs::remove_file(&partial_path) {
+                warn!("Unable to remove {}: {}", partial_path.display(), err);
+            }
+            return Ok(false);
+        }
+        fs::rename(&partial_path, &path).map_err(sevenz_rust::Error::io)?;
+        Ok(true)
+    })?;
+
+    if cancelled {
+        fs::remove_dir(&destination).ok(); // only when nothing else is in it
+        return Err(eyre!("Extracting {} was cancelled", archive_path.display()));
+    }
+    info!("Decompressed file: {:?}", archive_path);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::wav;
+
+    #[test]
+    fn entries_stay_in_the_destination() {
+        let destination = Path::new("/cache/song");
+        assert_eq!(entry_path(destination, "song.wav").unwrap(), destination.join("song.wav"));
+        assert_eq!(entry_path(destination, "stems/kick.wav").unwrap(), destination.join("stems/kick.wav"));
+        for name in ["../../x", "stems/../../x", "/etc/x", "./song.wav", ""] {
+            assert!(entry_path(destination, name).is_err(), "{} was let through", name);
+        }
+    }
+
+    // A second of 16 bit mono silence
+    #[test]
+    fn a_cancelled_decode_is_not_a_broken_song() {
+        assert!(decode(Box::new(ArchiveMediaSource::new(wav(1, 16, &[0; 44100]))), "wav", &mut |_, _| true).is_ok());
+
+        let mut cancelled = false;
+        let err = decode(Box::new(ArchiveMediaSource::new(wav(1, 16, &[0; 44100]))), "wav", &mut |_, _| {
+            cancelled = true;
+            false
+        })
+        .map(|_| ())
+        .unwrap_err();
+        let err = decode_error(err, cancelled);
+        assert_eq!(err, LoadError::Cancelled);
+        assert!(!err.is_song_broken());
+    }
+
+    #[test]
+    fn partial_files_keep_their_extension() {
+        let (wav, flac) = (partial_path(Path::new("/cache/a.wav")), partial_path(Path::new("/cache/a.flac")));
+        assert_eq!(wav, Path::new("/cache/a.wav.part"));
+        assert_ne!(wav, flac);
+    }
+}
@@ -1,8 +1,8 @@
 use app::App;
-use crossbeam_channel::{unbounded, Receiver, Sender};
+use clap::{Parser, Subcommand};
+use drum_weaver::player::PlayerHandle;
 use log::LevelFilter;
 mod app;
-use app::player::{Player, PlayerCommand, PlayerEvent};
 
 use log4rs::append::rolling_file::policy::compound::roll::delete::DeleteRoller;
 use log4rs::append::rolling_file::policy::compound::{trigger::size::SizeTrigger, CompoundPolicy};
@@ -10,15 +10,67 @@ use log4rs::append::rolling_file::RollingFileAppender;
 use log4rs::config::{Appender, Config, Root};
 use log4rs::encode::pattern::PatternEncoder;
 
+#[derive(Parser)]
+#[command(version, about = "Practice drums along to drumless tracks")]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Reports songs that can't be played and files that aren't in the catalog. Exits with 1 if anything was found.
+    Check {
+        /// The root folder of the library
+        library: String,
+        /// Read every archive through and decode the extracted songs
+        #[arg(long)]
+        deep: bool,
+        /// Extract the broken songs again
+        #[arg(long)]
+        repair: bool,
+        /// Leave the songs that can't be played out of the library
+        #[arg(long)]
+        ignore: bool,
+    },
+    /// Extracts, or converts to FLAC, a set of songs ahead of time and pins them in the cache
+    Batch {
+        /// The root folder of the library
+        library: String,
+        /// Convert the songs to FLAC, which takes a little over half the disk
+        #[arg(long)]
+        flac: bool,
+        /// queue, all or a genre
+        #[arg(long, default_value = "all")]
+        songs: String,
+        /// Only the songs a search of the song list finds
+        #[arg(long)]
+        search: Option<String>,
+        /// Carry on with the batch that was interrupted
+        #[arg(long)]
+        resume: bool,
+    },
+}
+
 fn main() {
+    let cli = Cli::parse();
     init_logging();
+    app::crash::install_panic_hook();
 
-    let (player_command_sender, player_command_receiver): (Sender<PlayerCommand>, Receiver<PlayerCommand>) = unbounded();
-    let (player_event_sender, player_event_receiver): (Sender<PlayerEvent>, Receiver<PlayerEvent>) = unbounded();
-    let mut player = Player::new(player_command_receiver, player_event_sender);
-    player.run();
+    match cli.command {
+        Some(Command::Check { library, deep, repair, ignore }) => {
+            let is_clean = app::check::run_cli(&library, deep, repair, ignore);
+            std::process::exit(if is_clean { 0 } else { 1 });
+        }
+        Some(Command::Batch { library, flac, songs, search, resume }) => {
+            let is_done = app::batch::run_cli(&library, flac, &songs, search.as_deref(), resume);
+            std::process::exit(if is_done { 0 } else { 1 });
+        }
+        None => {}
+    }
 
-    let mut app = App::new(player_command_sender, player_event_receiver);
+    let player = PlayerHandle::start();
+    let mut app = App::new(player.commands, player.events);
     app.run();
 }
 
@@ -0,0 +1,226 @@
+//! A metronome for when we only want the click: rudiments, speed drills, polyrhythms.
+//! The pattern is generated as whole cycles, plus one spare, so the player can loop it without a gap.
+use std::time::{Duration, Instant};
+
+use color_eyre::eyre::Result;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    audio::Song,
+    beats::DEFAULT_BEATS_PER_BAR,
+    beep::{add_click, CLICK_SAMPLE_RATE},
+};
+
+/// The slowest the metronome goes
+pub const MIN_BPM: usize = 20;
+/// The fastest the metronome goes
+pub const MAX_BPM: usize = 400;
+const MAX_BEATS_PER_BAR: usize = 12;
+const MAX_SUBDIVISION: usize = 4; // sixteenths
+const MIN_CYCLE: Duration = Duration::from_secs(30); // how much is generated before it loops
+
+// Notes played evenly across a number of beats, e.g. 3:2 is three notes in the time of two beats
+const POLYRHYTHMS: [Option<(usize, usize)>; 6] = [None, Some((3, 2)), Some((4, 3)), Some((5, 4)), Some((2, 3)), Some((3, 4))];
+
+// Minutes the practice timer can be set to. None runs until stopped.
+const TIMERS: [Option<u64>; 8] = [None, Some(1), Some(2), Some(5), Some(10), Some(15), Some(20), Some(30)];
+
+const TAP_TIMEOUT: Duration = Duration::from_secs(2); // a longer gap between taps starts over
+const MAX_TAPS: usize = 8;
+
+/// The metronome's settings, kept in the configuration
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Metronome {
+    /// Beats per minute, from [`MIN_BPM`] to [`MAX_BPM`]
+    pub bpm: usize,
+    /// Beats per bar, 1 to 12
+    pub beats_per_bar: usize,
+    /// 4 or 8. Only changes how the time signature reads.
+    pub beat_unit: usize,
+    /// Clicks per beat
+    pub subdivision: usize,
+    /// One per beat of the bar
+    pub accents: Vec<bool>,
+    /// Notes played evenly across a number of beats, e.g. (3, 2) is three in the time of two
+    pub polyrhythm: Option<(usize, usize)>,
+    /// Minutes
+    pub timer: Option<u64>,
+}
+
+impl Default for Metronome {
+    fn default() -> Self {
+        Metronome::new(100, DEFAULT_BEATS_PER_BAR)
+    }
+}
+
+impl Metronome {
+    /// Accents the first beat of every bar
+    pub fn new(bpm: usize, beats_per_bar: usize) -> Self {
+        let beats_per_bar = beats_per_bar.clamp(1, MAX_BEATS_PER_BAR);
+        Metronome {
+            bpm: bpm.clamp(MIN_BPM, MAX_BPM),
+            beats_per_bar,
+            beat_unit: 4,
+            subdivision: 1,
+            accents: (0..beats_per_bar).map(|beat| beat == 0).collect(),
+            polyrhythm: None,
+            timer: None,
+        }
+    }
+
+    /// Sets the tempo, kept to [`MIN_BPM`] to [`MAX_BPM`]
+    pub fn set_bpm(&mut self, bpm: usize) {
+        self.bpm = bpm.clamp(MIN_BPM, MAX_BPM);
+    }
+
+    /// Speeds up or slows down by some beats per minute
+    pub fn change_bpm(&mut self, change: i32) {
+        self.set_bpm((self.bpm as i32 + change).max(0) as usize);
+    }
+
+    /// 1 to 12 beats, keeping the accents of the beats that are left
+    pub fn cycle_beats_per_bar(&mut self) {
+        self.beats_per_bar = self.beats_per_bar % MAX_BEATS_PER_BAR + 1;
+        self.accents.resize(self.beats_per_bar, false);
+    }
+
+    /// Switches between quarters and eighths
+    pub fn cycle_beat_unit(&mut self) {
+        self.beat_unit = if self.beat_unit == 4 { 8 } else { 4 };
+    }
+
+    /// No subdivision, eighths, triplets, sixteenths and round again
+    pub fn cycle_subdivision(&mut self) {
+        self.subdivision = self.subdivision % MAX_SUBDIVISION + 1;
+    }
+
+    /// Steps through the polyrhythms on offer, and none
+    pub fn cycle_polyrhythm(&mut self) {
+        let idx = POLYRHYTHMS.iter().position(|polyrhythm| *polyrhythm == self.polyrhythm).unwrap_or(0);
+        self.polyrhythm = POLYRHYTHMS[(idx + 1) % POLYRHYTHMS.len()];
+    }
+
+    /// Steps through the timers on offer, and none
+    pub fn cycle_timer(&mut self) {
+        let idx = TIMERS.iter().position(|timer| *timer == self.timer).unwrap_or(0);
+        self.timer = TIMERS[(idx + 1) % TIMERS.len()];
+    }
+
+    /// Beats are numbered from 1
+    pub fn toggle_accent(&mut self, beat: usize) {
+        self.accents.resize(self.beats_per_bar, false);
+        if let Some(accent) = beat.checked_sub(1).and_then(|idx| self.accents.get_mut(idx)) {
+            *accent = !*accent;
+        }
+    }
+
+    /// Whether a beat is accented. Beats are numbered from 1.
+    pub fn is_accented(&self, beat: usize) -> bool {
+        beat.checked_sub(1).and_then(|idx| self.accents.get(idx)).copied().unwrap_or(false)
+    }
+
+    /// How long the practice timer runs. None runs until stopped.
+    pub fn timer(&self) -> Option<Duration> {
+        self.timer.map(|minutes| Duration::from_secs(minutes * 60))
+    }
+
+    /// E.g. "7/8"
+    pub fn time_signature(&self) -> String {
+        format!("{}/{}", self.beats_per_bar, self.beat_unit)
+    }
+
+    /// What the subdivision is called, e.g. "triplets"
+    pub fn subdivision_name(&self) -> &'static str {
+        match self.subdivision {
+            1 => "none",
+            2 => "eighths",
+            3 => "triplets",
+            _ => "sixteenths",
+        }
+    }
+
+    /// E.g. "120 BPM in 4/4, eighths, 3:2"
+    pub fn describe(&self) -> String {
+        let mut description = format!("{} BPM in {}", self.bpm, self.time_signature());
+        if self.subdivision > 1 {
+            description.push_str(&format!(", {}", self.subdivision_name()));
+        }
+        if let Some((notes, beats)) = self.polyrhythm {
+            description.push_str(&format!(", {}:{}", notes, beats));
+        }
+        description
+    }
+
+    /// The click and how long a cycle of it is. The player goes back a cycle whenever it passes the end of one.
+    /// Beats are accented or not, subdivisions are quieter and higher, the polyrhythm lower.
+    pub fn song(&self) -> Result<(Song, Duration)> {
+        let rate = CLICK_SAMPLE_RATE as f64;
+        let beat_length = 60.0 / self.bpm.max(1) as f64; // seconds
+        let beats_per_bar = self.beats_per_bar.max(1);
+        let pattern_beats = match self.polyrhythm {
+            Some((_, beats)) => lcm(beats_per_bar, beats.max(1)),
+            None => beats_per_bar,
+        };
+        let pattern = beat_length * pattern_beats as f64;
+        let repeats = (MIN_CYCLE.as_secs_f64() / pattern).ceil().max(1.0) as usize;
+        let beat_count = pattern_beats * (repeats + 1);
+
+        let frame_at = |seconds: f64| (seconds * rate).round() as usize;
+        let mut samples = vec![0.0; frame_at(beat_count as f64 * beat_length)];
+        for beat in 0..beat_count {
+            let start = beat as f64 * beat_length;
+            let (frequency, level) = if self.is_accented(beat % beats_per_bar + 1) { (1500.0, 0.6) } else { (1000.0, 0.5) };
+            add_click(&mut samples, frame_at(start), frequency, level);
+
+            for part in 1..self.subdivision {
+                add_click(&mut samples, frame_at(start + beat_length * part as f64 / self.subdivision as f64), 2000.0, 0.25);
+            }
+        }
+
+        if let Some((notes, beats)) = self.polyrhythm {
+            let note_length = beat_length * beats.max(1) as f64 / notes.max(1) as f64;
+            for note in 0..beat_count / beats.max(1) * notes {
+                add_click(&mut samples, frame_at(note as f64 * note_length), 700.0, 0.5);
+            }
+        }
+
+        let song = Song::from_samples(vec![samples], CLICK_SAMPLE_RATE)?;
+        Ok((song, Duration::from_secs_f64(pattern * repeats as f64)))
+    }
+}
+
+fn lcm(a: usize, b: usize) -> usize {
+    let (mut x, mut y) = (a, b);
+    while y != 0 {
+        (x, y) = (y, x % y);
+    }
+    a / x * b
+}
+
+/// Works out a tempo from keys tapped along with the music
+#[derive(Default)]
+pub struct TapTempo {
+    taps: Vec<Instant>,
+}
+
+impl TapTempo {
+    /// The tempo of the recent taps, once there are at least two
+    pub fn tap(&mut self) -> Option<usize> {
+        let now = Instant::now();
+        if self.taps.last().is_some_and(|last| now - *last > TAP_TIMEOUT) {
+            self.taps.clear();
+        }
+        self.taps.push(now);
+        if self.taps.len() > MAX_TAPS {
+            self.taps.remove(0);
+        }
+
+        let (first, last) = (self.taps.first()?, self.taps.last()?);
+        let gaps = self.taps.len() - 1;
+        if gaps == 0 || *last == *first {
+            return None;
+        }
+        let beat = (*last - *first).as_secs_f64() / gaps as f64;
+        Some(((60.0 / beat).round() as usize).clamp(MIN_BPM, MAX_BPM))
+    }
+}
@@ -0,0 +1,184 @@
+// Writes a song's tempo map as a Standard MIDI File, so a DAW or an e-kit module can follow the song.
+// A single track holds the song name, then a tempo and, when it changes, a time signature at the start of every bar.
+// Whatever comes before the first bar plays at its tempo, so bar 1 lands where it is in the audio.
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use color_eyre::eyre::{ensure, Result};
+
+use super::beats::TempoBar;
+
+const TICKS_PER_QUARTER: u32 = 480;
+
+// In the exports folder beside the configuration, as the library may be read-only
+pub fn tempo_map_path(file_name: &str) -> Result<PathBuf> {
+    let config_path = confy::get_configuration_file_path("drum-weaver", None)?;
+    Ok(config_path.with_file_name("exports").join(format!("{}_tempo.mid", file_name)))
+}
+
+pub fn write_tempo_map(path: &Path, name: &str, bars: &[TempoBar]) -> Result<()> {
+    let file = tempo_map_file(name, bars)?;
+    if let Some(folder) = path.parent() {
+        fs::create_dir_all(folder)?;
+    }
+    fs::write(path, file)?;
+    Ok(())
+}
+
+fn tempo_map_file(name: &str, bars: &[TempoBar]) -> Result<Vec<u8>> {
+    ensure!(!bars.is_empty(), "No bars to export");
+
+    let mut track = vec![];
+    meta_event(&mut track, 0, 0x03, name.as_bytes());
+
+    let mut delta = 0;
+    if !bars[0].start.is_zero() {
+        let micros_per_quarter = micros_per_quarter(&bars[0]);
+        meta_event(&mut track, 0, 0x51, &micros_per_quarter.to_be_bytes()[1..]);
+        delta = (bars[0].start.as_micros() as f64 * TICKS_PER_QUARTER as f64 / micros_per_quarter as f64).round() as u32;
+    }
+    let mut time_signature = None;
+    for bar in bars.iter() {
+        let (numerator, denominator) = bar.time_signature();
+        if time_signature != Some((numerator, denominator)) {
+            // numerator, denominator as a power of two, MIDI clocks per click, 32nds per quarter
+            let signature = [numerator as u8, denominator.trailing_zeros() as u8, 24, 8];
+            meta_event(&mut track, delta, 0x58, &signature);
+            delta = 0;
+            time_signature = Some((numerator, denominator));
+        }
+
+        meta_event(&mut track, delta, 0x51, &micros_per_quarter(bar).to_be_bytes()[1..]);
+
+        delta = TICKS_PER_QUARTER * 4 / denominator as u32 * numerator as u32;
+    }
+    meta_event(&mut track, delta, 0x2F, &[]); // end of track
+
+    let mut file = vec![];
+    file.extend_from_slice(b"MThd");
+    file.extend_from_slice(&6u32.to_be_bytes());
+    file.extend_from_slice(&0u16.to_be_bytes()); // format 0, a single track
+    file.extend_from_slice(&1u16.to_be_bytes());
+    file.extend_from_slice(&(TICKS_PER_QUARTER as u16).to_be_bytes());
+    file.extend_from_slice(b"MTrk");
+    file.extend_from_slice(&(track.len() as u32).to_be_bytes());
+    file.extend_from_slice(&track);
+    Ok(file)
+}
+
+// bpm counts clicks, and a click in x/8 is an eighth
+fn micros_per_quarter(bar: &TempoBar) -> u32 {
+    let quarter_bpm = bar.bpm * 4.0 / bar.time_signature().1 as f64;
+    ((60_000_000.0 / quarter_bpm) as u32).min(0xFF_FFFF)
+}
+
+fn meta_event(track: &mut Vec<u8>, delta: u32, kind: u8, data: &[u8]) {
+    write_variable_length(track, delta);
+    track.extend_from_slice(&[0xFF, kind]);
+    write_variable_length(track, data.len() as u32);
+    track.extend_from_slice(data);
+}
+
+// Seven bits per byte, most significant first, the high bit set on all but the last
+fn write_variable_length(track: &mut Vec<u8>, value: u32) {
+    let mut bytes = vec![(value & 0x7F) as u8];
+    let mut rest = value >> 7;
+    while rest > 0 {
+        bytes.push((rest & 0x7F) as u8 | 0x80);
+        rest >>= 7;
+    }
+    bytes.reverse();
+    track.extend_from_slice(&bytes);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    // When each bar starts and the time signature it has, by reading the file back and following its tempo changes
+    fn read_bars(file: &[u8]) -> Vec<(Duration, (u8, u8))> {
+        assert_eq!(&file[..4], b"MThd");
+        let ticks_per_quarter = u16::from_be_bytes([file[12], file[13]]) as f64;
+        assert_eq!(&file[14..18], b"MTrk");
+        let track = &file[22..];
+
+        let read_variable_length = |at: &mut usize| {
+            let mut value = 0u32;
+            loop {
+                let byte = track[*at];
+                *at += 1;
+                value = (value << 7) | (byte & 0x7F) as u32;
+                if byte & 0x80 == 0 {
+                    return value;
+                }
+            }
+        };
+
+        let (mut at, mut seconds, mut micros_per_quarter) = (0, 0.0, 500_000.0);
+        let mut bars = vec![];
+        let mut time_signature = (4, 2);
+        while at < track.len() {
+            seconds += read_variable_length(&mut at) as f64 * micros_per_quarter / ticks_per_quarter / 1_000_000.0;
+            assert_eq!(track[at], 0xFF);
+            let kind = track[at + 1];
+            at += 2;
+            let len = read_variable_length(&mut at) as usize;
+            let data = &track[at..at + len];
+            at += len;
+            match kind {
+                0x58 => time_signature = (data[0], data[1]),
+                0x51 => {
+                    micros_per_quarter = u32::from_be_bytes([0, data[0], data[1], data[2]]) as f64;
+                    // every bar has a tempo, so it starts with one, after any time signature
+                    if bars.last().is_none_or(|(start, _): &(Duration, _)| (start.as_secs_f64() - seconds).abs() > 1e-9) {
+                        bars.push((Duration::from_secs_f64(seconds), time_signature));
+                    }
+                }
+                _ => {}
+            }
+        }
+        bars
+    }
+
+    fn bar(start_ms: u64, bpm: f64, beats_per_bar: usize) -> TempoBar {
+        TempoBar {
+            start: Duration::from_millis(start_ms),
+            bpm,
+            beats_per_bar,
+        }
+    }
+
+    fn assert_close(found: Duration, expected: Duration) {
+        let error = (found.as_secs_f64() - expected.as_secs_f64()).abs();
+        assert!(error < 0.002, "{:?} instead of {:?}", found, expected);
+    }
+
+    #[test]
+    fn bar_one_lands_after_the_lead_in() {
+        // 1.3 s of silence, then two bars of 4/4 at 120 and one of 6/8 at 180 clicks a minute
+        let bars = [bar(1300, 120.0, 4), bar(3300, 120.0, 4), bar(5300, 180.0, 6)];
+        let read = read_bars(&tempo_map_file("song", &bars).unwrap());
+
+        // the lead-in is the first tempo, with bar 1 of the song after it
+        assert_eq!(read.len(), 4);
+        assert_eq!(read[0].0, Duration::ZERO);
+        for (found, expected) in read[1..].iter().zip(bars.iter()) {
+            assert_close(found.0, expected.start);
+        }
+        assert_eq!(read[1].1, (4, 2));
+        assert_eq!(read[3].1, (6, 3));
+    }
+
+    #[test]
+    fn bar_one_at_the_start_needs_no_lead_in() {
+        let bars = [bar(0, 90.0, 4), bar(2667, 90.0, 4)];
+        let read = read_bars(&tempo_map_file("song", &bars).unwrap());
+        assert_eq!(read.len(), 2);
+        assert_eq!(read[0].0, Duration::ZERO);
+        assert_close(read[1].0, bars[1].start);
+    }
+}
@@ -0,0 +1,405 @@
+//! The monitor mix: which sources are heard on which outputs, and how loud.
+//! The model, a [`MixMatrix`], is shared with the UI and saved in the config. The [`Mixer`] runs it on the player thread.
+use std::time::Duration;
+
+use color_eyre::eyre::Result;
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    audio::{AudioPlayer, Song},
+    player::{OutputConfig, CLICK_OUTPUT, TRACK_OUTPUT},
+};
+
+/// The loudest a send can be, in percent
+pub const MAX_GAIN: usize = 200;
+/// How far a send can be panned either way
+pub const MAX_PAN: i32 = 100;
+
+/// Something that can be heard in the mix
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MixSource {
+    /// The drumless track
+    Track,
+    /// The song's click
+    Click,
+    /// Synthesized from the song's BPM
+    GeneratedClick,
+    /// `<file_name>_cue.wav` next to the track, if present
+    Cue,
+    /// `<file_name>_take.wav` next to the track, if present
+    Take,
+}
+
+impl MixSource {
+    /// Also the voice order. The track comes first so it drives the transport.
+    pub const ALL: [MixSource; 5] = [MixSource::Track, MixSource::Click, MixSource::GeneratedClick, MixSource::Cue, MixSource::Take];
+
+    /// The name shown in the mixer view
+    pub fn label(&self) -> &'static str {
+        match self {
+            MixSource::Track => "Track",
+            MixSource::Click => "Click",
+            MixSource::GeneratedClick => "Generated click",
+            MixSource::Cue => "Cue",
+            MixSource::Take => "Take",
+        }
+    }
+}
+
+/// One cell of the matrix: how a source is sent to an output
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct MixCell {
+    /// Percent, 0 to [`MAX_GAIN`]
+    pub gain: usize,
+    /// Silenced, keeping the gain and pan for when it is unmuted
+    pub muted: bool,
+    /// -[`MAX_PAN`] (left) to [`MAX_PAN`] (right)
+    pub pan: i32,
+}
+
+impl Default for MixCell {
+    fn default() -> Self {
+        MixCell { gain: 100, muted: false, pan: 0 }
+    }
+}
+
+impl MixCell {
+    /// What a source/output pair gets when nothing has been routed
+    pub const OFF: MixCell = MixCell { gain: 100, muted: true, pan: 0 };
+
+    /// Whether anything is heard
+    pub fn is_audible(&self) -> bool {
+        !self.muted && self.gain > 0
+    }
+
+    /// Gain for each output channel. Panning is a balance control on each stereo pair.
+    pub fn channel_gains(&self, channel_count: usize) -> Vec<f32> {
+        if self.muted {
+            return vec![0.0; channel_count];
+        }
+
+        let gain = self.gain as f32 / 100.0;
+        if channel_count < 2 {
+            return vec![gain; channel_count];
+        }
+
+        let pan = self.pan as f32 / MAX_PAN as f32;
+        (0..channel_count).map(|c| if c % 2 == 0 { gain * (1.0 - pan).min(1.0) } else { gain * (1.0 + pan).min(1.0) }).collect()
+    }
+
+    /// E.g. "80% L20", or "muted"
+    pub fn describe(&self) -> String {
+        if self.muted {
+            return "muted".to_string();
+        }
+
+        let pan = match self.pan {
+            0 => "C".to_string(),
+            pan if pan < 0 => format!("L{}", -pan),
+            pan => format!("R{}", pan),
+        };
+        format!("{}% {}", self.gain, pan)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+struct MixRoute {
+    source: MixSource,
+    output: String, // output name
+    cell: MixCell,
+}
+
+/// Sources by outputs. Pairs without a route are off.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct MixMatrix {
+    routes: Vec<MixRoute>,
+}
+
+impl Default for MixMatrix {
+    // The classic setup: the track on the track device, the click and a copy of the track (the bleed) on the click device
+    fn default() -> Self {
+        MixMatrix::from_volumes(100, 100, 100)
+    }
+}
+
+impl MixMatrix {
+    /// Builds the classic setup from the track, click and bleed volumes older configs stored
+    pub fn from_volumes(track_volume: usize, click_volume: usize, bleed_volume: usize) -> Self {
+        let mut matrix = MixMatrix { routes: Vec::new() };
+        let cell = |gain: usize| MixCell {
+            gain: gain.min(MAX_GAIN),
+            ..MixCell::default()
+        };
+        matrix.set_cell(MixSource::Track, TRACK_OUTPUT, cell(track_volume));
+        matrix.set_cell(MixSource::Click, CLICK_OUTPUT, cell(click_volume));
+        matrix.set_cell(MixSource::Track, CLICK_OUTPUT, cell(bleed_volume));
+        matrix
+    }
+
+    /// The send from a source to an output, [`MixCell::OFF`] when there is none
+    pub fn cell(&self, source: MixSource, output: &str) -> MixCell {
+        self.routes.iter().find(|r| r.source == source && r.output == output).map(|r| r.cell).unwrap_or(MixCell::OFF)
+    }
+
+    /// Sets the send from a source to an output
+    pub fn set_cell(&mut self, source: MixSource, output: &str, cell: MixCell) {
+        match self.routes.iter_mut().find(|r| r.source == source && r.output == output) {
+            Some(route) => route.cell = cell,
+            None => self.routes.push(MixRoute {
+                source,
+                output: output.to_string(),
+                cell,
+            }),
+        }
+    }
+
+    /// Forgets the sends to an output that was deleted
+    pub fn remove_output(&mut self, output: &str) {
+        self.routes.retain(|r| r.output != output);
+    }
+
+    /// Whether a source is heard anywhere. Sources nobody hears aren't loaded.
+    pub fn is_routed(&self, source: MixSource) -> bool {
+        self.routes.iter().any(|r| r.source == source && r.cell.is_audible())
+    }
+}
+
+/// Plays the loaded sources on every output through one AudioPlayer per output, each source being a voice.
+/// All outputs share the transport: play state, speed and position.
+pub struct Mixer {
+    matrix: MixMatrix,
+    sources: Vec<(MixSource, Song)>,
+    outputs: Vec<(String, AudioPlayer)>, // by output name. The first one reports the position.
+    speed: f64,
+    playing: bool,
+    counting_in: bool, // the sources start once the count-in has played
+}
+
+impl Mixer {
+    /// A mixer with no outputs and nothing loaded
+    pub fn new(matrix: MixMatrix) -> Self {
+        Mixer {
+            matrix,
+            sources: Vec::new(),
+            outputs: Vec::new(),
+            speed: 1.0,
+            playing: true,
+            counting_in: false,
+        }
+    }
+
+    /// The mix in effect
+    pub fn matrix(&self) -> &MixMatrix {
+        &self.matrix
+    }
+
+    /// The player of an output, if it is open
+    pub fn output(&self, output: &str) -> Option<&AudioPlayer> {
+        self.outputs.iter().find(|(o, _)| o == output).map(|(_, player)| player)
+    }
+
+    /// A stream error usually means the device went away
+    pub fn has_stream_error(&self, output: &str) -> bool {
+        self.output(output).is_some_and(|player| player.has_stream_error())
+    }
+
+    /// Replaces the player of an output, carrying over the transport and resuming the sources where they were.
+    /// The old player is released first, since JACK port names have to be free before we register them again.
+    pub fn open_output<F>(&mut self, config: &OutputConfig, open: F) -> Result<()>
+    where
+        F: FnOnce() -> Result<AudioPlayer>,
+    {
+        let position = self.position().map(|(position, _)| position);
+        self.remove_output(&config.name);
+
+        let player = open()?;
+        player.set_playback_speed(self.speed);
+        player.set_playing(self.playing);
+        player.set_channel_map(config.channel_map(player.channel_count()));
+        self.apply_gains(&config.name, &player);
+        if !self.sources.is_empty() && !self.counting_in {
+            player.play_songs_now(&self.songs(), position)?;
+        }
+
+        self.outputs.push((config.name.clone(), player));
+        Ok(())
+    }
+
+    /// Closes an output
+    pub fn remove_output(&mut self, output: &str) {
+        if let Some(idx) = self.outputs.iter().position(|(o, _)| o == output) {
+            let (_, previous) = self.outputs.remove(idx);
+            previous.stop();
+        }
+    }
+
+    /// Starts the given sources from the beginning on every output, after the count-in if there is one
+    pub fn load(&mut self, sources: Vec<(MixSource, Song)>, count_in: Option<&Song>) -> Result<()> {
+        self.sources = sources;
+        match count_in {
+            Some(count_in) => self.count_in(count_in),
+            None => self.restart_voices(None),
+        }
+    }
+
+    /// Plays a count-in wherever the click is heard, then the sources from the beginning. See [`update`](Mixer::update).
+    pub fn count_in(&mut self, count_in: &Song) -> Result<()> {
+        self.counting_in = !self.outputs.is_empty();
+        for (output, player) in self.outputs.iter() {
+            self.apply_gains(output, player);
+            player.play_song_now(count_in, None)?;
+        }
+        Ok(())
+    }
+
+    /// Whether the count-in is playing rather than the sources
+    pub fn is_counting_in(&self) -> bool {
+        self.counting_in
+    }
+
+    /// Called regularly by the player. Starts the sources when the count-in is over.
+    pub fn update(&mut self) -> Result<()> {
+        if self.counting_in && !self.outputs.first().is_some_and(|(_, player)| player.has_current_song()) {
+            self.counting_in = false;
+            self.restart_voices(None)?;
+        }
+        Ok(())
+    }
+
+    /// Adds a source to what is playing, e.g. when a cell is unmuted mid song
+    pub fn add_source(&mut self, source: MixSource, song: Song) -> Result<()> {
+        let position = self.position().map(|(position, _)| position);
+        self.sources.push((source, song));
+        self.sources.sort_by_key(|(s, _)| MixSource::ALL.iter().position(|a| a == s));
+        if self.counting_in {
+            return Ok(()); // starts with the others
+        }
+        self.restart_voices(position)
+    }
+
+    /// Whether the source is loaded
+    pub fn has_source(&self, source: MixSource) -> bool {
+        self.source(source).is_some()
+    }
+
+    /// The song loaded for the source, if any
+    pub fn source(&self, source: MixSource) -> Option<&Song> {
+        self.sources.iter().find(|(s, _)| *s == source).map(|(_, song)| song)
+    }
+
+    /// Stops every source and the count-in, keeping the outputs open
+    pub fn stop(&mut self) {
+        self.sources.clear();
+        self.counting_in = false;
+        for (_, player) in self.outputs.iter() {
+            player.stop();
+        }
+    }
+
+    /// Plays a short sound on one output, e.g. the beep when a device is picked. Does nothing while a song is loaded.
+    pub fn play_on_output(&mut self, output: &str, song: &Song) -> Result<()> {
+        if !self.sources.is_empty() {
+            return Ok(());
+        }
+
+        self.set_playing(true);
+        if let Some(player) = self.output(output) {
+            player.set_voice_gains(Vec::new());
+            player.play_song_now(song, None)?;
+        }
+        Ok(())
+    }
+
+    /// Replaces the whole mix, applying it straight away
+    pub fn set_matrix(&mut self, matrix: MixMatrix) {
+        self.matrix = matrix;
+        for (output, player) in self.outputs.iter() {
+            self.apply_gains(output, player);
+        }
+    }
+
+    /// Changes one send, applying it straight away
+    pub fn set_cell(&mut self, source: MixSource, output: &str, cell: MixCell) {
+        self.matrix.set_cell(source, output, cell);
+        if let Some(player) = self.output(output) {
+            self.apply_gains(output, player);
+        }
+    }
+
+    /// Whether the transport is playing rather than paused
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    /// Plays or pauses every output
+    pub fn set_playing(&mut self, playing: bool) {
+        self.playing = playing;
+        for (_, player) in self.outputs.iter() {
+            player.set_playing(playing);
+        }
+    }
+
+    /// The playback speed, 1.0 being the original
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    /// Sets the playback speed of every output
+    pub fn set_speed(&mut self, speed: f64) {
+        self.speed = speed;
+        for (_, player) in self.outputs.iter() {
+            player.set_playback_speed(speed);
+        }
+    }
+
+    /// The position in the song and its length, from the first output
+    pub fn position(&self) -> Option<(Duration, Duration)> {
+        self.outputs.first().and_then(|(_, player)| player.get_playback_position())
+    }
+
+    /// Each output's loudest sample since the last call
+    pub fn take_levels(&self) -> Vec<(String, f32)> {
+        self.outputs.iter().map(|(output, player)| (output.clone(), player.take_peak())).collect()
+    }
+
+    /// Moves every output to the same position
+    pub fn seek(&self, position: Duration) {
+        for (_, player) in self.outputs.iter() {
+            player.seek(position);
+        }
+    }
+
+    /// Whether the first output is still playing a song
+    pub fn has_current_song(&self) -> bool {
+        self.outputs.first().is_some_and(|(_, player)| player.has_current_song())
+    }
+
+    fn songs(&self) -> Vec<&Song> {
+        self.sources.iter().map(|(_, song)| song).collect()
+    }
+
+    fn restart_voices(&self, position: Option<Duration>) -> Result<()> {
+        if self.sources.is_empty() {
+            return Ok(());
+        }
+        for (output, player) in self.outputs.iter() {
+            self.apply_gains(output, player);
+            if let Err(err) = player.play_songs_now(&self.songs(), position) {
+                error!("Unable to play on output {}: {:?}", output, err);
+                return Err(err);
+            }
+        }
+        info!("Mixing {:?}", self.sources.iter().map(|(source, _)| source).collect::<Vec<_>>());
+        Ok(())
+    }
+
+    fn apply_gains(&self, output: &str, player: &AudioPlayer) {
+        let gains = if self.counting_in {
+            vec![self.matrix.cell(MixSource::Click, output).channel_gains(player.channel_count())]
+        } else {
+            self.sources.iter().map(|(source, _)| self.matrix.cell(*source, output).channel_gains(player.channel_count())).collect()
+        };
+        player.set_voice_gains(gains);
+    }
+}
@@ -0,0 +1,1080 @@
+//! The player thread: it loads and plays songs through the mixer, runs the metronome, loops and count-ins, and keeps the
+//! practice settings and history. A front end drives it through a [`PlayerHandle`].
+use std::{
+    any::Any,
+    collections::HashMap,
+    io,
+    path::PathBuf,
+    thread,
+    time::{Duration, Instant},
+};
+
+use color_eyre::eyre::Result;
+use cpal::{traits::DeviceTrait, Device};
+use crossbeam_channel::{bounded, unbounded, Receiver, Select, Sender, TryRecvError};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::audio::{Song, MAXIMUM_PLAYBACK_SPEED, MINIMUM_PLAYBACK_SPEED};
+
+use super::{
+    audio::AudioPlayer,
+    beats::{BarBeat, BeatGrid, DEFAULT_BEATS_PER_BAR},
+    beep::{beep_song, click_song, test_signal_song, TestSignal},
+    devices::{default_device, find_device, is_jack_host, resolve_device, set_host, DevicePreference},
+    history::{PlayRecord, PlaySession},
+    library::SongRecord,
+    loader::{LoadError, LoadJob, LoadProgress},
+    metronome::Metronome,
+    midi::{tempo_map_path, write_tempo_map},
+    mixer::{MixCell, MixMatrix, MixSource, Mixer},
+    prefetch::{PrefetchSettings, Prefetcher},
+    song_data::{LoopRegion, SongData},
+};
+struct Player {
+    player_command_receiver: Receiver<PlayerCommand>,
+    player_event_sender: Sender<PlayerEvent>,
+}
+
+/// A running player. It takes [`PlayerCommand`]s and answers with [`PlayerEvent`]s, sending the playback status on its own.
+///
+/// The player runs on its own thread and is restarted when it panics, after a [`PlayerEvent::Crashed`]. It starts with
+/// no outputs and stops once told to [`PlayerCommand::Quit`].
+pub struct PlayerHandle {
+    /// Where commands go. [`send`](PlayerHandle::send) is the same without the error.
+    pub commands: Sender<PlayerCommand>,
+    /// Everything the player has to say
+    pub events: Receiver<PlayerEvent>,
+}
+
+impl PlayerHandle {
+    /// Starts a player. Give it [`PlayerCommand::SetOutputs`] before playing anything.
+    pub fn start() -> PlayerHandle {
+        let (commands, player_command_receiver) = unbounded();
+        let (player_event_sender, events) = unbounded();
+        Player::new(player_command_receiver, player_event_sender).run();
+        PlayerHandle { commands, events }
+    }
+
+    /// False once the player is gone
+    pub fn send(&self, command: PlayerCommand) -> bool {
+        self.commands.send(command).is_ok()
+    }
+}
+
+/// The track's output in older configs, which had one device for the track and one for the click
+pub const TRACK_OUTPUT: &str = "Track";
+/// The click's output in older configs
+pub const CLICK_OUTPUT: &str = "Click";
+
+/// A named output: a device and the device channels that carry our left and right.
+/// Any number of them play from the same transport, each with its own levels in the mix.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OutputConfig {
+    /// Shown in the UI, and what the mix routes to
+    pub name: String,
+    /// None plays on the default output
+    pub device_name: Option<String>,
+    /// 1-based device channels for left and right. Empty plays on every channel.
+    #[serde(default)]
+    pub channels: Vec<usize>,
+}
+
+impl OutputConfig {
+    /// An output on every channel of the device, or the default output when no device is given
+    pub fn new(name: &str, device_name: Option<String>) -> Self {
+        OutputConfig {
+            name: name.to_string(),
+            device_name,
+            channels: Vec::new(),
+        }
+    }
+
+    /// What configs from before named outputs get
+    pub fn defaults(track_device_name: Option<String>, click_device_name: Option<String>) -> Vec<OutputConfig> {
+        vec![OutputConfig::new(TRACK_OUTPUT, track_device_name), OutputConfig::new(CLICK_OUTPUT, click_device_name)]
+    }
+
+    /// Also the JACK port prefix, e.g. "track" gives track_L and track_R
+    pub fn port_name(&self) -> String {
+        self.name.to_lowercase().chars().map(|c| if c.is_alphanumeric() { c } else { '_' }).collect()
+    }
+
+    /// Which mixed channel each device channel plays. Channels not listed are silent.
+    pub fn channel_map(&self, channel_count: usize) -> Vec<Option<usize>> {
+        if self.channels.is_empty() {
+            return Vec::new();
+        }
+
+        (1..=channel_count).map(|device_channel| self.channels.iter().position(|c| *c == device_channel)).collect()
+    }
+
+    /// The channels as shown in the device view, e.g. "3+4", or "All"
+    pub fn describe_channels(&self) -> String {
+        if self.channels.is_empty() {
+            return "All".to_string();
+        }
+
+        self.channels.iter().map(|c| c.to_string()).collect::<Vec<_>>().join("+")
+    }
+}
+
+/// A song as the player needs it: where its files are and what to show for it
+#[derive(Debug, Clone)]
+pub struct SongStub {
+    /// The name of the song's archive, which identifies it
+    pub file_name: String,
+    /// The title to show
+    pub title: String,
+    /// The artist to show
+    pub artist: String,
+    /// The folder its archive is in
+    pub folder: String,
+    /// Its tempo in beats per minute, 0 when unknown
+    pub bpm: usize,
+}
+
+/// Where playback is, sent by the player on its own while a song or the metronome plays
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlaybackStatus {
+    /// Where in the song playback is. None while the metronome plays.
+    pub track_position: Option<Duration>,
+    /// How long the song is
+    pub track_duration: Option<Duration>,
+    /// The bar and beat playing, when the beats are known
+    pub bar_beat: Option<BarBeat>,
+    /// The playback speed, 1.0 being the original
+    pub speed: f64,
+    /// Each output's peak since the last status, while playing
+    pub levels: Vec<(String, f32)>,
+}
+impl SongStub {
+    /// The stub for a song of the library
+    pub fn from_song_record(song_record: &SongRecord) -> Self {
+        SongStub {
+            file_name: song_record.file_name.clone(),
+            title: song_record.title.clone(),
+            folder: song_record.folder.clone(),
+            artist: song_record.artist.clone(),
+            bpm: song_record.bpm,
+        }
+    }
+}
+
+/// What a front end asks of the player, through [`PlayerHandle::send`]
+#[derive(Debug)]
+pub enum PlayerCommand {
+    /// Loads the song in the background and plays it once ready. Whatever plays carries on until then.
+    Play(SongStub),
+    /// Just the click, looped until something else plays
+    PlayMetronome(Metronome),
+    /// Stops the song or metronome, keeping the outputs open
+    Stop,
+    /// Pauses, or carries on when paused
+    Pause,
+    /// Stops and ends the player, after a [`PlayerEvent::Quit`]
+    Quit,
+    /// A bar, or 10 seconds when the bars are unknown
+    Forward,
+    /// A bar back, or 10 seconds when the bars are unknown
+    Backward,
+    /// Jumps to a position in the song
+    Seek(Duration),
+    /// Bars are numbered as shown, the pickup being bar 0
+    SeekBar(usize),
+    /// 1.0 is the original speed
+    SetSpeed(f64),
+    /// E.g. 0.01 for a percent faster
+    ChangeSpeed(f64),
+    /// The outputs to play on. Devices are looked up by name, falling back to the default output while missing.
+    SetOutputs(Vec<OutputConfig>),
+    /// Goes back to the original speed
+    ResetSpeed,
+    /// Changes the send from a source to an output
+    SetMixCell(MixSource, String, MixCell),
+    /// Which sources are heard on which outputs, and how loud
+    SetMix(MixMatrix),
+    /// Plays a region of the song over and over, or stops looping
+    SetLoop(Option<LoopRegion>),
+    /// Bars of click before the song starts
+    SetCountIn(usize),
+    /// Saves the song's tempo map as a MIDI file in the exports folder beside the configuration
+    ExportTempoMap,
+    /// Plays the song again from the start
+    Restart,
+    /// Looks for the configured devices again, e.g. after plugging one in
+    RescanDevices,
+    /// The sample rate and buffer size to open a device with, by device name
+    SetDevicePreference(String, DevicePreference),
+    /// The audio host to look for devices on. None is the platform default.
+    SetHost(Option<String>),
+    /// Our JACK port names to the ports they connect to
+    SetJackConnections(HashMap<String, Vec<String>>),
+    /// Plays a test signal on one channel of an output's device
+    TestChannel(String, usize, TestSignal),
+    /// The songs that play next, in order, to get ready ahead of time
+    Prefetch(Vec<SongStub>),
+    /// How far ahead songs are got ready, and how
+    SetPrefetch(PrefetchSettings),
+    /// Status updates a second while playing
+    SetStatusRate(u32),
+}
+
+/// What the player tells a front end, on [`PlayerHandle::events`]
+#[derive(Debug)]
+pub enum PlayerEvent {
+    /// The song has loaded and is playing
+    Playing(SongStub),
+    /// The song didn't load, and why. Whatever was playing carries on.
+    LoadFailure(SongStub, LoadError),
+    /// Where playback is. Sent on its own at the status rate.
+    Status(PlaybackStatus),
+    /// Playback was paused
+    Paused,
+    /// Playing again after a pause. No song while only the metronome plays.
+    Continuing(Option<SongStub>),
+    /// The song played to its end
+    Ended,
+    /// Sent as a song loads, before it plays
+    Loading(SongStub, LoadProgress),
+    /// An output's device is missing, so it plays on the default output: output name, device name
+    DeviceFallback(String, String),
+    /// The device is back
+    DeviceRestored(String, String),
+    /// Something to show the user, e.g. where a file was saved
+    Notice(String),
+    /// Sent when a song stops, however it stopped, for the practice history
+    Played(PlayRecord),
+    /// The panic message, and whether the player was restarted. A restarted player has to be set up again.
+    Crashed(String, bool),
+    /// The player has stopped, as asked
+    Quit,
+}
+
+// More crashes than this in the window and the player is left down, rather than crashing over and over
+const MAX_CRASHES: usize = 3;
+const CRASH_WINDOW: Duration = Duration::from_secs(60);
+
+// While something plays or loads the player wakes this often to keep loops, ramps and the metronome on time.
+// Otherwise it sleeps until a command comes, an output fails or a device check falls due.
+const LOOP_INTERVAL: Duration = Duration::from_millis(5);
+/// Often enough for the gauge to move smoothly and the beat indicator to flash on time
+pub const DEFAULT_STATUS_RATE: u32 = 30;
+
+// How often we look for a missing device to come back, at first and after it stayed away a while
+const DEVICE_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+const MAX_DEVICE_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+// Speed changes glide at this many times the original speed per second
+const SPEED_RAMP_RATE: f64 = 0.25;
+const RAMP_INTERVAL: Duration = Duration::from_millis(20);
+
+// Everything besides the device itself that decides how a player opens its output
+#[derive(Default)]
+struct OutputSettings {
+    device_preferences: HashMap<String, DevicePreference>,
+    jack_connections: HashMap<String, Vec<String>>, // our port name -> ports to connect it to
+}
+
+// The device a player is wanted on versus where it actually lives
+struct DeviceBinding {
+    wanted: Option<String>,
+    device: Device,
+    is_fallback: bool,
+}
+
+impl Player {
+    fn new(player_command_receiver: Receiver<PlayerCommand>, player_event_sender: Sender<PlayerEvent>) -> Self {
+        Player {
+            player_command_receiver,
+            player_event_sender,
+        }
+    }
+
+    fn run(&mut self) {
+        let player_event_sender = self.player_event_sender.clone();
+        let player_command_receiver = self.player_command_receiver.clone();
+
+        // Each restart after a crash starts over from here, with a copy of the channels
+        let serve = move || {
+            // Outputs come from configuration via SetOutputs, the mix via SetMix
+            let mut outputs: Vec<OutputConfig> = Vec::new();
+            let mut bindings: HashMap<String, DeviceBinding> = HashMap::new();
+            let mut output_settings = OutputSettings::default();
+            let mut mixer = Mixer::new(MixMatrix::default());
+
+            // Plays test signals on a single channel. Dropped once the signal has finished.
+            let mut test_player: Option<AudioPlayer> = None;
+
+            let mut current_stub: Option<SongStub> = None;
+            let mut beat_grid: Option<BeatGrid> = None; // of the current song
+            let mut last_device_check = Instant::now();
+            let mut device_check_interval = DEVICE_CHECK_INTERVAL;
+            let mut device_check: Option<Receiver<bool>> = None; // whether a device waited for is there, once looked up
+            let mut device_check_due: Option<Instant> = None; // while a device is waited for
+            let (wake_sender, wake_receiver) = unbounded::<()>(); // told when an output fails
+            let mut host_changed = false;
+            let mut target_speed = 1.0;
+
+            // The practice settings in effect. A song's saved ones are restored when it is loaded, not when it is replayed.
+            let mut settings_song: Option<String> = None; // file name
+            let mut loop_region: Option<LoopRegion> = None;
+            let mut count_in_bars = 0;
+            let mut base_matrix = MixMatrix::default(); // the mix for songs without their own
+            let mut song_mix = false;
+            let mut last_ramp = Instant::now();
+            let mut session: Option<PlaySession> = None; // the play of the current song, for the history
+            let mut metronome_cycle: Option<Duration> = None; // while the metronome plays, how long before it repeats
+            let mut loading: Option<LoadJob> = None; // the song to play next, while it loads
+            let mut prefetcher = Prefetcher::default();
+            let mut status_interval = Duration::from_secs(1) / DEFAULT_STATUS_RATE;
+            let mut last_status: Option<PlaybackStatus> = None; // the last one sent, and when
+            let mut last_status_sent = Instant::now();
+
+            // run a thread that monitors the player and sends an Ended event to the UI thread when the song is complete. Used for autoplay
+
+            loop {
+                let is_busy = mixer.has_current_song() || loading.is_some() || test_player.is_some() || mixer.speed() != target_speed;
+
+                // The status goes out at the status rate, and only when it changed, so nothing is sent while idle.
+                // Before sleeping the last change goes out straight away.
+                if !is_busy || last_status_sent.elapsed() >= status_interval {
+                    let status = Self::status(&mixer, beat_grid.as_ref());
+                    if last_status.as_ref() != Some(&status) {
+                        player_event_sender.send(PlayerEvent::Status(status.clone())).unwrap();
+                        last_status = Some(status);
+                    }
+                    last_status_sent = Instant::now();
+                }
+
+                // Wait for a command, an output failing or a device check
+                let mut select = Select::new();
+                select.recv(&player_command_receiver);
+                select.recv(&wake_receiver);
+                if let Some(check) = device_check.as_ref() {
+                    select.recv(check);
+                }
+                match (is_busy, device_check_due) {
+                    (true, _) => select.ready_timeout(LOOP_INTERVAL).ok(),
+                    (false, Some(due)) => select.ready_timeout(due.saturating_duration_since(Instant::now())).ok(),
+                    (false, None) => Some(select.ready()),
+                };
+                wake_receiver.try_iter().for_each(drop);
+
+                // See if any commands have been sent to the player
+                match player_command_receiver.try_recv() {
+                    Ok(command) => match command {
+                        PlayerCommand::Play(stub) => {
+                            // Whatever is playing carries on until the new song is ready. A load already going is dropped, which cancels it.
+                            info!("Loading {}", stub.file_name);
+                            loading = Some(match prefetcher.take(&stub.file_name) {
+                                Some(songs) => LoadJob::ready(stub, songs),
+                                None => LoadJob::start(stub, player_event_sender.clone()),
+                            });
+                        }
+                        PlayerCommand::PlayMetronome(metronome) => {
+                            loading = None;
+                            if let Some(session) = session.take() {
+                                player_event_sender.send(PlayerEvent::Played(session.finish())).unwrap();
+                            }
+                            current_stub = None;
+                            target_speed = 1.0;
+                            mixer.set_speed(1.0);
+
+                            match metronome.song().and_then(|(click, cycle)| {
+                                let duration = click.duration();
+                                mixer.load(vec![(MixSource::Click, click)], None)?;
+                                Ok((cycle, duration))
+                            }) {
+                                Ok((cycle, duration)) => {
+                                    beat_grid = BeatGrid::from_bpm(metronome.bpm, metronome.beats_per_bar, duration);
+                                    metronome_cycle = Some(cycle);
+                                    mixer.set_playing(true);
+                                }
+                                Err(err) => {
+                                    error!("Unable to play the metronome: {:?}", err);
+                                    beat_grid = None;
+                                    metronome_cycle = None;
+                                    mixer.stop();
+                                }
+                            }
+                        }
+                        PlayerCommand::Stop => {
+                            loading = None;
+                            if let Some(session) = session.take() {
+                                player_event_sender.send(PlayerEvent::Played(session.finish())).unwrap();
+                            }
+                            current_stub = None;
+                            beat_grid = None;
+                            metronome_cycle = None;
+                            mixer.stop();
+                        }
+                        PlayerCommand::Pause => {
+                            let is_playing = mixer.is_playing();
+
+                            mixer.set_playing(!is_playing);
+
+                            if !is_playing {
+                                player_event_sender.send(PlayerEvent::Continuing(current_stub.clone())).unwrap();
+                            } else {
+                                player_event_sender.send(PlayerEvent::Paused).unwrap();
+                            }
+                        }
+                        PlayerCommand::Quit => {
+                            info!("Player received quit signal. Exiting.");
+                            if let Some(job) = loading.take() {
+                                job.cancel();
+                            }
+                            prefetcher.cancel();
+                            mixer.stop();
+                            if let Some(session) = session.take() {
+                                player_event_sender.send(PlayerEvent::Played(session.finish())).unwrap();
+                            }
+                            player_event_sender.send(PlayerEvent::Quit).unwrap();
+                            thread::sleep(std::time::Duration::from_millis(100)); // time for the exit to propagate
+                            break;
+                        }
+                        PlayerCommand::SetStatusRate(rate) => status_interval = Duration::from_secs(1) / rate.max(1),
+                        PlayerCommand::Forward => {
+                            if current_stub.is_none() {
+                                continue;
+                            }
+                            if let Some(session) = session.as_mut() {
+                                session.seeked();
+                            }
+
+                            if let Some((position, duration)) = mixer.position() {
+                                let new_position = match beat_grid.as_ref() {
+                                    Some(grid) => grid.seek_bars(position, 1),
+                                    None => position.checked_add(Duration::from_secs(10)),
+                                };
+                                if let Some(seek) = new_position {
+                                    if seek > duration {
+                                        // nope
+                                    } else {
+                                        mixer.seek(seek);
+                                    }
+                                } else {
+                                    mixer.seek(Duration::from_micros(0));
+                                }
+                            }
+                        }
+                        PlayerCommand::Backward => {
+                            if current_stub.is_none() {
+                                continue;
+                            }
+                            if let Some(session) = session.as_mut() {
+                                session.seeked();
+                            }
+                            if let Some((position, _)) = mixer.position() {
+                                let new_position = match beat_grid.as_ref() {
+                                    Some(grid) => grid.seek_bars(position, -1),
+                                    None => position.checked_sub(Duration::from_secs(10)),
+                                };
+                                if let Some(seek) = new_position {
+                                    mixer.seek(seek);
+                                } else {
+                                    mixer.seek(Duration::from_micros(0));
+                                }
+                            }
+                        }
+                        PlayerCommand::Seek(position) => {
+                            if current_stub.is_none() {
+                                continue;
+                            }
+                            if let Some(session) = session.as_mut() {
+                                session.seeked();
+                            }
+
+                            if let Some((_, duration)) = mixer.position() {
+                                mixer.seek(position.min(duration));
+                            }
+                        }
+                        PlayerCommand::SeekBar(bar) => {
+                            if current_stub.is_none() {
+                                continue;
+                            }
+                            if let Some(session) = session.as_mut() {
+                                session.seeked();
+                            }
+
+                            match beat_grid.as_ref().and_then(|grid| grid.bar_start(bar)) {
+                                Some(position) => mixer.seek(position),
+                                None => player_event_sender.send(PlayerEvent::Notice(format!("There is no bar {}", bar))).unwrap(),
+                            }
+                        }
+                        // The speed ramps to the target below, so changes don't jolt
+                        PlayerCommand::SetSpeed(speed) => {
+                            target_speed = speed.clamp(MINIMUM_PLAYBACK_SPEED, MAXIMUM_PLAYBACK_SPEED);
+                        }
+                        PlayerCommand::ChangeSpeed(delta) => {
+                            // round so repeated steps land on whole percents
+                            target_speed = ((target_speed + delta) * 100.0).round().clamp(MINIMUM_PLAYBACK_SPEED * 100.0, MAXIMUM_PLAYBACK_SPEED * 100.0) / 100.0;
+                        }
+                        PlayerCommand::SetOutputs(configs) => {
+                            for removed in outputs.iter().filter(|output| !configs.iter().any(|config| config.name == output.name)) {
+                                info!("Removing output {}", removed.name);
+                                mixer.remove_output(&removed.name);
+                                bindings.remove(&removed.name);
+                            }
+
+                            let beep_song = beep_song().unwrap();
+                            for config in configs.iter() {
+                                if outputs.contains(config) && mixer.output(&config.name).is_some() {
+                                    continue; // unchanged
+                                }
+
+                                let binding = match Self::bind_output(config, &player_event_sender) {
+                                    Some(binding) => binding,
+                                    None => {
+                                        error!("No output device available for {}", config.name);
+                                        continue;
+                                    }
+                                };
+
+                                match mixer.open_output(config, || Self::new_audio_player(&binding.device, &config.port_name(), &output_settings, &wake_sender)) {
+                                    Ok(()) => {
+                                        bindings.insert(config.name.clone(), binding);
+                                        // let the user hear where the output went, unless a song is playing
+                                        if let Err(err) = mixer.play_on_output(&config.name, &beep_song) {
+                                            warn!("Could not play beep on {}: {:?}", config.name, err);
+                                        }
+                                    }
+                                    Err(err) => error!("Unable to open output {}: {:?}", config.name, err),
+                                }
+                            }
+
+                            outputs = configs;
+                        }
+                        PlayerCommand::ExportTempoMap => {
+                            let (stub, grid) = match (current_stub.as_ref(), beat_grid.as_ref()) {
+                                (Some(stub), Some(grid)) => (stub, grid),
+                                _ => {
+                                    player_event_sender.send(PlayerEvent::Notice("No tempo map to export".to_string())).unwrap();
+                                    continue;
+                                }
+                            };
+
+                            let notice = match tempo_map_path(&stub.file_name).and_then(|path| write_tempo_map(&path, &stub.title, &grid.tempo_map()).map(|()| path)) {
+                                Ok(path) => {
+                                    info!("Exported the tempo map to {}", path.display());
+                                    format!("Tempo map saved to {}", path.display())
+                                }
+                                Err(err) => {
+                                    error!("Unable to export the tempo map of {}: {:?}", stub.file_name, err);
+                                    format!("Tempo map export failed: {}", err)
+                                }
+                            };
+                            player_event_sender.send(PlayerEvent::Notice(notice)).unwrap();
+                        }
+                        PlayerCommand::ResetSpeed => {
+                            target_speed = 1.0;
+                        }
+                        PlayerCommand::SetMixCell(source, output, cell) => {
+                            if !song_mix {
+                                base_matrix.set_cell(source, &output, cell);
+                            }
+                            mixer.set_cell(source, &output, cell);
+                            if let Some(stub) = current_stub.as_ref() {
+                                Self::load_missing_sources(&mut mixer, stub);
+                            }
+                        }
+                        PlayerCommand::SetMix(matrix) => {
+                            if !song_mix {
+                                base_matrix = matrix.clone();
+                            }
+                            mixer.set_matrix(matrix);
+                            if let Some(stub) = current_stub.as_ref() {
+                                Self::load_missing_sources(&mut mixer, stub);
+                            }
+                        }
+                        PlayerCommand::Restart => {
+                            if let Some(session) = session.as_mut() {
+                                session.seeked();
+                            }
+                            match current_stub.as_ref().and_then(|stub| Self::count_in_song(count_in_bars, beat_grid.as_ref(), stub.bpm)) {
+                                Some(count_in) => {
+                                    if let Err(err) = mixer.count_in(&count_in) {
+                                        error!("Unable to count in: {:?}", err);
+                                    }
+                                }
+                                None => mixer.seek(Duration::from_micros(0)),
+                            }
+                        }
+                        PlayerCommand::SetLoop(region) => {
+                            loop_region = region;
+                        }
+                        PlayerCommand::SetCountIn(bars) => {
+                            count_in_bars = bars;
+                        }
+                        PlayerCommand::RescanDevices => {
+                            device_check_interval = DEVICE_CHECK_INTERVAL;
+                            last_device_check = Instant::now() - DEVICE_CHECK_INTERVAL;
+                        }
+                        PlayerCommand::SetDevicePreference(device_name, preference) => {
+                            info!("Device preference for {}: {:?}", device_name, preference);
+                            output_settings.device_preferences.insert(device_name.clone(), preference);
+
+                            // reopen any output already on this device so the new settings take effect
+                            for output in outputs.iter() {
+                                let binding = match bindings.get(&output.name) {
+                                    Some(binding) => binding,
+                                    None => continue,
+                                };
+                                if binding.device.name().ok().as_deref() == Some(device_name.as_str()) {
+                                    if let Err(err) = mixer.open_output(output, || Self::new_audio_player(&binding.device, &output.port_name(), &output_settings, &wake_sender)) {
+                                        error!("Unable to apply preference to output {}: {:?}", output.name, err);
+                                    }
+                                }
+                            }
+                        }
+                        PlayerCommand::SetHost(host_name) => {
+                            info!("Switching host to {:?}", host_name);
+                            set_host(host_name.as_deref());
+                            host_changed = true;
+                        }
+                        PlayerCommand::SetJackConnections(connections) => {
+                            output_settings.jack_connections = connections;
+                        }
+                        PlayerCommand::TestChannel(device_name, channel, signal) => {
+                            let device = match find_device(device_name.as_str()) {
+                                Some(device) => device,
+                                None => {
+                                    warn!("Cannot test {}. It is not attached.", device_name);
+                                    continue;
+                                }
+                            };
+
+                            let player = Self::new_audio_player(&device, "test", &output_settings, &wake_sender).and_then(|player| {
+                                // silence every channel except the one under test
+                                let gains = (0..player.channel_count()).map(|c| if c == channel { 1.0 } else { 0.0 }).collect();
+                                player.set_channel_gains(gains);
+                                player.play_song_now(&test_signal_song(signal)?, None)?;
+                                Ok(player)
+                            });
+
+                            match player {
+                                Ok(player) => test_player = Some(player),
+                                Err(err) => error!("Unable to play test signal on {} channel {}: {:?}", device_name, channel, err),
+                            }
+                        }
+                        PlayerCommand::Prefetch(upcoming) => prefetcher.set_upcoming(upcoming),
+                        PlayerCommand::SetPrefetch(settings) => prefetcher.set_settings(settings),
+                    },
+                    Err(TryRecvError::Empty) => {}
+                    Err(TryRecvError::Disconnected) => break, // the UI is gone
+                }
+
+                // A song has finished loading. It takes over from whatever was playing.
+                // 1. Restore the song's practice settings, unless it is being replayed
+                // 2. Load any other source the mix uses
+                // 3. Play the song, after the count-in
+                if let Some(result) = loading.as_ref().and_then(|job| job.try_result()) {
+                    let stub = loading.take().map(|job| job.stub.clone()).unwrap();
+                    match result {
+                        Err(err) => {
+                            // what was playing, if anything, carries on
+                            error!("Failed to load {}: {}", stub.file_name, err);
+                            player_event_sender.send(PlayerEvent::LoadFailure(stub.clone(), err)).unwrap();
+                        }
+                        Ok((track_song, click_song)) => {
+                            mixer.set_playing(false);
+                            metronome_cycle = None;
+                            if let Some(session) = session.take() {
+                                player_event_sender.send(PlayerEvent::Played(session.finish())).unwrap();
+                            }
+
+                            if settings_song.as_ref() != Some(&stub.file_name) {
+                                let practice = SongData::load(&stub.folder, &stub.file_name).practice;
+                                if let Some(speed) = practice.speed {
+                                    target_speed = speed.clamp(MINIMUM_PLAYBACK_SPEED, MAXIMUM_PLAYBACK_SPEED);
+                                }
+                                match practice.mix {
+                                    Some(matrix) => {
+                                        mixer.set_matrix(matrix);
+                                        song_mix = true;
+                                    }
+                                    None if song_mix => {
+                                        mixer.set_matrix(base_matrix.clone());
+                                        song_mix = false;
+                                    }
+                                    None => {}
+                                }
+                                loop_region = practice.loop_region;
+                                count_in_bars = practice.count_in_bars;
+                                settings_song = Some(stub.file_name.clone());
+                            }
+
+                            beat_grid = BeatGrid::for_song(&click_song, stub.bpm);
+                            let count_in = Self::count_in_song(count_in_bars, beat_grid.as_ref(), stub.bpm);
+
+                            let mut sources = vec![(MixSource::Track, track_song.clone()), (MixSource::Click, click_song)];
+                            for source in [MixSource::GeneratedClick, MixSource::Cue, MixSource::Take] {
+                                if mixer.matrix().is_routed(source) {
+                                    if let Some(song) = Self::load_source(source, &stub, &track_song) {
+                                        sources.push((source, song));
+                                    }
+                                }
+                            }
+
+                            match mixer.load(sources, count_in.as_ref()) {
+                                Ok(()) => {
+                                    mixer.set_playing(true);
+                                    current_stub = Some(stub.clone());
+                                    session = Some(PlaySession::new(&stub));
+
+                                    player_event_sender.send(PlayerEvent::Playing(stub.clone())).unwrap();
+                                }
+                                Err(err) => {
+                                    error!("Failed to play song: {:?}", err);
+                                    player_event_sender.send(PlayerEvent::LoadFailure(stub.clone(), LoadError::Device(err.to_string()))).unwrap();
+                                    mixer.stop();
+                                    current_stub = None;
+                                }
+                            }
+                        }
+                    }
+                }
+
+                if mixer.speed() != target_speed && last_ramp.elapsed() >= RAMP_INTERVAL {
+                    let step = SPEED_RAMP_RATE * last_ramp.elapsed().as_secs_f64();
+                    let speed = mixer.speed();
+                    mixer.set_speed(if speed < target_speed {
+                        (speed + step).min(target_speed)
+                    } else {
+                        (speed - step).max(target_speed)
+                    });
+                    last_ramp = Instant::now();
+                } else if mixer.speed() == target_speed {
+                    last_ramp = Instant::now();
+                }
+
+                if let Err(err) = mixer.update() {
+                    error!("Unable to start after the count-in: {:?}", err);
+                }
+
+                // Going back exactly a cycle keeps the beat, however late we notice
+                let metronome_wrap = metronome_cycle.and_then(|cycle| mixer.position().filter(|(position, _)| *position >= cycle).map(|(position, _)| position - cycle));
+                if let Some(position) = metronome_wrap {
+                    mixer.seek(position);
+                } else if let (Some(region), false, None) = (loop_region, mixer.is_counting_in(), metronome_cycle) {
+                    if mixer.position().is_some_and(|(position, _)| position >= region.end()) {
+                        mixer.seek(region.start());
+                        if let Some(session) = session.as_mut() {
+                            session.looped();
+                        }
+                    }
+                }
+
+                if let Some(session) = session.as_mut() {
+                    session.tick(mixer.is_playing() && !mixer.is_counting_in(), mixer.speed());
+                }
+
+                if test_player.as_ref().is_some_and(|player| !player.has_current_song()) {
+                    test_player = None;
+                }
+
+                // A stream error means the device went away. Move to the default output until it comes back.
+                // A fallback binding, or an output that failed to open, is retried once its device is there again.
+                // Listing devices can block for a while, so that is looked up on its own thread, less often the
+                // longer the device stays away.
+                // Switching hosts moves every output, as if all devices were lost at once.
+                let lost = outputs
+                    .iter()
+                    .filter(|output| host_changed || mixer.has_stream_error(&output.name))
+                    .map(|output| output.name.clone())
+                    .collect::<Vec<_>>();
+                host_changed = false;
+                let waiting = outputs
+                    .iter()
+                    .filter(|output| bindings.get(&output.name).is_none_or(|binding| binding.is_fallback) || mixer.output(&output.name).is_none())
+                    .map(|output| output.device_name.clone())
+                    .collect::<Vec<_>>();
+                let is_waiting = !waiting.is_empty();
+                if device_check.is_none() && is_waiting && last_device_check.elapsed() >= device_check_interval {
+                    device_check = Some(Self::check_devices(waiting));
+                }
+                let checked = match device_check.as_ref().map(|check| check.try_recv()) {
+                    Some(Ok(is_back)) => Some(is_back),
+                    Some(Err(TryRecvError::Disconnected)) => Some(false),
+                    _ => None, // not looked up yet
+                };
+                if let Some(is_back) = checked {
+                    device_check = None;
+                    last_device_check = Instant::now();
+                    if !is_back {
+                        device_check_interval = (device_check_interval * 2).min(MAX_DEVICE_CHECK_INTERVAL);
+                    }
+                }
+                let retry = checked == Some(true);
+
+                if !lost.is_empty() || retry {
+                    let mut reattached = false;
+                    for output in outputs.iter() {
+                        let is_lost = lost.contains(&output.name) || mixer.output(&output.name).is_none();
+                        let binding = match bindings.get(&output.name) {
+                            Some(binding) if !is_lost && !binding.is_fallback => continue,
+                            Some(binding) => Self::rebind_device(binding, &output.name, is_lost, &player_event_sender),
+                            None => Self::bind_output(output, &player_event_sender), // never opened
+                        };
+
+                        if let Some(binding) = binding {
+                            match mixer.open_output(output, || Self::new_audio_player(&binding.device, &output.port_name(), &output_settings, &wake_sender)) {
+                                Ok(()) => {
+                                    reattached |= !binding.is_fallback;
+                                    bindings.insert(output.name.clone(), binding);
+                                }
+                                Err(err) => error!("Unable to reattach output {}: {:?}", output.name, err),
+                            }
+                        }
+                    }
+                    // a device that is there but still won't open is left longer too
+                    device_check_interval = if reattached || !lost.is_empty() {
+                        DEVICE_CHECK_INTERVAL
+                    } else {
+                        (device_check_interval * 2).min(MAX_DEVICE_CHECK_INTERVAL)
+                    };
+                }
+                device_check_due = (device_check.is_none() && is_waiting).then(|| last_device_check + device_check_interval);
+
+                // if we have a current_stub, but the player is not playing, then we need to send a stopped event
+                if current_stub.clone().is_some() && !mixer.has_current_song() {
+                    if let Some(session) = session.take() {
+                        player_event_sender.send(PlayerEvent::Played(session.finish())).unwrap();
+                    }
+                    player_event_sender.send(PlayerEvent::Ended).unwrap();
+                    current_stub = None;
+                    mixer.stop();
+                }
+            }
+        };
+
+        let player_event_sender = self.player_event_sender.clone();
+        thread::spawn(move || Self::supervise(serve, player_event_sender));
+    }
+
+    // Runs the player until it quits. When it panics the UI is told and it is restarted with nothing set up, for the UI
+    // to set up again. If it keeps crashing it is left down.
+    fn supervise(serve: impl FnOnce() + Clone + Send + 'static, player_event_sender: Sender<PlayerEvent>) {
+        let mut crashes: Vec<Instant> = Vec::new();
+        loop {
+            let result = thread::Builder::new()
+                .name("player".to_string())
+                .spawn(serve.clone())
+                .and_then(|handle| handle.join().map_err(|panic| io::Error::other(panic_message(panic.as_ref()))));
+            let message = match result {
+                Ok(()) => return, // quit
+                Err(err) => err.to_string(),
+            };
+
+            crashes.retain(|crashed| crashed.elapsed() < CRASH_WINDOW);
+            crashes.push(Instant::now());
+            let is_restarting = crashes.len() <= MAX_CRASHES;
+            error!("The player crashed: {}. {}", message, if is_restarting { "Restarting it." } else { "Leaving it down." });
+            player_event_sender.send(PlayerEvent::Crashed(message, is_restarting)).ok();
+            if !is_restarting {
+                return;
+            }
+        }
+    }
+
+    fn status(mixer: &Mixer, beat_grid: Option<&BeatGrid>) -> PlaybackStatus {
+        let mut status = PlaybackStatus {
+            track_duration: None,
+            track_position: None,
+            bar_beat: None,
+            speed: mixer.speed(),
+            levels: Vec::new(),
+        };
+
+        if let Some((position, duration)) = mixer.position() {
+            if mixer.is_playing() {
+                status.track_position = Some(position);
+                status.track_duration = Some(duration);
+                status.bar_beat = beat_grid.and_then(|grid| grid.position_at(position));
+                status.levels = mixer.take_levels();
+            }
+        }
+        status
+    }
+
+    // Loads one of the optional sources for a song. Returns None when the song doesn't have it.
+    fn load_source(source: MixSource, stub: &SongStub, track_song: &Song) -> Option<Song> {
+        let song = match source {
+            MixSource::Track | MixSource::Click => return None, // always loaded with the song
+            MixSource::GeneratedClick if stub.bpm == 0 => return None,
+            MixSource::GeneratedClick => click_song(stub.bpm, 4, track_song.duration()),
+            MixSource::Cue | MixSource::Take => {
+                let suffix = if source == MixSource::Cue { "cue" } else { "take" };
+                let mut path = PathBuf::new();
+                path.push(stub.folder.as_str());
+                path.push(format!("{}_{}.wav", stub.file_name, suffix));
+                if !path.exists() {
+                    return None;
+                }
+                Song::from_file(path, None)
+            }
+        };
+
+        match song {
+            Ok(song) => Some(song),
+            Err(err) => {
+                warn!("Unable to load {} for {}: {:?}", source.label(), stub.file_name, err);
+                None
+            }
+        }
+    }
+
+    // Bars of click at the tempo and meter of the song's first bar, played before it starts
+    fn count_in_song(bars: usize, beat_grid: Option<&BeatGrid>, bpm: usize) -> Option<Song> {
+        if bars == 0 {
+            return None;
+        }
+
+        let (bpm, beats_per_bar) = match beat_grid.and_then(|grid| grid.bar(1)) {
+            Some(bar) => (bar.bpm.round() as usize, bar.beats_per_bar),
+            None => (bpm, DEFAULT_BEATS_PER_BAR),
+        };
+        if bpm == 0 {
+            return None;
+        }
+
+        let duration = Duration::from_secs_f64((bars * beats_per_bar) as f64 * 60.0 / bpm as f64);
+        match click_song(bpm, beats_per_bar, duration) {
+            Ok(song) => Some(song),
+            Err(err) => {
+                warn!("Unable to generate the count-in: {:?}", err);
+                None
+            }
+        }
+    }
+
+    // Brings in sources the mix started using after the song was loaded
+    fn load_missing_sources(mixer: &mut Mixer, stub: &SongStub) {
+        let missing = MixSource::ALL
+            .into_iter()
+            .filter(|source| mixer.matrix().is_routed(*source) && !mixer.has_source(*source))
+            .collect::<Vec<_>>();
+        if missing.is_empty() {
+            return;
+        }
+
+        // the track is always loaded, and first
+        let track_song = match mixer.source(MixSource::Track) {
+            Some(song) => song.clone(),
+            None => return,
+        };
+
+        for source in missing {
+            if let Some(song) = Self::load_source(source, stub, &track_song) {
+                if let Err(err) = mixer.add_source(source, song) {
+                    error!("Unable to add {} to the mix: {:?}", source.label(), err);
+                }
+            }
+        }
+    }
+
+    // Looks on its own thread whether any of the devices is there, by name, or the default output for None
+    fn check_devices(wanted: Vec<Option<String>>) -> Receiver<bool> {
+        let (sender, receiver) = bounded(1);
+        thread::spawn(move || {
+            let is_back = wanted.iter().any(|name| match name {
+                Some(name) => find_device(name).is_some(),
+                None => default_device().is_some(),
+            });
+            sender.send(is_back).ok();
+        });
+        receiver
+    }
+
+    // Finds the device for a newly configured output, falling back to the default output when it is missing
+    fn bind_output(config: &OutputConfig, player_event_sender: &Sender<PlayerEvent>) -> Option<DeviceBinding> {
+        let (device, is_fallback) = resolve_device(config.device_name.as_deref())?;
+
+        if is_fallback {
+            let name = config.device_name.clone().unwrap_or_default();
+            warn!("Could not find device with name {}. Using the default output until it returns.", name);
+            player_event_sender.send(PlayerEvent::DeviceFallback(config.name.clone(), name)).unwrap();
+        }
+
+        Some(DeviceBinding {
+            wanted: config.device_name.clone(),
+            device,
+            is_fallback,
+        })
+    }
+
+    // Decides where a player should live after a device was lost or while it sits on the fallback.
+    // Returns None when nothing needs to change.
+    fn rebind_device(binding: &DeviceBinding, output_name: &str, lost: bool, player_event_sender: &Sender<PlayerEvent>) -> Option<DeviceBinding> {
+        let (device, is_fallback) = resolve_device(binding.wanted.as_deref())?;
+
+        if binding.is_fallback && is_fallback && !lost {
+            return None; // still missing, nothing to do
+        }
+
+        let name = binding.wanted.clone().unwrap_or_else(|| "default".to_string());
+        if is_fallback {
+            warn!("{} device {} is unavailable. Falling back to the default output.", output_name, name);
+            player_event_sender.send(PlayerEvent::DeviceFallback(output_name.to_string(), name)).unwrap();
+        } else if binding.is_fallback {
+            info!("{} device {} is back. Reattaching.", output_name, name);
+            player_event_sender.send(PlayerEvent::DeviceRestored(output_name.to_string(), name)).unwrap();
+        }
+
+        Some(DeviceBinding {
+            wanted: binding.wanted.clone(),
+            device,
+            is_fallback,
+        })
+    }
+
+    // Opens a player on a device using the sample rate and buffer size the user picked for it.
+    // Under JACK the device is ignored and the player gets its own ports named after the prefix.
+    // The player sends on wake when its stream fails.
+    fn new_audio_player(device: &Device, port_prefix: &str, output_settings: &OutputSettings, wake: &Sender<()>) -> Result<AudioPlayer> {
+        let player = if is_jack_host() {
+            AudioPlayer::new_jack(port_prefix, &output_settings.jack_connections)?
+        } else {
+            let preference = device.name().ok().and_then(|name| output_settings.device_preferences.get(&name).cloned()).unwrap_or_default();
+            AudioPlayer::new(preference.sample_rate.map(|rate| vec![rate]), preference.buffer_size, device)?
+        };
+        player.wake_on_stream_error(wake.clone());
+        Ok(player)
+    }
+}
+
+/// What was passed to panic!, which is nearly always a string
+pub fn panic_message(payload: &(dyn Any + Send)) -> String {
+    match (payload.downcast_ref::<&str>(), payload.downcast_ref::<String>()) {
+        (Some(message), _) => message.to_string(),
+        (_, Some(message)) => message.clone(),
+        _ => "unknown panic".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_player_left_down_takes_no_commands() {
+        let (commands, player_command_receiver) = unbounded();
+        let (player_event_sender, events) = unbounded();
+        let handle = PlayerHandle { commands, events };
+
+        // like the real one, each run holds a copy of the command receiver
+        let serve = move || {
+            let _receiver = &player_command_receiver;
+            panic!("broken");
+        };
+        Player::supervise(serve, player_event_sender);
+
+        let crashes = handle.events.try_iter().collect::<Vec<_>>();
+        assert_eq!(crashes.len(), MAX_CRASHES + 1);
+        for (crash, event) in crashes.iter().enumerate() {
+            assert!(matches!(event, PlayerEvent::Crashed(message, is_restarting) if message == "broken" && *is_restarting == (crash < MAX_CRASHES)));
+        }
+        assert!(!handle.send(PlayerCommand::Quit));
+    }
+}
@@ -0,0 +1,150 @@
+//! Gets the songs coming up in the queue ready while the current one plays, so a setlist never waits on decompression.
+//! Archives are extracted on rayon's pool, a few at a time. With pre-decoding on, the songs are decoded into memory instead.
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use color_eyre::eyre::Report;
+use log::{info, warn};
+use rayon::prelude::*;
+
+use super::{
+    audio::Song,
+    loader::{ensure_extracted, is_extracted, load_song, unpacked_size, LoadProgress},
+    player::SongStub,
+};
+
+/// How many songs ahead are got ready unless the user says
+pub const DEFAULT_LOOKAHEAD: usize = 3;
+/// How much is extracted ahead unless the user says, in megabytes
+pub const DEFAULT_BUDGET_MB: u64 = 4096;
+
+/// How far ahead of the queue songs are got ready, and how
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PrefetchSettings {
+    /// How many songs ahead. 0 turns prefetching off.
+    pub lookahead: usize,
+    /// The most that is extracted ahead of playback
+    pub budget_mb: u64,
+    /// Decode them rather than extract them, which costs memory rather than disk
+    pub decode: bool,
+}
+
+impl Default for PrefetchSettings {
+    fn default() -> Self {
+        PrefetchSettings {
+            lookahead: DEFAULT_LOOKAHEAD,
+            budget_mb: DEFAULT_BUDGET_MB,
+            decode: false,
+        }
+    }
+}
+
+/// Extracts or decodes the songs coming up in the background
+#[derive(Default)]
+pub(crate) struct Prefetcher {
+    settings: PrefetchSettings,
+    upcoming: Vec<SongStub>,
+    generation: Arc<AtomicUsize>,                       // bumped whenever the plan changes, which stops the work on the old one
+    decoded: Arc<Mutex<HashMap<String, (Song, Song)>>>, // track and click, by file name
+}
+
+impl Prefetcher {
+    /// Changes how songs are got ready, starting over with the songs coming up
+    pub fn set_settings(&mut self, settings: PrefetchSettings) {
+        self.settings = settings;
+        self.start();
+    }
+
+    /// The songs that play next, in order. Work on songs that are no longer coming up is dropped.
+    pub fn set_upcoming(&mut self, upcoming: Vec<SongStub>) {
+        let file_names = |stubs: &[SongStub]| stubs.iter().map(|stub| stub.file_name.clone()).collect::<Vec<_>>();
+        if file_names(&upcoming) == file_names(&self.upcoming) {
+            return;
+        }
+        self.upcoming = upcoming;
+        self.start();
+    }
+
+    /// The decoded track and click, if the song was decoded ahead
+    pub fn take(&self, file_name: &str) -> Option<(Song, Song)> {
+        self.decoded.lock().unwrap().remove(file_name)
+    }
+
+    /// Stops working on the songs coming up. Songs already decoded can still be taken.
+    pub fn cancel(&self) {
+        self.generation.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // Songs are taken in order until the budget runs out. Those already extracted cost nothing.
+    fn start(&mut self) {
+        let generation = self.generation.fetch_add(1, Ordering::Relaxed) + 1;
+        let settings = self.settings;
+        let songs = self.upcoming.iter().take(settings.lookahead).cloned().collect::<Vec<_>>();
+        self.decoded
+            .lock()
+            .unwrap()
+            .retain(|file_name, _| settings.decode && songs.iter().any(|stub| stub.file_name == *file_name));
+        if songs.is_empty() {
+            return;
+        }
+
+        let current = self.generation.clone();
+        let decoded = self.decoded.clone();
+        rayon::spawn(move || {
+            let is_current = || current.load(Ordering::Relaxed) == generation;
+
+            let budget = settings.budget_mb * 1024 * 1024;
+            let mut planned = 0;
+            let mut wanted = vec![];
+            for stub in songs {
+                let size = if is_extracted(&stub.folder, &stub.file_name) {
+                    0
+                } else {
+                    match unpacked_size(&stub.folder, &stub.file_name) {
+                        Ok(size) => size,
+                        Err(err) => {
+                            warn!("Unable to prefetch {}: {}", stub.file_name, err);
+                            continue;
+                        }
+                    }
+                };
+                if planned + size > budget {
+                    info!("Prefetching stops before {}, which would go over {} MB", stub.file_name, settings.budget_mb);
+                    break;
+                }
+                planned += size;
+                wanted.push(stub);
+            }
+
+            wanted.par_iter().for_each(|stub| {
+                if !is_current() || (settings.decode && decoded.lock().unwrap().contains_key(&stub.file_name)) {
+                    return;
+                }
+
+                let mut report = |_: LoadProgress| is_current();
+                let result = if settings.decode {
+                    load_song(stub, &mut report)
+                        .map(|songs| {
+                            if is_current() {
+                                decoded.lock().unwrap().insert(stub.file_name.clone(), songs);
+                            }
+                        })
+                        .map_err(Report::from)
+                } else {
+                    ensure_extracted(&stub.folder, &stub.file_name, &mut report)
+                };
+
+                match result {
+                    Ok(()) => info!("Prefetched {}", stub.file_name),
+                    Err(_) if !is_current() => {} // cancelled
+                    Err(err) => warn!("Unable to prefetch {}: {}", stub.file_name, err),
+                }
+            });
+        });
+    }
+}
@@ -0,0 +1,158 @@
+//! What we remember about a single song: its section markers, notes and the practice settings it starts with.
+//! Kept as songs/<file_name>_data.json beside the configuration, so the library can be read-only. Files saved next to
+//! the song before are still read until the song is saved again.
+use std::{fs, path::PathBuf, time::Duration};
+
+use color_eyre::eyre::Result;
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use super::mixer::MixMatrix;
+
+// Going back to a marker within this long of it goes to the one before instead
+const MARKER_GRACE: Duration = Duration::from_secs(1);
+
+/// A named point in the song, e.g. where the chorus starts
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Marker {
+    /// E.g. intro, verse, chorus, solo, outro
+    pub name: String,
+    /// Seconds
+    pub position: f64,
+}
+
+impl Marker {
+    /// The position as a [`Duration`]
+    pub fn position(&self) -> Duration {
+        Duration::from_secs_f64(self.position.max(0.0))
+    }
+}
+
+/// A part of the song played over and over
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct LoopRegion {
+    /// Seconds
+    pub start: f64,
+    /// Seconds
+    pub end: f64,
+}
+
+impl LoopRegion {
+    /// The region between two positions, whichever order they come in
+    pub fn new(start: Duration, end: Duration) -> Self {
+        LoopRegion {
+            start: start.min(end).as_secs_f64(),
+            end: start.max(end).as_secs_f64(),
+        }
+    }
+
+    /// Where the loop starts
+    pub fn start(&self) -> Duration {
+        Duration::from_secs_f64(self.start.max(0.0))
+    }
+
+    /// Where the loop goes back from
+    pub fn end(&self) -> Duration {
+        Duration::from_secs_f64(self.end.max(0.0))
+    }
+}
+
+/// Restored whenever the song is loaded. Anything missing keeps whatever was in use before.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct PracticeSettings {
+    /// 1.0 being the original speed
+    pub speed: Option<f64>,
+    /// The track, click and bleed levels, and any other sends
+    pub mix: Option<MixMatrix>,
+    /// The part of the song to loop
+    pub loop_region: Option<LoopRegion>,
+    /// Bars of click before the song starts
+    #[serde(default)]
+    pub count_in_bars: usize,
+}
+
+/// What we remember about a song, saved as JSON beside the configuration
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct SongData {
+    /// Sorted by position
+    #[serde(default)]
+    pub markers: Vec<Marker>,
+    /// Anything the user wants to remember about the song
+    #[serde(default)]
+    pub notes: String,
+    /// What the song starts with when loaded
+    #[serde(default)]
+    pub practice: PracticeSettings,
+}
+
+impl SongData {
+    fn path(file_name: &str) -> Result<PathBuf> {
+        let config_path = confy::get_configuration_file_path("drum-weaver", None)?;
+        Ok(config_path.with_file_name("songs").join(format!("{}_data.json", file_name)))
+    }
+
+    // Where it was kept before, in the library
+    fn library_path(folder: &str, file_name: &str) -> PathBuf {
+        let mut path = PathBuf::new();
+        path.push(folder);
+        path.push(format!("{}_data.json", file_name));
+        path
+    }
+
+    /// Songs without a data file, or with one we can't read, start out empty
+    pub fn load(folder: &str, file_name: &str) -> SongData {
+        let path = match Self::path(file_name) {
+            Ok(path) if path.exists() => path,
+            _ => Self::library_path(folder, file_name),
+        };
+        if !path.exists() {
+            return SongData::default();
+        }
+
+        match fs::read_to_string(&path)
+            .map_err(|err| err.to_string())
+            .and_then(|contents| serde_json::from_str(&contents).map_err(|err| err.to_string()))
+        {
+            Ok(data) => data,
+            Err(err) => {
+                warn!("Unable to read {}: {}", path.display(), err);
+                SongData::default()
+            }
+        }
+    }
+
+    /// Writes the data to `songs/<file_name>_data.json` beside the configuration
+    pub fn save(&self, file_name: &str) -> Result<()> {
+        let path = Self::path(file_name)?;
+        if let Some(folder) = path.parent() {
+            fs::create_dir_all(folder)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Adds a marker, keeping them in order
+    pub fn add_marker(&mut self, name: &str, position: Duration) {
+        self.markers.push(Marker {
+            name: name.to_string(),
+            position: position.as_secs_f64(),
+        });
+        self.markers.sort_by(|a, b| a.position.total_cmp(&b.position));
+    }
+
+    /// Removes the marker the position is in, i.e. the last one at or before it
+    pub fn remove_marker_at(&mut self, position: Duration) -> Option<Marker> {
+        let idx = self.markers.iter().rposition(|marker| marker.position() <= position)?;
+        Some(self.markers.remove(idx))
+    }
+
+    /// The first marker after the position
+    pub fn next_marker(&self, position: Duration) -> Option<&Marker> {
+        self.markers.iter().find(|marker| marker.position() > position)
+    }
+
+    /// The marker before the position. Going back within a second of a marker goes to the one before it.
+    pub fn previous_marker(&self, position: Duration) -> Option<&Marker> {
+        self.markers.iter().rev().find(|marker| marker.position() + MARKER_GRACE < position)
+    }
+}